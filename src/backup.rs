@@ -0,0 +1,167 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    db::Db,
+    error::{MgdlError, MgdlResult},
+    utils::UtcDateTime,
+};
+
+const PREFIX: &str = "mgdl-";
+const SUFFIX: &str = ".db";
+/// Reason of the snapshot `restore` takes of the DB it replaces.
+const RESTORE_REASON: &str = "restore";
+
+/// Timestamped snapshots of `mgdl.db`, kept in a single directory.
+pub struct Backups {
+    dir: PathBuf,
+    retention: usize,
+}
+
+impl Backups {
+    pub fn new(dir: PathBuf, retention: usize) -> Self {
+        Self { dir, retention }
+    }
+
+    /// Snapshot `db` as `mgdl-<timestamp>-<reason>.db`, then drop the oldest
+    /// snapshots beyond the retention limit.
+    pub fn snapshot(&self, db: &Db, reason: &str) -> MgdlResult<PathBuf> {
+        let path = self.snapshot_unpruned(db, reason)?;
+        self.enforce_retention()?;
+        Ok(path)
+    }
+
+    /// Like `snapshot`, but leaves older snapshots alone until the caller
+    /// runs `enforce_retention`.
+    pub fn snapshot_unpruned(&self, db: &Db, reason: &str) -> MgdlResult<PathBuf> {
+        let path = self.new_path(reason)?;
+        db.backup_to(&path)?;
+        Ok(path)
+    }
+
+    /// A free snapshot path for `reason`, creating the directory.
+    fn new_path(&self, reason: &str) -> MgdlResult<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+
+        let stamp = timestamp();
+        let mut path = self.dir.join(format!("{PREFIX}{stamp}-{reason}{SUFFIX}"));
+        let mut n = 1;
+        while path.exists() {
+            n += 1;
+            path = self
+                .dir
+                .join(format!("{PREFIX}{stamp}.{n}-{reason}{SUFFIX}"));
+        }
+        Ok(path)
+    }
+
+    /// Replace the DB file at `db_path` with the backup at `source`, or with
+    /// the latest snapshot. The DB is copied as a file, without opening it,
+    /// so one that is broken or fails to migrate can still be restored; the
+    /// replaced file is kept as a `restore` snapshot first. Returns the
+    /// backup restored from.
+    pub fn restore(&self, db_path: &Path, source: Option<&Path>) -> MgdlResult<PathBuf> {
+        let source = match source {
+            Some(source) => source.to_path_buf(),
+            None => self.latest()?,
+        };
+        if !source.is_file() {
+            return Err(MgdlError::Db(format!(
+                "Backup {} not found",
+                source.display()
+            )));
+        }
+
+        if db_path.exists() {
+            fs::copy(db_path, self.new_path(RESTORE_REASON)?)?;
+        }
+        // a journal left by a crash belongs to the replaced DB
+        for suffix in ["-journal", "-wal", "-shm"] {
+            let mut leftover = db_path.as_os_str().to_owned();
+            leftover.push(suffix);
+            let leftover = PathBuf::from(leftover);
+            if leftover.exists() {
+                fs::remove_file(leftover)?;
+            }
+        }
+        fs::copy(&source, db_path)?;
+        // the source may itself be the oldest snapshot, so prune afterwards
+        self.enforce_retention()?;
+        Ok(source)
+    }
+
+    /// Snapshot the DB at `db_path` if opening it would apply migrations.
+    pub fn snapshot_before_migration(&self, db_path: &Path) -> MgdlResult<()> {
+        if !db_path.exists() {
+            return Ok(());
+        }
+        let db = Db::open(db_path.to_path_buf())?;
+        if db.needs_migration()? {
+            self.snapshot(&db, "migration")?;
+        }
+        Ok(())
+    }
+
+    /// All snapshots, oldest first.
+    pub fn list(&self) -> MgdlResult<Vec<PathBuf>> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Ok(Vec::new());
+        };
+
+        let mut backups: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| is_backup(p))
+            .collect();
+        backups.sort();
+        Ok(backups)
+    }
+
+    /// The newest snapshot, other than those `restore` takes, so restoring
+    /// twice doesn't undo the first restore.
+    pub fn latest(&self) -> MgdlResult<PathBuf> {
+        let restored = format!("-{RESTORE_REASON}{SUFFIX}");
+        self.list()?
+            .into_iter()
+            .rfind(|path| !path.to_string_lossy().ends_with(&restored))
+            .ok_or_else(|| MgdlError::Db(format!("No backups in {}", self.dir.display())))
+    }
+
+    pub fn enforce_retention(&self) -> MgdlResult<()> {
+        let backups = self.list()?;
+        let excess = backups.len().saturating_sub(self.retention.max(1));
+        for path in &backups[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_backup(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(PREFIX) && name.ends_with(SUFFIX))
+}
+
+/// UTC timestamp as `YYYYMMDD-HHMMSS-mmm`, which sorts chronologically.
+fn timestamp() -> String {
+    let now = UtcDateTime::now();
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        now.year, now.month, now.day, now.hour, now.minute, now.second, now.millis
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_backup_matches_snapshot_names() {
+        assert!(is_backup(Path::new("/x/mgdl-20250101-120000-000-reset.db")));
+        assert!(!is_backup(Path::new("/x/mgdl.db")));
+        assert!(!is_backup(Path::new("/x/notes.txt")));
+    }
+}
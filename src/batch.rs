@@ -0,0 +1,125 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use crate::{
+    error::{MgdlError, MgdlResult},
+    models::ChapterRange,
+};
+
+/// A manga to add or download, read from a list given with `-` or
+/// `--from-file`.
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    /// 1-based line of the list, to report the entry by
+    pub line: usize,
+    pub url: String,
+    /// the line's own chapter selection, as written
+    pub chapters: Option<String>,
+}
+
+impl Entry {
+    /// The line's chapter selection; a bad one fails only this entry.
+    pub fn chapters(&self) -> MgdlResult<Option<ChapterRange>> {
+        self.chapters
+            .as_deref()
+            .map(ChapterRange::parse)
+            .transpose()
+            .map_err(|err| MgdlError::Config(format!("chapters: {err}")))
+    }
+}
+
+/// Entries of a list: one URL per line, optionally followed by a chapter
+/// selection. Blank lines are skipped, and `#` starts a comment at the start
+/// of a line or after whitespace.
+pub fn parse(text: &str) -> Vec<Entry> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line_content = strip_comment(line).trim();
+            if line_content.is_empty() {
+                return None;
+            }
+            let (url, chapters) = match line_content.split_once(char::is_whitespace) {
+                Some((url, chapters)) => (url, Some(chapters.trim().to_string())),
+                None => (line_content, None),
+            };
+            Some(Entry {
+                line: i + 1,
+                url: url.to_string(),
+                chapters,
+            })
+        })
+        .collect()
+}
+
+/// Read a list from a file, or from stdin when `path` is `None`.
+pub fn read(path: Option<&Path>) -> MgdlResult<Vec<Entry>> {
+    let text = match path {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            text
+        }
+    };
+    Ok(parse(&text))
+}
+
+/// `line` up to its comment; a `#` inside a URL, as in a fragment, is kept.
+fn strip_comment(line: &str) -> &str {
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        if c == '#' && previous.is_whitespace() {
+            return &line[..i];
+        }
+        previous = c;
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_with_comments_and_chapters() {
+        let text = "# my reading list\n\
+                    https://example.com/series/a\n\
+                    \n\
+                    https://example.com/series/b 5..10  # only the first arc\n\
+                    https://example.com/series/c#top latest:3\n";
+        assert_eq!(
+            parse(text),
+            vec![
+                Entry {
+                    line: 2,
+                    url: "https://example.com/series/a".to_string(),
+                    chapters: None,
+                },
+                Entry {
+                    line: 4,
+                    url: "https://example.com/series/b".to_string(),
+                    chapters: Some("5..10".to_string()),
+                },
+                Entry {
+                    line: 5,
+                    url: "https://example.com/series/c#top".to_string(),
+                    chapters: Some("latest:3".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_chapters_fail_their_entry() {
+        let entries = parse("https://example.com/series/a 5..x\nhttps://example.com/series/b 2");
+        assert!(entries[0].chapters().is_err());
+        assert_eq!(
+            entries[1].chapters().unwrap(),
+            Some(ChapterRange::parse("2").unwrap())
+        );
+    }
+}
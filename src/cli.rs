@@ -1,67 +1,576 @@
-use clap::{CommandFactory, Parser};
+use clap::{error::ErrorKind, ArgGroup, Args as ClapArgs, CommandFactory, Parser, Subcommand};
+use std::path::PathBuf;
 
-use crate::{error::MgdlResult, logger::LogMode, models::ChapterRange};
+use crate::{
+    batch,
+    error::MgdlResult,
+    export::{ExportFormat, PackMode},
+    logger::{LogMode, OutputFormat},
+    models::ChapterRange,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = "Download manga rust")]
 pub struct Args {
-    /// URL of manga to download
-    #[arg(short, long)]
-    pub download: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
 
-    /// URL of manga to add
-    #[arg(short, long)]
-    pub add: Option<String>,
+    /// logging mode: plain, fancy, or quiet
+    #[arg(short, long, value_enum, default_value = "plain", global = true)]
+    pub log: LogMode,
 
-    /// folder name of manga to update
-    #[arg(short, long)]
-    pub update: Option<Option<String>>,
+    /// verbose output (show INFO messages)
+    #[arg(short, long, default_value_t = false, global = true)]
+    pub verbose: bool,
 
-    /// URL of manga to scrape
-    #[arg(short, long)]
-    pub scrape: Option<String>,
+    /// output format: text, or json for one JSON object per line
+    #[arg(long, value_enum, default_value = "text", global = true)]
+    pub output: OutputFormat,
 
-    /// chapter range to download (e.g., 5..10, 5.., ..10, 5)
-    #[arg(short, long, value_parser = ChapterRange::parse)]
-    pub chapters: Option<ChapterRange>,
+    /// config file to read instead of the default one (or MGDL_CONFIG)
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
 
-    /// force redownload of existing pages
-    #[arg(short, long, default_value_t = false)]
-    pub force: bool,
+    /// folder series are stored in, overriding config.toml and MGDL_MANGA_DIR
+    #[arg(long, value_name = "DIR", global = true)]
+    pub manga_dir: Option<PathBuf>,
 
-    /// reset local DB
-    #[arg(short, long, default_value_t = false)]
-    pub reset: bool,
+    /// print benchmark timing data after download
+    #[cfg(feature = "bench")]
+    #[arg(short, long, default_value_t = false, global = true)]
+    pub bench: bool,
 
+    #[command(flatten)]
+    legacy: LegacyArgs,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum Command {
+    /// add a manga to the library without downloading chapters
+    Add {
+        #[command(flatten)]
+        urls: Urls,
+    },
+    /// download the chapters of a manga, adding it if needed
+    Download {
+        #[command(flatten)]
+        urls: Urls,
+        /// chapters to download (e.g., 5..10, 5.5, 1,3,7..9, latest:3,
+        /// 2025-01-01.., !12); list lines may give their own
+        #[arg(short, long, value_parser = ChapterRange::parse)]
+        chapters: Option<ChapterRange>,
+        /// redownload existing pages
+        #[arg(short, long, default_value_t = false)]
+        force: bool,
+        #[command(flatten)]
+        storage: StorageArgs,
+        /// only report what would be fetched
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// download new chapters of a manga, or of every manga
+    Update {
+        /// folder name of the manga (default: all)
+        name: Option<String>,
+        #[command(flatten)]
+        storage: StorageArgs,
+        /// only report what would be fetched
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
     /// check all chapters for missing pages and download them
-    #[arg(short = 'o', long, default_value_t = false)]
-    pub consolidate: bool,
+    Consolidate {
+        #[command(flatten)]
+        storage: StorageArgs,
+        /// only report what would be fetched
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// export downloaded chapters of a manga
+    Export {
+        /// folder name of the manga
+        name: String,
+        /// file format to export to
+        #[arg(long, value_enum, default_value = "epub")]
+        format: ExportFormat,
+        /// chapters to export (e.g., 5..10, 5.5, 1,3,7..9, latest:3, !12)
+        #[arg(short, long, value_parser = ChapterRange::parse)]
+        chapters: Option<ChapterRange>,
+        /// export one file per volume
+        #[arg(long, default_value_t = false)]
+        by_volume: bool,
+        /// directory to write exports to
+        #[arg(long, value_name = "DIR", default_value = ".")]
+        out: PathBuf,
+    },
+    /// scrape a manga and its chapters into CSV files (bench builds only)
+    Scrape {
+        /// URL of the manga
+        url: String,
+    },
+    /// reset local DB
+    Reset,
+    /// snapshot local DB into the backups folder
+    Backup,
+    /// restore local DB from a backup file
+    Restore {
+        /// snapshot to restore (default: latest)
+        file: Option<PathBuf>,
+    },
+    /// rebuild local DB from the series folders in manga_dir
+    Scan,
+    /// move the library to the [layout] in config.toml
+    Relayout,
+    /// point a manga at a new folder inside manga_dir
+    Relink {
+        /// folder name the manga is known by
+        name: String,
+        /// its new folder
+        folder: PathBuf,
+    },
+    /// block pages that look like these sample pages from future downloads
+    /// and exports of their series
+    Block {
+        #[arg(required = true, value_name = "PAGE")]
+        pages: Vec<PathBuf>,
+        /// block the pages for every series
+        #[arg(long, default_value_t = false)]
+        global: bool,
+    },
+    /// hardlink identical pages across chapter folders and report the savings
+    Dedupe,
+    /// delete manga whose folder is missing from local DB
+    Prune,
+    /// browse the library and pick chapters to download in a full-screen UI
+    Tui,
+    /// write a commented starter config.toml
+    Init {
+        /// overwrite an existing config file
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+    /// inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
 
-    /// logging mode: plain, fancy, or quiet
-    #[arg(short, long, value_enum, default_value = "plain")]
-    pub log: LogMode,
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum ConfigCommand {
+    /// print the resolved settings and where each one came from
+    Show,
+}
 
-    /// verbose output (show INFO messages)
-    #[arg(short, long, default_value_t = false)]
-    pub verbose: bool,
+/// The manga `add` and `download` work on: one URL, or a list of them.
+#[derive(ClapArgs, Debug, Default, PartialEq)]
+#[group(required = true, multiple = false)]
+pub struct Urls {
+    /// URL of the manga, or `-` to read a list from stdin
+    url: Option<String>,
 
-    /// print benchmark timing data after download
-    #[cfg(feature = "bench")]
-    #[arg(short, long, default_value_t = false)]
-    pub bench: bool,
+    /// read a list from FILE: one URL per line, optionally followed by
+    /// chapters, with `#` comments
+    #[arg(long, value_name = "FILE")]
+    from_file: Option<PathBuf>,
 }
 
-pub fn parse() -> Args {
-    let mut args = Args::parse();
+impl Urls {
+    pub fn one(url: &str) -> Self {
+        Self {
+            url: Some(url.to_string()),
+            from_file: None,
+        }
+    }
 
-    if let Some(None) = args.update {
-        args.update = Some(Some("all".to_string()));
+    /// The URL given, unless a list is to be read instead.
+    pub fn single(&self) -> Option<&str> {
+        self.url.as_deref().filter(|url| *url != "-")
     }
 
-    args
+    /// Read the list given with `-` or `--from-file`.
+    pub fn read_list(&self) -> MgdlResult<Vec<batch::Entry>> {
+        batch::read(self.from_file.as_deref())
+    }
+}
+
+/// How downloaded chapters are stored, overriding config.toml.
+#[derive(ClapArgs, Debug, Default, PartialEq)]
+pub struct StorageArgs {
+    /// how to store finished chapters
+    #[arg(short, long, value_enum)]
+    pub pack: Option<PackMode>,
+
+    /// page processing profile from config.toml ("none" to skip), overriding
+    /// the series' one
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+}
+
+impl Command {
+    /// Storage overrides of the commands that download.
+    pub fn storage(&self) -> Option<&StorageArgs> {
+        match self {
+            Command::Download { storage, .. }
+            | Command::Update { storage, .. }
+            | Command::Consolidate { storage, .. } => Some(storage),
+            _ => None,
+        }
+    }
+
+    /// Whether the command only reports what it would do.
+    pub fn dry_run(&self) -> bool {
+        match self {
+            Command::Download { dry_run, .. }
+            | Command::Update { dry_run, .. }
+            | Command::Consolidate { dry_run, .. } => *dry_run,
+            _ => false,
+        }
+    }
+}
+
+/// The flags commands used to be given as, kept hidden until they are
+/// removed. Only one command may be given, and options only go with the
+/// commands that use them.
+#[derive(ClapArgs, Debug, Default)]
+#[command(group(ArgGroup::new("legacy_command").multiple(false)))]
+#[command(group(ArgGroup::new("legacy_storage").args(["download", "update", "consolidate"]).multiple(true)))]
+#[command(group(ArgGroup::new("legacy_download").arg("download")))]
+#[command(group(ArgGroup::new("legacy_export").arg("export")))]
+#[command(group(ArgGroup::new("legacy_block").arg("block")))]
+#[command(group(ArgGroup::new("legacy_ranged").args(["download", "export"]).multiple(true)))]
+struct LegacyArgs {
+    #[arg(short, long, hide = true, group = "legacy_command")]
+    download: Option<String>,
+
+    #[arg(short, long, hide = true, group = "legacy_command")]
+    add: Option<String>,
+
+    #[arg(short, long, hide = true, group = "legacy_command")]
+    update: Option<Option<String>>,
+
+    #[arg(short, long, hide = true, group = "legacy_command")]
+    scrape: Option<String>,
+
+    #[arg(short, long, hide = true, group = "legacy_command")]
+    export: Option<String>,
+
+    #[arg(short = 'o', long, hide = true, group = "legacy_command")]
+    consolidate: bool,
+
+    #[arg(short, long, hide = true, group = "legacy_command")]
+    reset: bool,
+
+    #[arg(long, hide = true, group = "legacy_command")]
+    backup: bool,
+
+    #[arg(long, hide = true, group = "legacy_command")]
+    restore: Option<Option<PathBuf>>,
+
+    #[arg(long, hide = true, group = "legacy_command")]
+    scan: bool,
+
+    #[arg(long, hide = true, group = "legacy_command")]
+    relayout: bool,
+
+    #[arg(long, num_args = 2, hide = true, group = "legacy_command")]
+    relink: Option<Vec<String>>,
+
+    #[arg(long, num_args = 1.., hide = true, group = "legacy_command")]
+    block: Option<Vec<PathBuf>>,
+
+    #[arg(long, hide = true, group = "legacy_command")]
+    dedupe: bool,
+
+    #[arg(long, hide = true, group = "legacy_command")]
+    prune: bool,
+
+    #[arg(long, hide = true, requires = "legacy_block")]
+    global: bool,
+
+    #[arg(long, value_enum, hide = true, requires = "legacy_export")]
+    format: Option<ExportFormat>,
+
+    #[arg(long, hide = true, requires = "legacy_export")]
+    by_volume: bool,
+
+    #[arg(long, hide = true, requires = "legacy_export")]
+    out: Option<PathBuf>,
+
+    #[arg(short, long, value_parser = ChapterRange::parse, hide = true, requires = "legacy_ranged")]
+    chapters: Option<ChapterRange>,
+
+    #[arg(short, long, value_enum, hide = true, requires = "legacy_storage")]
+    pack: Option<PackMode>,
+
+    #[arg(long, hide = true, requires = "legacy_storage")]
+    profile: Option<String>,
+
+    #[arg(short, long, hide = true, requires = "legacy_download")]
+    force: bool,
+
+    #[arg(long, hide = true, requires = "legacy_storage")]
+    dry_run: bool,
+}
+
+impl LegacyArgs {
+    /// The command the flags stand for, and the flag that named it.
+    fn into_command(self) -> Option<(Command, &'static str)> {
+        let storage = StorageArgs {
+            pack: self.pack,
+            profile: self.profile,
+        };
+        let command = if let Some(url) = self.download {
+            (
+                Command::Download {
+                    urls: Urls::one(&url),
+                    chapters: self.chapters,
+                    force: self.force,
+                    storage,
+                    dry_run: self.dry_run,
+                },
+                "--download",
+            )
+        } else if let Some(url) = self.add {
+            (
+                Command::Add {
+                    urls: Urls::one(&url),
+                },
+                "--add",
+            )
+        } else if let Some(name) = self.update {
+            // `--update all` was how every manga got updated
+            let name = name.filter(|name| name != "all");
+            (
+                Command::Update {
+                    name,
+                    storage,
+                    dry_run: self.dry_run,
+                },
+                "--update",
+            )
+        } else if let Some(url) = self.scrape {
+            (Command::Scrape { url }, "--scrape")
+        } else if let Some(name) = self.export {
+            (
+                Command::Export {
+                    name,
+                    format: self.format.unwrap_or(ExportFormat::Epub),
+                    chapters: self.chapters,
+                    by_volume: self.by_volume,
+                    out: self.out.unwrap_or_else(|| PathBuf::from(".")),
+                },
+                "--export",
+            )
+        } else if self.consolidate {
+            (
+                Command::Consolidate {
+                    storage,
+                    dry_run: self.dry_run,
+                },
+                "--consolidate",
+            )
+        } else if self.reset {
+            (Command::Reset, "--reset")
+        } else if self.backup {
+            (Command::Backup, "--backup")
+        } else if let Some(file) = self.restore {
+            (Command::Restore { file }, "--restore")
+        } else if self.scan {
+            (Command::Scan, "--scan")
+        } else if self.relayout {
+            (Command::Relayout, "--relayout")
+        } else if let Some(mut relink) = self.relink {
+            let folder = PathBuf::from(relink.pop()?);
+            let name = relink.pop()?;
+            (Command::Relink { name, folder }, "--relink")
+        } else if let Some(pages) = self.block {
+            (
+                Command::Block {
+                    pages,
+                    global: self.global,
+                },
+                "--block",
+            )
+        } else if self.dedupe {
+            (Command::Dedupe, "--dedupe")
+        } else if self.prune {
+            (Command::Prune, "--prune")
+        } else {
+            return None;
+        };
+        Some(command)
+    }
+}
+
+pub fn parse() -> Args {
+    match resolve(Args::parse()) {
+        Ok(args) => args,
+        Err(err) => err.exit(),
+    }
+}
+
+/// Turn the deprecated command flags into their subcommand, warning about it.
+fn resolve(mut args: Args) -> Result<Args, clap::Error> {
+    let legacy = std::mem::take(&mut args.legacy);
+    let Some((command, flag)) = legacy.into_command() else {
+        return Ok(args);
+    };
+    if args.command.is_some() {
+        return Err(Args::command().error(
+            ErrorKind::ArgumentConflict,
+            format!("{flag} can't be combined with a subcommand"),
+        ));
+    }
+    let name = flag.trim_start_matches('-');
+    eprintln!("Warning: {flag} is deprecated, use `mgdl {name}` instead");
+    args.command = Some(command);
+    Ok(args)
 }
 
 pub fn print_help() -> MgdlResult<()> {
     Args::command().print_help()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_from(args: &[&str]) -> Result<Args, clap::Error> {
+        resolve(Args::try_parse_from([&["mgdl"], args].concat())?)
+    }
+
+    fn command(args: &[&str]) -> Command {
+        parse_from(args).unwrap().command.unwrap()
+    }
+
+    #[test]
+    fn cli_is_well_formed() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn subcommands_take_their_own_options() {
+        assert_eq!(
+            command(&["download", "URL", "-c", "5", "-f", "--pack", "cbz"]),
+            Command::Download {
+                urls: Urls::one("URL"),
+                chapters: Some(ChapterRange::parse("5").unwrap()),
+                force: true,
+                storage: StorageArgs {
+                    pack: Some(PackMode::Cbz),
+                    profile: None,
+                },
+                dry_run: false,
+            }
+        );
+        assert_eq!(
+            command(&["update"]),
+            Command::Update {
+                name: None,
+                storage: StorageArgs::default(),
+                dry_run: false,
+            }
+        );
+        assert!(parse_from(&["update", "x", "--chapters", "5"]).is_err());
+        assert!(parse_from(&["update", "--force"]).is_err());
+        assert!(parse_from(&["block"]).is_err());
+        assert!(parse_from(&["export", "x", "--dry-run"]).is_err());
+        assert!(matches!(
+            command(&["update", "--dry-run"]),
+            Command::Update { dry_run: true, .. }
+        ));
+    }
+
+    #[test]
+    fn urls_come_one_at_a_time_or_as_a_list() {
+        let urls = |args: &[&str]| match command(args) {
+            Command::Add { urls } | Command::Download { urls, .. } => urls,
+            other => panic!("unexpected {other:?}"),
+        };
+        assert_eq!(urls(&["add", "URL"]).single(), Some("URL"));
+        assert_eq!(urls(&["add", "-"]).single(), None);
+        let list = urls(&["download", "--from-file", "list.txt", "-c", "latest:1"]);
+        assert_eq!(list.single(), None);
+        assert_eq!(list.from_file, Some(PathBuf::from("list.txt")));
+
+        assert!(parse_from(&["add"]).is_err());
+        assert!(parse_from(&["add", "URL", "--from-file", "list.txt"]).is_err());
+        assert_eq!(command(&["-a", "URL"]), command(&["add", "URL"]));
+    }
+
+    #[test]
+    fn config_commands_and_options() {
+        assert_eq!(command(&["init"]), Command::Init { force: false });
+        assert_eq!(
+            command(&["config", "show"]),
+            Command::Config {
+                command: ConfigCommand::Show
+            }
+        );
+        assert!(parse_from(&["config"]).is_err());
+        let args = parse_from(&[
+            "update",
+            "--config",
+            "alt.toml",
+            "--manga-dir",
+            "/mnt/manga",
+        ])
+        .unwrap();
+        assert_eq!(args.config, Some(PathBuf::from("alt.toml")));
+        assert_eq!(args.manga_dir, Some(PathBuf::from("/mnt/manga")));
+    }
+
+    #[test]
+    fn global_options_go_anywhere() {
+        let args = parse_from(&["-v", "add", "URL", "--log", "quiet", "--output", "json"]).unwrap();
+        assert!(args.verbose);
+        assert_eq!(args.log, LogMode::Quiet);
+        assert_eq!(args.output, OutputFormat::Json);
+    }
+
+    #[test]
+    fn legacy_flags_map_to_subcommands() {
+        assert_eq!(
+            command(&["-u"]),
+            Command::Update {
+                name: None,
+                storage: StorageArgs::default(),
+                dry_run: false,
+            }
+        );
+        assert_eq!(command(&["--update", "all"]), command(&["update"]));
+        assert_eq!(
+            command(&["-e", "my_manga", "--format", "pdf", "-c", "..3"]),
+            Command::Export {
+                name: "my_manga".to_string(),
+                format: ExportFormat::Pdf,
+                chapters: Some(ChapterRange::parse("..3").unwrap()),
+                by_volume: false,
+                out: PathBuf::from("."),
+            }
+        );
+        assert_eq!(
+            command(&["--relink", "old", "new"]),
+            Command::Relink {
+                name: "old".to_string(),
+                folder: PathBuf::from("new"),
+            }
+        );
+        assert!(parse_from(&[]).unwrap().command.is_none());
+    }
+
+    #[test]
+    fn legacy_flags_are_validated() {
+        // only one command at a time
+        assert!(parse_from(&["-a", "X", "-d", "Y"]).is_err());
+        // options only with the commands that use them
+        assert!(parse_from(&["-u", "x", "-c", "5"]).is_err());
+        assert!(parse_from(&["-u", "--force"]).is_err());
+        assert!(parse_from(&["-a", "X", "--format", "pdf"]).is_err());
+        assert!(parse_from(&["-a", "X", "--global"]).is_err());
+        assert!(parse_from(&["-a", "X", "--dry-run"]).is_err());
+        assert!(parse_from(&["-d", "X", "add", "Y"]).is_err());
+    }
+}
@@ -1,41 +1,650 @@
 use directories::ProjectDirs;
 use serde::Deserialize;
-use std::{fs, path::PathBuf};
+use std::{collections::HashMap, env, fmt, fs, io::ErrorKind, path::PathBuf};
 
 use crate::{
+    backup::Backups,
     error::{MgdlError, MgdlResult},
+    export::PackMode,
+    filter::{FilterAction, MAX_DISTANCE},
+    layout::{Layout, LayoutConfig},
+    models::ReadingDirection,
+    process::{Profile, NO_PROFILE},
+    scrape::{Retry, INITIAL_DELAY},
     utils::expand_tilde,
 };
 
+const DEFAULT_BACKUP_RETENTION: usize = 10;
+const DEFAULT_MAX_ATTEMPTS: usize = 20;
+const DEFAULT_PAGE_REQUESTS: usize = 16;
+const DEFAULT_MANGA_DIR: &str = "~/manga";
+/// Names the config file to read, like `--config`.
+const CONFIG_ENV: &str = "MGDL_CONFIG";
+
+/// Top-level settings that `MGDL_<NAME>` variables override, with what they
+/// are when nothing sets them.
+const SETTINGS: [(&str, &str); 11] = [
+    ("manga_dir", DEFAULT_MANGA_DIR),
+    ("base_url", "(not set)"),
+    ("backup_retention", "10"),
+    ("pack", "folder"),
+    ("profile", "none"),
+    ("direction", "ltr"),
+    ("filter", "skip"),
+    ("max_attempts", "20"),
+    ("retry_delay", "300"),
+    ("page_requests", "16"),
+    ("max_distance", "8"),
+];
+/// Settings whose `MGDL_<NAME>` variable is read as a number.
+const NUMBER_SETTINGS: [&str; 5] = [
+    "backup_retention",
+    "max_attempts",
+    "retry_delay",
+    "page_requests",
+    "max_distance",
+];
+
+/// Commented starter `config.toml`, written by `mgdl init`.
+pub const STARTER: &str = r#"# mgdl configuration. Top-level settings can also be set with MGDL_<NAME>
+# environment variables (e.g. MGDL_MANGA_DIR), which win over this file.
+
+# site to download from (required)
+# base_url = "https://..."
+
+# where series folders go
+manga_dir = "~/manga"
+
+# DB snapshots kept in the backups folder
+backup_retention = 10
+
+# how finished chapters are stored: "folder" or "cbz"
+pack = "folder"
+
+# page order: "ltr", or "rtl" for manga read right to left
+direction = "ltr"
+
+# pages matching the blocklist: "skip" deletes them, "quarantine" moves them
+# under .quarantine/ in the series folder
+filter = "skip"
+
+# tries per request before giving up, and milliseconds before the first
+# retry (doubled for each next one)
+max_attempts = 20
+retry_delay = 300
+
+# page downloads in flight at once
+page_requests = 16
+
+# most bits a page's hash may differ from a blocked page's by to be filtered
+max_distance = 8
+
+# page processing profile, one of the [profiles.<name>] tables
+# profile = "kindle"
+
+# [profiles.kindle]
+# format = "jpeg"
+# width = 1072
+# height = 1448
+# grayscale = true
+
+# settings for one series, by folder name
+# [series.one_piece]
+# pack = "cbz"
+# direction = "rtl"
+
+# where series, chapters and pages live; run `mgdl relayout` after changing it
+# [layout]
+# series = "{name}"
+# chapter = "chapter_{number}"
+# page = "{page:03}.{ext}"
+# cbz = "{title} - Ch {chapter:04}"
+"#;
+
 #[derive(Deserialize)]
 struct RawConfig {
+    #[serde(default = "default_manga_dir")]
     manga_dir: String,
-    base_url: String,
+    base_url: Option<String>,
+    #[serde(default = "default_backup_retention")]
+    backup_retention: usize,
+    #[serde(default = "default_max_attempts")]
+    max_attempts: usize,
+    #[serde(default = "default_retry_delay")]
+    retry_delay: u64,
+    #[serde(default = "default_page_requests")]
+    page_requests: usize,
+    #[serde(default = "default_max_distance")]
+    max_distance: u32,
+    #[serde(flatten)]
+    defaults: SeriesConfig,
+    #[serde(default)]
+    series: HashMap<String, SeriesConfig>,
+    #[serde(default)]
+    layout: LayoutConfig,
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+}
+
+fn default_backup_retention() -> usize {
+    DEFAULT_BACKUP_RETENTION
+}
+
+fn default_max_attempts() -> usize {
+    DEFAULT_MAX_ATTEMPTS
+}
+
+fn default_retry_delay() -> u64 {
+    INITIAL_DELAY
+}
+
+fn default_page_requests() -> usize {
+    DEFAULT_PAGE_REQUESTS
+}
+
+fn default_max_distance() -> u32 {
+    MAX_DISTANCE
+}
+
+fn default_manga_dir() -> String {
+    DEFAULT_MANGA_DIR.to_string()
+}
+
+/// Settings that can be set at the top level of `config.toml` and overridden
+/// per series in a `[series.<folder name>]` table.
+#[derive(Deserialize, Default, Clone, Debug)]
+pub struct SeriesConfig {
+    pub pack: Option<PackMode>,
+    /// name of the `[profiles.<name>]` table to process pages with
+    pub profile: Option<String>,
+    /// `rtl` for series read right to left, like most manga
+    pub direction: Option<ReadingDirection>,
+    /// what to do with downloaded pages on the blocklist
+    pub filter: Option<FilterAction>,
+}
+
+impl SeriesConfig {
+    /// Fill the fields left unset from `fallback`.
+    fn or(self, fallback: &SeriesConfig) -> SeriesConfig {
+        SeriesConfig {
+            pack: self.pack.or(fallback.pack),
+            profile: self.profile.or_else(|| fallback.profile.clone()),
+            direction: self.direction.or(fallback.direction),
+            filter: self.filter.or(fallback.filter),
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct SeriesSettings {
+    /// Set from the command line for a single run; wins over the config.
+    pub overrides: SeriesConfig,
+    pub defaults: SeriesConfig,
+    pub series: HashMap<String, SeriesConfig>,
+    pub profiles: HashMap<String, Profile>,
+}
+
+impl SeriesSettings {
+    pub fn get(&self, normalized_name: &str) -> SeriesConfig {
+        let series = self
+            .series
+            .get(normalized_name)
+            .cloned()
+            .unwrap_or_default();
+        self.overrides.clone().or(&series.or(&self.defaults))
+    }
+
+    /// The processing profile of a series, if any.
+    pub fn profile(&self, normalized_name: &str) -> MgdlResult<Option<&Profile>> {
+        match self.get(normalized_name).profile.as_deref() {
+            None | Some(NO_PROFILE) => Ok(None),
+            Some(name) => self
+                .profiles
+                .get(name)
+                .map(Some)
+                .ok_or_else(|| MgdlError::Config(format!("Unknown profile: {name}"))),
+        }
+    }
+
+    /// Check the profiles and that every profile named is defined.
+    fn validate(&self) -> MgdlResult<()> {
+        for (name, profile) in &self.profiles {
+            profile.validate(name)?;
+        }
+        // "" has no series table, so it resolves the defaults
+        for name in self.series.keys().map(String::as_str).chain([""]) {
+            self.profile(name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a setting came from, from lowest to highest precedence.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Default,
+    File,
+    /// the environment variable
+    Env(String),
+    /// the command line option
+    Cli(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File => write!(f, "config file"),
+            Source::Env(var) => write!(f, "{var}"),
+            Source::Cli(option) => write!(f, "{option}"),
+        }
+    }
+}
+
+/// A setting as resolved, for `mgdl config show`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Setting {
+    pub name: &'static str,
+    pub value: String,
+    pub source: Source,
+}
+
+/// Settings given on the command line, which win over everything else.
+#[derive(Debug, Default)]
+pub struct CliOverrides {
+    pub config: Option<PathBuf>,
+    pub manga_dir: Option<PathBuf>,
 }
 
 pub struct Config {
+    /// The config file read, if it exists.
+    pub path: PathBuf,
     pub manga_dir: PathBuf,
     pub db_dir: PathBuf,
     pub base_url: String,
+    /// How many DB snapshots to keep in `db_dir/backups`.
+    pub backup_retention: usize,
+    /// How often and how patiently requests are retried.
+    pub retry: Retry,
+    /// Page requests in flight at once, across every series of a run.
+    pub page_requests: usize,
+    /// Most bits a page's dHash may differ from a blocked one's by.
+    pub max_distance: u32,
+    pub series: SeriesSettings,
+    pub layout: Layout,
+    /// The config file and top-level settings, and where each came from.
+    pub settings: Vec<Setting>,
 }
 
 impl Config {
-    pub fn load() -> MgdlResult<Self> {
-        let project_dirs = ProjectDirs::from("com", "NasreddinHodja", "Mgdl")
-            .ok_or_else(|| MgdlError::Config("Could not open config dirs.".to_string()))?;
+    /// Settings layered from defaults, `config.toml`, `MGDL_*` variables and
+    /// the command line, each overriding the ones before.
+    pub fn load(cli: &CliOverrides) -> MgdlResult<Self> {
+        let config = Self::resolve(cli)?;
+        if config.base_url.is_empty() {
+            return Err(MgdlError::Config(format!(
+                "base_url is not set; add it to {} (`mgdl init` writes a starter one) or set MGDL_BASE_URL",
+                config.path.display()
+            )));
+        }
+        Ok(config)
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.db_dir.join("mgdl.db")
+    }
 
-        let config_dir = project_dirs.config_dir();
-        fs::create_dir_all(config_dir)?;
+    /// Snapshots of the DB, in `db_dir/backups`.
+    pub fn backups(&self) -> Backups {
+        Backups::new(self.db_dir.join("backups"), self.backup_retention)
+    }
 
-        let config_path = config_dir.join("config.toml");
-        let config_string = fs::read_to_string(&config_path)
-            .map_err(|e| MgdlError::Config(format!("{}: {}", config_path.display(), e)))?;
-        let raw: RawConfig = toml::from_str(&config_string)?;
+    /// Like `load`, but without requiring a `base_url`.
+    pub fn resolve(cli: &CliOverrides) -> MgdlResult<Self> {
+        let db_dir = config_dir()?;
+        fs::create_dir_all(&db_dir)?;
+
+        let (path, path_source) = config_path(cli, env_var)?;
+        let table = match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text)?,
+            // the default file is optional, one asked for isn't
+            Err(e) if e.kind() == ErrorKind::NotFound && path_source == Source::Default => {
+                toml::Table::new()
+            }
+            Err(e) => return Err(MgdlError::Config(format!("{}: {}", path.display(), e))),
+        };
+        let (raw, mut settings) = layer(table, env_var, cli)?;
+        settings.insert(
+            0,
+            Setting {
+                name: "config",
+                value: path.display().to_string(),
+                source: path_source,
+            },
+        );
+
+        let series = SeriesSettings {
+            overrides: SeriesConfig::default(),
+            defaults: raw.defaults,
+            series: raw.series,
+            profiles: raw.profiles,
+        };
+        series.validate()?;
 
         Ok(Self {
+            path,
             manga_dir: expand_tilde(PathBuf::from(raw.manga_dir))?,
-            db_dir: expand_tilde(config_dir.to_path_buf())?,
-            base_url: raw.base_url,
+            db_dir,
+            base_url: raw.base_url.unwrap_or_default(),
+            backup_retention: raw.backup_retention,
+            retry: Retry {
+                attempts: raw.max_attempts,
+                delay: raw.retry_delay,
+            },
+            page_requests: raw.page_requests,
+            max_distance: raw.max_distance,
+            series,
+            layout: Layout::new(raw.layout)?,
+            settings,
         })
     }
 }
+
+/// Write the starter config where mgdl would read it, unless one is there.
+pub fn init(cli: &CliOverrides, force: bool) -> MgdlResult<PathBuf> {
+    let (path, _) = config_path(cli, env_var)?;
+    if path.exists() && !force {
+        return Err(MgdlError::Config(format!(
+            "{} already exists (use --force to overwrite it)",
+            path.display()
+        )));
+    }
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, STARTER)?;
+    Ok(path)
+}
+
+/// The standard config folder, which also holds the DB.
+fn config_dir() -> MgdlResult<PathBuf> {
+    let project_dirs = ProjectDirs::from("com", "NasreddinHodja", "Mgdl")
+        .ok_or_else(|| MgdlError::Config("Could not open config dirs.".to_string()))?;
+    expand_tilde(project_dirs.config_dir().to_path_buf())
+}
+
+/// Unset and empty variables both leave a setting alone.
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// The config file: `--config`, else `MGDL_CONFIG`, else `config.toml` in
+/// the standard config folder.
+fn config_path(
+    cli: &CliOverrides,
+    env: impl Fn(&str) -> Option<String>,
+) -> MgdlResult<(PathBuf, Source)> {
+    if let Some(path) = &cli.config {
+        return Ok((path.clone(), Source::Cli("--config")));
+    }
+    if let Some(path) = env(CONFIG_ENV) {
+        return Ok((
+            expand_tilde(PathBuf::from(path))?,
+            Source::Env(CONFIG_ENV.to_string()),
+        ));
+    }
+    Ok((config_dir()?.join("config.toml"), Source::Default))
+}
+
+/// Apply `MGDL_*` variables and command line options over the config file's
+/// table, noting where each top-level setting ends up coming from.
+fn layer(
+    mut table: toml::Table,
+    env: impl Fn(&str) -> Option<String>,
+    cli: &CliOverrides,
+) -> MgdlResult<(RawConfig, Vec<Setting>)> {
+    let mut settings = Vec::new();
+    for (name, default) in SETTINGS {
+        let mut source = match table.contains_key(name) {
+            true => Source::File,
+            false => Source::Default,
+        };
+        let var = format!("MGDL_{}", name.to_uppercase());
+        if let Some(text) = env(&var) {
+            let value = match NUMBER_SETTINGS.contains(&name) {
+                true => text
+                    .parse()
+                    .map(toml::Value::Integer)
+                    .map_err(|_| MgdlError::Config(format!("{var}: {text:?} is not a number")))?,
+                false => toml::Value::String(text),
+            };
+            table.insert(name.to_string(), value);
+            source = Source::Env(var);
+        }
+        if let (Some(dir), "manga_dir") = (&cli.manga_dir, name) {
+            let dir = dir.display().to_string();
+            table.insert(name.to_string(), toml::Value::String(dir));
+            source = Source::Cli("--manga-dir");
+        }
+        let value = match table.get(name) {
+            Some(toml::Value::String(text)) => text.clone(),
+            Some(value) => value.to_string(),
+            None => default.to_string(),
+        };
+        settings.push(Setting {
+            name,
+            value,
+            source,
+        });
+    }
+    Ok((toml::Value::Table(table).try_into()?, settings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_settings_precedence() {
+        let mut settings = SeriesSettings {
+            defaults: SeriesConfig {
+                pack: Some(PackMode::Folder),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        settings.series.insert(
+            "packed".to_string(),
+            SeriesConfig {
+                pack: Some(PackMode::Cbz),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(settings.get("other").pack, Some(PackMode::Folder));
+        assert_eq!(settings.get("packed").pack, Some(PackMode::Cbz));
+
+        settings.overrides.pack = Some(PackMode::Folder);
+        assert_eq!(settings.get("packed").pack, Some(PackMode::Folder));
+    }
+
+    #[test]
+    fn raw_config_reads_series_tables() {
+        let raw: RawConfig = toml::from_str(
+            r#"
+            manga_dir = "~/manga"
+            base_url = "https://example.com"
+            pack = "cbz"
+
+            [series.one_piece]
+            pack = "folder"
+            direction = "rtl"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(raw.defaults.pack, Some(PackMode::Cbz));
+        assert_eq!(raw.series["one_piece"].pack, Some(PackMode::Folder));
+        assert_eq!(
+            raw.series["one_piece"].direction,
+            Some(ReadingDirection::Rtl)
+        );
+        assert_eq!(raw.defaults.direction, None);
+    }
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    fn source_of(settings: &[Setting], name: &str) -> Source {
+        let setting = settings.iter().find(|s| s.name == name).unwrap();
+        setting.source.clone()
+    }
+
+    #[test]
+    fn settings_layer_file_env_and_cli() {
+        let table: toml::Table = toml::from_str(
+            r#"
+            manga_dir = "~/from_file"
+            base_url = "https://example.com"
+            pack = "cbz"
+            "#,
+        )
+        .unwrap();
+        let vars = [
+            ("MGDL_MANGA_DIR", "/from/env"),
+            ("MGDL_BACKUP_RETENTION", "3"),
+            ("MGDL_DIRECTION", "rtl"),
+            ("MGDL_PAGE_REQUESTS", "4"),
+        ];
+        let cli = CliOverrides {
+            manga_dir: Some(PathBuf::from("/from/cli")),
+            ..Default::default()
+        };
+        let (raw, settings) = layer(table, env(&vars), &cli).unwrap();
+
+        assert_eq!(raw.manga_dir, "/from/cli");
+        assert_eq!(raw.base_url.as_deref(), Some("https://example.com"));
+        assert_eq!(raw.backup_retention, 3);
+        assert_eq!(raw.page_requests, 4);
+        assert_eq!(raw.max_attempts, DEFAULT_MAX_ATTEMPTS);
+        assert_eq!(raw.defaults.pack, Some(PackMode::Cbz));
+        assert_eq!(raw.defaults.direction, Some(ReadingDirection::Rtl));
+        assert_eq!(
+            source_of(&settings, "manga_dir"),
+            Source::Cli("--manga-dir")
+        );
+        assert_eq!(source_of(&settings, "base_url"), Source::File);
+        assert_eq!(
+            source_of(&settings, "backup_retention"),
+            Source::Env("MGDL_BACKUP_RETENTION".to_string())
+        );
+        assert_eq!(source_of(&settings, "filter"), Source::Default);
+    }
+
+    #[test]
+    fn settings_have_defaults() {
+        let (raw, settings) =
+            layer(toml::Table::new(), env(&[]), &CliOverrides::default()).unwrap();
+        assert_eq!(raw.manga_dir, DEFAULT_MANGA_DIR);
+        assert_eq!(raw.base_url, None);
+        assert_eq!(raw.backup_retention, DEFAULT_BACKUP_RETENTION);
+        assert_eq!(raw.max_attempts, DEFAULT_MAX_ATTEMPTS);
+        assert_eq!(raw.retry_delay, INITIAL_DELAY);
+        assert_eq!(raw.page_requests, DEFAULT_PAGE_REQUESTS);
+        assert_eq!(raw.max_distance, MAX_DISTANCE);
+        assert!(settings.iter().all(|s| s.source == Source::Default));
+        // the shown defaults are the ones used
+        let shown = |name| {
+            settings
+                .iter()
+                .find(|s| s.name == name)
+                .unwrap()
+                .value
+                .clone()
+        };
+        assert_eq!(shown("max_attempts"), DEFAULT_MAX_ATTEMPTS.to_string());
+        assert_eq!(shown("retry_delay"), INITIAL_DELAY.to_string());
+        assert_eq!(shown("page_requests"), DEFAULT_PAGE_REQUESTS.to_string());
+        assert_eq!(shown("max_distance"), MAX_DISTANCE.to_string());
+
+        for bad in [
+            ("MGDL_BACKUP_RETENTION", "ten"),
+            ("MGDL_MAX_DISTANCE", "close"),
+        ] {
+            assert!(layer(toml::Table::new(), env(&[bad]), &CliOverrides::default()).is_err());
+        }
+    }
+
+    #[test]
+    fn config_path_prefers_cli_then_env() {
+        let vars = [(CONFIG_ENV, "/env/config.toml")];
+        let (path, source) = config_path(&CliOverrides::default(), env(&vars)).unwrap();
+        assert_eq!(path, PathBuf::from("/env/config.toml"));
+        assert_eq!(source, Source::Env(CONFIG_ENV.to_string()));
+
+        let cli = CliOverrides {
+            config: Some(PathBuf::from("other.toml")),
+            ..Default::default()
+        };
+        let (path, source) = config_path(&cli, env(&vars)).unwrap();
+        assert_eq!(path, PathBuf::from("other.toml"));
+        assert_eq!(source, Source::Cli("--config"));
+    }
+
+    #[test]
+    fn starter_config_is_valid() {
+        let table: toml::Table = toml::from_str(STARTER).unwrap();
+        let vars = [("MGDL_BASE_URL", "https://example.com")];
+        let (raw, _) = layer(table, env(&vars), &CliOverrides::default()).unwrap();
+        assert_eq!(raw.defaults.pack, Some(PackMode::Folder));
+        assert_eq!(raw.max_attempts, DEFAULT_MAX_ATTEMPTS);
+        assert_eq!(raw.retry_delay, INITIAL_DELAY);
+        assert_eq!(raw.page_requests, DEFAULT_PAGE_REQUESTS);
+        assert_eq!(raw.max_distance, MAX_DISTANCE);
+        Layout::new(raw.layout).unwrap();
+    }
+
+    #[test]
+    fn series_profiles_resolve_by_name() {
+        let raw: RawConfig = toml::from_str(
+            r#"
+            manga_dir = "~/manga"
+            base_url = "https://example.com"
+            profile = "kindle"
+
+            [profiles.kindle]
+            format = "jpeg"
+            width = 1072
+            height = 1448
+            grayscale = true
+
+            [series.webtoon]
+            profile = "none"
+            "#,
+        )
+        .unwrap();
+        let settings = SeriesSettings {
+            defaults: raw.defaults,
+            series: raw.series,
+            profiles: raw.profiles,
+            ..Default::default()
+        };
+        settings.validate().unwrap();
+
+        let kindle = settings.profile("other").unwrap().unwrap();
+        assert_eq!(kindle.width, Some(1072));
+        assert!(kindle.grayscale);
+        assert!(settings.profile("webtoon").unwrap().is_none());
+
+        let mut settings = settings;
+        settings.defaults.profile = Some("kobo".to_string());
+        assert!(settings.validate().is_err());
+    }
+}
@@ -1,11 +1,97 @@
-use rusqlite::{params, Connection};
-use std::path::PathBuf;
+use rusqlite::{backup::Progress, params, Connection, DatabaseName, OptionalExtension};
+use std::path::{Path, PathBuf};
 
 use crate::{
     error::{MgdlError, MgdlResult},
-    models::Manga,
+    models::{LocalChapter, Manga},
 };
 
+/// Schema migrations, applied in order. `PRAGMA user_version` holds the
+/// number of migrations already applied to a database.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS mangas (
+        hash TEXT NOT NULL PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        normalized_name TEXT UNIQUE,
+        authors TEXT NOT NULL,
+        status TEXT NOT NULL
+    )",
+    "ALTER TABLE mangas ADD COLUMN detached INTEGER NOT NULL DEFAULT 0",
+    "CREATE TABLE IF NOT EXISTS chapters (
+        manga_hash TEXT NOT NULL,
+        number TEXT NOT NULL,
+        pages INTEGER NOT NULL,
+        PRIMARY KEY (manga_hash, number)
+    )",
+    // identity is the hash: titles may change upstream, so drop `name UNIQUE`
+    "CREATE TABLE mangas_by_hash (
+        hash TEXT NOT NULL PRIMARY KEY,
+        name TEXT NOT NULL,
+        normalized_name TEXT UNIQUE,
+        authors TEXT NOT NULL,
+        status TEXT NOT NULL,
+        detached INTEGER NOT NULL DEFAULT 0
+    );
+    INSERT INTO mangas_by_hash SELECT hash, name, normalized_name, authors, status, detached
+        FROM mangas;
+    DROP TABLE mangas;
+    ALTER TABLE mangas_by_hash RENAME TO mangas;
+    CREATE TABLE IF NOT EXISTS manga_titles (
+        manga_hash TEXT NOT NULL,
+        name TEXT NOT NULL,
+        normalized_name TEXT NOT NULL,
+        PRIMARY KEY (manga_hash, name)
+    );
+    INSERT OR IGNORE INTO manga_titles SELECT hash, name, normalized_name FROM mangas",
+    "ALTER TABLE chapters ADD COLUMN volume INTEGER",
+    "CREATE TABLE IF NOT EXISTS settings (
+        key TEXT NOT NULL PRIMARY KEY,
+        value TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS page_hashes (
+        hash TEXT NOT NULL PRIMARY KEY,
+        path TEXT NOT NULL
+    )",
+    // manga_hash '' marks a page blocked for every series
+    "CREATE TABLE IF NOT EXISTS blocked_pages (
+        phash INTEGER NOT NULL,
+        manga_hash TEXT NOT NULL,
+        PRIMARY KEY (phash, manga_hash)
+    );
+    CREATE TABLE IF NOT EXISTS filtered_pages (
+        manga_hash TEXT NOT NULL,
+        number TEXT NOT NULL,
+        page INTEGER NOT NULL,
+        PRIMARY KEY (manga_hash, number, page)
+    )",
+    // chapter numbers move from `NNNN-NN`, where `-01` marked a whole chapter
+    // and 5.1 collided with 5, to `NNNN` and `NNNN.N`; the folders follow on
+    // the next start. Libraries from before the chapters table have folders
+    // to rename but no rows, so any tracked manga sets the flag.
+    "INSERT OR REPLACE INTO settings (key, value)
+        SELECT 'legacy_chapter_names', '1'
+        WHERE EXISTS (SELECT 1 FROM mangas)
+            OR EXISTS (SELECT 1 FROM chapters WHERE number GLOB '[0-9]*-[0-9]*');
+    UPDATE chapters SET number = CASE CAST(substr(number, instr(number, '-') + 1) AS INTEGER)
+        WHEN 1 THEN substr(number, 1, instr(number, '-') - 1)
+        ELSE substr(number, 1, instr(number, '-') - 1) || '.'
+            || CAST(substr(number, instr(number, '-') + 1) AS INTEGER)
+        END
+        WHERE number GLOB '[0-9]*-[0-9]*';
+    UPDATE filtered_pages SET number = CASE CAST(substr(number, instr(number, '-') + 1) AS INTEGER)
+        WHEN 1 THEN substr(number, 1, instr(number, '-') - 1)
+        ELSE substr(number, 1, instr(number, '-') - 1) || '.'
+            || CAST(substr(number, instr(number, '-') + 1) AS INTEGER)
+        END
+        WHERE number GLOB '[0-9]*-[0-9]*'",
+];
+
+/// Set by the chapter number migration while chapter folders may still carry
+/// `NNNN-NN` names.
+pub const LEGACY_NAMES_SETTING: &str = "legacy_chapter_names";
+
+const MANGA_COLUMNS: &str = "hash, name, normalized_name, authors, status, detached";
+
 pub struct Db {
     conn: Connection,
 }
@@ -17,37 +103,89 @@ fn manga_from_row(row: &rusqlite::Row) -> rusqlite::Result<Manga> {
         normalized_name: row.get(2)?,
         authors: row.get(3)?,
         status: row.get(4)?,
+        detached: row.get(5)?,
     })
 }
 
 impl Db {
     pub fn new(path: PathBuf) -> MgdlResult<Self> {
+        let db = Self::open(path)?;
+        db.migrate()?;
+        Ok(db)
+    }
+
+    /// Open a database without applying pending migrations.
+    pub fn open(path: PathBuf) -> MgdlResult<Self> {
         let conn = Connection::open(&path)?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS mangas (
-                hash TEXT NOT NULL PRIMARY KEY,
-                name TEXT NOT NULL UNIQUE,
-                normalized_name TEXT UNIQUE,
-                authors TEXT NOT NULL,
-                status TEXT NOT NULL
-            )",
-            [],
-        )?;
         Ok(Self { conn })
     }
 
+    /// A migrated in-memory database, standing in for one not created yet.
+    pub fn empty() -> MgdlResult<Self> {
+        let db = Self {
+            conn: Connection::open_in_memory()?,
+        };
+        db.migrate()?;
+        Ok(db)
+    }
+
+    fn schema_version(&self) -> MgdlResult<usize> {
+        Ok(self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?)
+    }
+
+    /// Whether `migrate` would change an existing (non-empty) database.
+    pub fn needs_migration(&self) -> MgdlResult<bool> {
+        let tables: usize =
+            self.conn
+                .query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get(0))?;
+        Ok(tables > 0 && self.schema_version()? < MIGRATIONS.len())
+    }
+
+    fn migrate(&self) -> MgdlResult<()> {
+        let version = self.schema_version()?;
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            self.conn.execute_batch(&format!(
+                "BEGIN; {migration}; PRAGMA user_version = {}; COMMIT;",
+                i + 1
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    /// Copy the database to `path` using SQLite's online backup API.
+    pub fn backup_to(&self, path: &Path) -> MgdlResult<()> {
+        self.conn
+            .backup(DatabaseName::Main, path, None::<fn(Progress)>)?;
+        Ok(())
+    }
+
     pub fn drop_table(&self) -> MgdlResult<()> {
         self.conn.execute("DROP TABLE IF EXISTS mangas", [])?;
+        self.conn.execute("DROP TABLE IF EXISTS chapters", [])?;
+        self.conn.execute("DROP TABLE IF EXISTS manga_titles", [])?;
+        self.conn.execute("DROP TABLE IF EXISTS settings", [])?;
+        self.conn.execute("DROP TABLE IF EXISTS page_hashes", [])?;
+        self.conn
+            .execute("DROP TABLE IF EXISTS blocked_pages", [])?;
+        self.conn
+            .execute("DROP TABLE IF EXISTS filtered_pages", [])?;
+        self.conn.execute_batch("PRAGMA user_version = 0")?;
         Ok(())
     }
 
+    /// Insert or update a manga keyed on its hash. The stored
+    /// `normalized_name` (its folder) is kept on update, and every title the
+    /// manga has had is recorded in `manga_titles`.
     pub fn upsert_manga(&self, manga: Manga) -> MgdlResult<Manga> {
         self.conn.execute(
             "INSERT INTO mangas (hash, name, normalized_name, authors, status)
              VALUES (?, ?, ?, ?, ?)
-             ON CONFLICT(name) DO UPDATE SET
-                hash = excluded.hash,
-                normalized_name = excluded.normalized_name,
+             ON CONFLICT(hash) DO UPDATE SET
+                name = excluded.name,
                 authors = excluded.authors,
                 status = excluded.status",
             params![
@@ -58,30 +196,77 @@ impl Db {
                 manga.status,
             ],
         )?;
+        self.conn.execute(
+            "INSERT OR IGNORE INTO manga_titles (manga_hash, name, normalized_name)
+             VALUES (?, ?, ?)",
+            params![manga.hash, manga.name, manga.normalized_name],
+        )?;
+
+        self.get_manga_by_hash(&manga.hash)?
+            .ok_or_else(|| MgdlError::Db(format!("Couldn't upsert manga '{}'", manga.name)))
+    }
 
-        Ok(manga)
+    pub fn get_manga_by_hash(&self, hash: &str) -> MgdlResult<Option<Manga>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("SELECT {MANGA_COLUMNS} FROM mangas WHERE hash = ?"),
+                params![hash],
+                manga_from_row,
+            )
+            .optional()?)
     }
 
+    /// Look a manga up by folder name, falling back to names it had under
+    /// earlier titles.
     pub fn get_manga_by_normalized_name(&self, normalized_name: &str) -> MgdlResult<Manga> {
-        self.conn
+        let current = self
+            .conn
             .query_row(
-                "SELECT hash, name, normalized_name, authors, status
-                 FROM mangas WHERE normalized_name = ?",
+                &format!("SELECT {MANGA_COLUMNS} FROM mangas WHERE normalized_name = ?"),
                 params![normalized_name],
                 manga_from_row,
             )
-            .map_err(|_| {
-                MgdlError::Db(format!(
-                    "Couldn't get manga by normalized_name = '{}'",
-                    normalized_name
-                ))
-            })
+            .optional()?;
+        let manga = match current {
+            Some(manga) => Some(manga),
+            None => self
+                .conn
+                .query_row(
+                    &format!(
+                        "SELECT {MANGA_COLUMNS} FROM mangas WHERE hash =
+                            (SELECT manga_hash FROM manga_titles WHERE normalized_name = ?)"
+                    ),
+                    params![normalized_name],
+                    manga_from_row,
+                )
+                .optional()?,
+        };
+
+        manga.ok_or_else(|| {
+            MgdlError::Db(format!(
+                "Couldn't get manga by normalized_name = '{}'",
+                normalized_name
+            ))
+        })
     }
 
-    pub fn get_ongoing_manga(&self) -> MgdlResult<Vec<Manga>> {
+    /// Hash of the manga currently stored in folder `normalized_name`.
+    pub fn normalized_name_owner(&self, normalized_name: &str) -> MgdlResult<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT hash FROM mangas WHERE normalized_name = ?",
+                params![normalized_name],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn get_all_manga(&self) -> MgdlResult<Vec<Manga>> {
         let mut stmt = self
             .conn
-            .prepare("SELECT hash, name, normalized_name, authors, status FROM mangas WHERE status = 'Ongoing'")?;
+            .prepare(&format!("SELECT {MANGA_COLUMNS} FROM mangas ORDER BY name"))?;
 
         let mangas = stmt
             .query_map([], manga_from_row)?
@@ -90,11 +275,211 @@ impl Db {
         Ok(mangas)
     }
 
+    pub fn get_ongoing_manga(&self) -> MgdlResult<Vec<Manga>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {MANGA_COLUMNS} FROM mangas WHERE status = 'Ongoing'"
+        ))?;
+
+        let mangas = stmt
+            .query_map([], manga_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(mangas)
+    }
+
+    pub fn get_detached_manga(&self) -> MgdlResult<Vec<Manga>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {MANGA_COLUMNS} FROM mangas WHERE detached = 1"
+        ))?;
+
+        let mangas = stmt
+            .query_map([], manga_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(mangas)
+    }
+
+    /// Mark a manga whose folder went missing (or came back).
+    pub fn set_detached(&self, normalized_name: &str, detached: bool) -> MgdlResult<()> {
+        self.conn.execute(
+            "UPDATE mangas SET detached = ? WHERE normalized_name = ?",
+            params![detached, normalized_name],
+        )?;
+        Ok(())
+    }
+
+    /// Point a manga at a different folder and re-attach it.
+    pub fn relink_manga(&self, normalized_name: &str, new_normalized_name: &str) -> MgdlResult<()> {
+        let changed = self.conn.execute(
+            "UPDATE mangas SET normalized_name = ?, detached = 0 WHERE normalized_name = ?",
+            params![new_normalized_name, normalized_name],
+        )?;
+        if changed == 0 {
+            return Err(MgdlError::Db(format!(
+                "Couldn't get manga by normalized_name = '{}'",
+                normalized_name
+            )));
+        }
+        Ok(())
+    }
+
     pub fn delete_manga_by_normalized_name(&self, normalized_name: &str) -> MgdlResult<()> {
+        for table in ["chapters", "manga_titles"] {
+            self.conn.execute(
+                &format!(
+                    "DELETE FROM {table} WHERE manga_hash IN
+                        (SELECT hash FROM mangas WHERE normalized_name = ?)"
+                ),
+                params![normalized_name],
+            )?;
+        }
         self.conn.execute(
             "DELETE FROM mangas WHERE normalized_name = ?",
             params![normalized_name],
         )?;
         Ok(())
     }
+
+    pub fn get_setting(&self, key: &str) -> MgdlResult<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn set_setting(&self, key: &str, value: &str) -> MgdlResult<()> {
+        self.conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?, ?)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_setting(&self, key: &str) -> MgdlResult<()> {
+        self.conn
+            .execute("DELETE FROM settings WHERE key = ?", params![key])?;
+        Ok(())
+    }
+
+    /// Path, relative to `manga_dir`, of the page stored for a content hash.
+    pub fn get_page_path(&self, hash: &str) -> MgdlResult<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT path FROM page_hashes WHERE hash = ?",
+                params![hash],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn set_page_path(&self, hash: &str, path: &str) -> MgdlResult<()> {
+        self.conn.execute(
+            "INSERT INTO page_hashes (hash, path) VALUES (?, ?)
+             ON CONFLICT(hash) DO UPDATE SET path = excluded.path",
+            params![hash, path],
+        )?;
+        Ok(())
+    }
+
+    /// Block pages that look like `phash`, for one series or, with `None`,
+    /// for all.
+    pub fn add_blocked_page(&self, phash: u64, manga_hash: Option<&str>) -> MgdlResult<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO blocked_pages (phash, manga_hash) VALUES (?, ?)",
+            params![phash as i64, manga_hash.unwrap_or("")],
+        )?;
+        Ok(())
+    }
+
+    /// Perceptual hashes blocked for a series, including the global ones.
+    pub fn get_blocked_pages(&self, manga_hash: &str) -> MgdlResult<Vec<u64>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT phash FROM blocked_pages WHERE manga_hash IN ('', ?)")?;
+        let phashes = stmt
+            .query_map(params![manga_hash], |row| row.get::<_, i64>(0))?
+            .map(|phash| phash.map(|phash| phash as u64))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(phashes)
+    }
+
+    /// Remember that a page was dropped by the blocklist, so it counts as
+    /// done instead of being downloaded again.
+    pub fn add_filtered_page(&self, manga_hash: &str, number: &str, page: usize) -> MgdlResult<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO filtered_pages (manga_hash, number, page) VALUES (?, ?, ?)",
+            params![manga_hash, number, page],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_filtered_pages(&self, manga_hash: &str, number: &str) -> MgdlResult<Vec<usize>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT page FROM filtered_pages WHERE manga_hash = ? AND number = ? ORDER BY page",
+        )?;
+        let pages = stmt
+            .query_map(params![manga_hash, number], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(pages)
+    }
+
+    /// Record how many pages of a chapter are on disk. A missing volume keeps
+    /// the stored one, so a scan of the folders does not forget scraped
+    /// volumes.
+    pub fn upsert_chapter(&self, manga_hash: &str, chapter: &LocalChapter) -> MgdlResult<()> {
+        self.conn.execute(
+            "INSERT INTO chapters (manga_hash, number, pages, volume)
+             VALUES (?, ?, ?, ?)
+             ON CONFLICT(manga_hash, number) DO UPDATE SET
+                pages = excluded.pages,
+                volume = COALESCE(excluded.volume, chapters.volume)",
+            params![manga_hash, chapter.number, chapter.pages, chapter.volume],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_chapters(&self, manga_hash: &str) -> MgdlResult<Vec<LocalChapter>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT number, pages, volume FROM chapters WHERE manga_hash = ? ORDER BY number",
+        )?;
+
+        let chapters = stmt
+            .query_map(params![manga_hash], |row| {
+                Ok(LocalChapter {
+                    number: row.get(0)?,
+                    pages: row.get(1)?,
+                    volume: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(chapters)
+    }
+
+    /// Move a chapter's row and its filtered pages to a new number.
+    pub fn rename_chapter(&self, manga_hash: &str, from: &str, to: &str) -> MgdlResult<()> {
+        for table in ["chapters", "filtered_pages"] {
+            self.conn.execute(
+                &format!(
+                    "UPDATE OR REPLACE {table} SET number = ? WHERE manga_hash = ? AND number = ?"
+                ),
+                params![to, manga_hash, from],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn delete_chapter(&self, manga_hash: &str, number: &str) -> MgdlResult<()> {
+        self.conn.execute(
+            "DELETE FROM chapters WHERE manga_hash = ? AND number = ?",
+            params![manga_hash, number],
+        )?;
+        Ok(())
+    }
 }
@@ -0,0 +1,106 @@
+use sha2::{Digest, Sha256};
+use std::{fs, path::Path};
+
+use crate::{db::Db, error::MgdlResult, layout::Layout};
+
+/// Pages looked at by a dedupe pass, how many were replaced by a link to an
+/// identical page, and the bytes that freed.
+#[derive(Debug, Default, PartialEq)]
+pub struct DedupeReport {
+    pub pages: usize,
+    pub linked: usize,
+    pub bytes_saved: u64,
+}
+
+impl DedupeReport {
+    pub fn add(&mut self, other: DedupeReport) {
+        self.pages += other.pages;
+        self.linked += other.linked;
+        self.bytes_saved += other.bytes_saved;
+    }
+}
+
+/// Hex SHA-256 of a page's content.
+pub fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Share the page at `path` with the stored page of the same content, or
+/// record it as that content's copy. Paths are stored relative to `root`, the
+/// manga dir. Returns the bytes freed.
+pub fn dedupe_page(db: &Db, root: &Path, path: &Path) -> MgdlResult<u64> {
+    let bytes = fs::read(path)?;
+    let hash = content_hash(&bytes);
+    let relative = path.strip_prefix(root).unwrap_or(path);
+
+    let stored = db.get_page_path(&hash)?.map(|stored| root.join(stored));
+    let Some(stored) = stored.filter(|stored| stored.is_file() && stored != path) else {
+        db.set_page_path(&hash, &relative.to_string_lossy())?;
+        return Ok(0);
+    };
+    if is_same_file(&stored, path) || fs::metadata(&stored)?.len() != bytes.len() as u64 {
+        return Ok(0);
+    }
+
+    // link beside the page and rename over it, so it is never missing;
+    // filesystems without hardlinks keep their copy
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{file_name}.link"));
+    if fs::hard_link(&stored, &tmp).is_err() {
+        return Ok(0);
+    }
+    fs::rename(&tmp, path)?;
+    Ok(bytes.len() as u64)
+}
+
+/// Dedupe every page in a chapter folder.
+pub fn dedupe_chapter(
+    db: &Db,
+    layout: &Layout,
+    root: &Path,
+    chapter_path: &Path,
+) -> MgdlResult<DedupeReport> {
+    let mut report = DedupeReport::default();
+    let Ok(entries) = fs::read_dir(chapter_path) else {
+        return Ok(report);
+    };
+    let mut pages: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .filter(|e| {
+            e.file_name()
+                .to_str()
+                .is_some_and(|name| layout.page_number(name).is_some())
+        })
+        .map(|e| e.path())
+        .collect();
+    // the first page of a content to be seen is the one kept
+    pages.sort();
+
+    for page in pages {
+        let saved = dedupe_page(db, root, &page)?;
+        report.pages += 1;
+        if saved > 0 {
+            report.linked += 1;
+            report.bytes_saved += saved;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(unix)]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
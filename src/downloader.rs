@@ -1,66 +1,346 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::{fs, path::Path, path::PathBuf};
 use tokio::sync::Semaphore;
 use tokio::task::JoinSet;
 
+use indicatif::HumanBytes;
 use std::time::Instant;
 
 use crate::{
+    backup::Backups,
+    batch::Entry,
+    config::{Config, SeriesSettings},
     db,
-    error::MgdlResult,
-    logger::{LogMode, Logger},
-    models::{Chapter, ChapterRange, Manga},
+    dedupe::{self, DedupeReport},
+    error::{MgdlError, MgdlResult},
+    export::{
+        self, cbz,
+        comic_info::{display_number, ComicInfo},
+        epub, folder, pdf,
+        series_json::SeriesJson,
+        ExportFormat, PackMode,
+    },
+    filter::{self, Blocklist, FilterAction, QUARANTINE_DIR},
+    layout::{Layout, LayoutConfig},
+    library::{self, Sidecar, VolumeMap},
+    logger::{Event, Logger},
+    models::{
+        cmp_chapter_keys, major_number, Chapter, ChapterNumber, ChapterRange, LocalChapter, Manga,
+        ReadingDirection,
+    },
+    plan::{ChapterPlan, SeriesPlan},
+    process::{self, Profile, ORIGINALS_DIR},
     scrape,
 };
 
 #[cfg(feature = "bench")]
 use crate::bench::BenchCollector;
 
-const MAX_ATTEMPTS: usize = 20;
+/// Settings key of the layout the library on disk was written with.
+const LAYOUT_SETTING: &str = "layout";
 
 pub struct Downloader {
     db: db::Db,
+    backups: Backups,
     client: reqwest::Client,
     manga_dir: PathBuf,
     base_url: String,
-    logger: Logger,
+    series: SeriesSettings,
+    layout: Layout,
+    /// Layout of the library on disk; differs from `layout` until relayout.
+    disk_layout: LayoutConfig,
+    logger: Arc<Logger>,
+    retry: scrape::Retry,
+    /// shared by every series of a run, so a list of them keeps to the same
+    /// limit as one
+    page_requests: Arc<Semaphore>,
+    /// how close a page's dHash must be to a blocked one to be filtered
+    max_distance: u32,
     #[cfg(feature = "bench")]
     bench: Option<BenchCollector>,
 }
 
+/// What to do with each manga of a list of URLs.
+pub enum BatchJob<'a> {
+    Add,
+    Download {
+        /// for entries without their own selection
+        chapters: Option<&'a ChapterRange>,
+        force: bool,
+        dry_run: bool,
+    },
+}
+
 impl Downloader {
     pub fn new(
-        manga_dir: PathBuf,
-        db_dir: PathBuf,
-        base_url: String,
-        log_mode: LogMode,
-        verbose: bool,
+        config: Config,
+        logger: Arc<Logger>,
+        client: reqwest::Client,
+        #[cfg(feature = "bench")] bench: Option<BenchCollector>,
+    ) -> MgdlResult<Self> {
+        let backups = config.backups();
+        backups.snapshot_before_migration(&config.db_path())?;
+        let db = db::Db::new(config.db_path())?;
+        let disk_layout = Self::recorded_layout(&db, &config.layout)?;
+
+        let downloader = Self::with_db(
+            config,
+            db,
+            disk_layout,
+            logger,
+            client,
+            #[cfg(feature = "bench")]
+            bench,
+        );
+        if downloader
+            .db
+            .get_setting(db::LEGACY_NAMES_SETTING)?
+            .is_some()
+        {
+            downloader.rename_legacy_chapters()?;
+        }
+        Ok(downloader)
+    }
+
+    /// A downloader for dry runs: it opens the DB as it is, taking no
+    /// snapshot and writing no setting, and renames nothing on disk. A DB
+    /// that still needs migrating is refused, since planning would read it
+    /// with the wrong schema.
+    pub fn planner(
+        config: Config,
+        logger: Arc<Logger>,
         client: reqwest::Client,
         #[cfg(feature = "bench")] bench: Option<BenchCollector>,
     ) -> MgdlResult<Self> {
-        let db = db::Db::new(db_dir.join("mgdl.db"))?;
-        let logger = Logger::new(log_mode, verbose);
+        let db_path = config.db_path();
+        let db = if db_path.exists() {
+            db::Db::open(db_path)?
+        } else {
+            db::Db::empty()?
+        };
+        if db.needs_migration()? {
+            return Err(MgdlError::Db(
+                "The local DB needs migrating; run a command without --dry-run first".to_string(),
+            ));
+        }
+        let disk_layout = Self::library_layout(&db, &config.layout)?;
+        Ok(Self::with_db(
+            config,
+            db,
+            disk_layout,
+            logger,
+            client,
+            #[cfg(feature = "bench")]
+            bench,
+        ))
+    }
 
-        Ok(Self {
+    fn with_db(
+        config: Config,
+        db: db::Db,
+        disk_layout: LayoutConfig,
+        logger: Arc<Logger>,
+        client: reqwest::Client,
+        #[cfg(feature = "bench")] bench: Option<BenchCollector>,
+    ) -> Self {
+        Self {
             db,
+            backups: config.backups(),
             client,
-            manga_dir,
-            base_url,
+            manga_dir: config.manga_dir,
+            base_url: config.base_url,
+            series: config.series,
+            layout: config.layout,
+            disk_layout,
             logger,
+            retry: config.retry,
+            page_requests: Arc::new(Semaphore::new(config.page_requests)),
+            max_distance: config.max_distance,
             #[cfg(feature = "bench")]
             bench,
+        }
+    }
+
+    /// Rename chapter folders and CBZs still named by `NNNN-NN` numbers to
+    /// the numbers the DB migration gave them, in the library's own layout.
+    fn rename_legacy_chapters(&self) -> MgdlResult<()> {
+        let layout = Layout::new(self.disk_layout.clone())?;
+        for manga in self.db.get_all_manga()? {
+            let manga_path = self.manga_dir.join(&manga.normalized_name);
+            if !manga_path.is_dir() {
+                continue;
+            }
+            let dirs = layout
+                .find_chapter_dirs(&manga_path)
+                .into_iter()
+                .map(|(number, found)| {
+                    let target = layout.chapter_dir(&manga, &number, found.volume);
+                    (found.path, target)
+                });
+            let cbzs = layout
+                .find_cbz_files(&manga_path)
+                .into_iter()
+                .map(|(number, found)| {
+                    let target = layout.cbz_file(&manga, &number, found.volume);
+                    (found.path, target)
+                });
+
+            let mut renamed = 0;
+            for (path, target) in dirs.chain(cbzs) {
+                if self.move_chapter(&manga_path, &path, &manga_path.join(target))? {
+                    renamed += 1;
+                }
+            }
+            if renamed > 0 {
+                self.logger.success(format!(
+                    "Renamed {renamed} chapters of {} to the new chapter numbers",
+                    &manga.name
+                ));
+            }
+        }
+        self.db.delete_setting(db::LEGACY_NAMES_SETTING)
+    }
+
+    /// Move a chapter folder or CBZ within its series folder, keeping it
+    /// with a warning if something else is at `target` already. Whether it
+    /// was moved.
+    fn move_chapter(&self, manga_path: &Path, path: &Path, target: &Path) -> MgdlResult<bool> {
+        if path == target {
+            return Ok(false);
+        }
+        if target.exists() {
+            self.logger.warn(format!(
+                "Keeping {} since {} exists",
+                path.display(),
+                target.display()
+            ));
+            return Ok(false);
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(path, target)?;
+        if let Some(parent) = path.parent() {
+            remove_empty_dirs(parent, manga_path);
+        }
+        Ok(true)
+    }
+
+    /// Give specials still keyed by their bare name, from when only
+    /// same-named specials carried the end of their hash, the key of the
+    /// listed special of that name. A name several listed specials share
+    /// can't be told apart and is left as it is.
+    fn retag_specials(
+        &self,
+        manga: &Manga,
+        manga_path: &Path,
+        chapters: &[Chapter],
+    ) -> MgdlResult<()> {
+        let mut by_name: HashMap<&str, Vec<&str>> = HashMap::new();
+        for chapter in chapters {
+            if let Some(ChapterNumber::Special(_)) = ChapterNumber::from_key(&chapter.number) {
+                if let Some((bare, _)) = chapter.number.rsplit_once('-') {
+                    by_name.entry(bare).or_default().push(&chapter.number);
+                }
+            }
+        }
+        let chapter_dirs = self.layout.find_chapter_dirs(manga_path);
+        let cbz_files = self.layout.find_cbz_files(manga_path);
+        let recorded: HashSet<String> = self
+            .db
+            .get_chapters(&manga.hash)?
+            .into_iter()
+            .map(|ch| ch.number)
+            .collect();
+
+        for (bare, keys) in by_name {
+            let (dir, cbz) = (chapter_dirs.get(bare), cbz_files.get(bare));
+            if dir.is_none() && cbz.is_none() && !recorded.contains(bare) {
+                continue;
+            }
+            let [key] = keys[..] else {
+                self.logger.warn(format!(
+                    "Keeping chapter {} of {} since {} specials share its name",
+                    display_number(bare),
+                    &manga.name,
+                    keys.len()
+                ));
+                continue;
+            };
+            let mut moved = true;
+            if let Some(found) = dir {
+                let target = manga_path.join(self.layout.chapter_dir(manga, key, found.volume));
+                moved &= self.move_chapter(manga_path, &found.path, &target)?;
+            }
+            if let Some(found) = cbz {
+                let target = manga_path.join(self.layout.cbz_file(manga, key, found.volume));
+                moved &= self.move_chapter(manga_path, &found.path, &target)?;
+            }
+            if moved {
+                self.db.rename_chapter(&manga.hash, bare, key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The layout recorded in the DB, recording it first if it isn't yet.
+    fn recorded_layout(db: &db::Db, layout: &Layout) -> MgdlResult<LayoutConfig> {
+        let recorded = Self::library_layout(db, layout)?;
+        if db.get_setting(LAYOUT_SETTING)?.is_none() {
+            db.set_setting(LAYOUT_SETTING, &toml::to_string(&recorded)?)?;
+        }
+        Ok(recorded)
+    }
+
+    /// The layout the library on disk is in. A library from before layouts
+    /// were configurable uses the default one; an empty library adopts the
+    /// configured one.
+    fn library_layout(db: &db::Db, layout: &Layout) -> MgdlResult<LayoutConfig> {
+        if let Some(raw) = db.get_setting(LAYOUT_SETTING)? {
+            return Ok(toml::from_str(&raw)?);
+        }
+        Ok(if db.get_all_manga()?.is_empty() {
+            layout.config().clone()
+        } else {
+            LayoutConfig::default()
         })
     }
 
+    /// Refuse to touch the library while config.toml asks for a layout it
+    /// isn't in yet.
+    fn ensure_layout(&self) -> MgdlResult<()> {
+        if self.disk_layout == *self.layout.config() {
+            return Ok(());
+        }
+        Err(MgdlError::Config(
+            "The [layout] in config.toml differs from the library's; run `mgdl relayout` to move it"
+                .to_string(),
+        ))
+    }
+
+    /// Scrape a series and its chapter list, reporting the list entries
+    /// that had to be skipped.
+    async fn scrape_manga(&self, manga_url: &str) -> MgdlResult<(Manga, Vec<Chapter>)> {
+        let (manga, list) =
+            scrape::manga_from_url(&self.client, &self.base_url, manga_url, self.retry).await?;
+        for message in list.warnings {
+            self.logger.event(Event::ChapterListWarning {
+                series: &manga.name,
+                message,
+            });
+        }
+        Ok((manga, list.chapters))
+    }
+
     pub async fn add(&self, manga_url: &str) -> MgdlResult<(Manga, Vec<Chapter>)> {
+        self.ensure_layout()?;
         let spinner = self
             .logger
             .add_spinner(Some("Scraping manga and chapters".to_owned()))?;
 
         let scrape_start = Instant::now();
-        let (manga, chapters) =
-            scrape::manga_from_url(&self.client, &self.base_url, manga_url, MAX_ATTEMPTS).await?;
+        let (manga, chapters) = self.scrape_manga(manga_url).await?;
         #[cfg(feature = "bench")]
         if let Some(bench) = &self.bench {
             bench.record_scrape(scrape_start.elapsed());
@@ -68,12 +348,169 @@ impl Downloader {
         let _ = scrape_start; // suppress unused warning when bench is off
 
         spinner.set_message(format!("Adding manga {}", &manga.name));
-        let added_manga = self.db.upsert_manga(manga)?;
+        let added_manga = self.record_manga(manga)?;
+        let manga_path = self.manga_dir.join(&added_manga.normalized_name);
+        fs::create_dir_all(&manga_path)?;
+        Self::write_series_files(&added_manga, manga_url, &chapters, &manga_path)?;
+        self.retag_specials(&added_manga, &manga_path, &chapters)?;
 
         self.logger.finish_spinner(spinner);
         Ok((added_manga, chapters))
     }
 
+    /// Write the series' `mgdl.toml` and `series.json`.
+    fn write_series_files(
+        manga: &Manga,
+        manga_url: &str,
+        chapters: &[Chapter],
+        manga_path: &Path,
+    ) -> MgdlResult<()> {
+        Sidecar::new(manga, manga_url).write(manga_path)?;
+        SeriesJson::new(manga, chapters).write(manga_path)
+    }
+
+    /// Store freshly scraped metadata, following upstream title changes.
+    fn record_manga(&self, mut scraped: Manga) -> MgdlResult<Manga> {
+        scraped.normalized_name = self.layout.series_folder(&scraped.name, &scraped.hash);
+        let previous = self.db.get_manga_by_hash(&scraped.hash)?;
+        if previous.is_none() {
+            scraped.normalized_name =
+                self.distinct_folder_name(&scraped.hash, &scraped.normalized_name)?;
+        }
+        let scraped_normalized_name = scraped.normalized_name.clone();
+        let mut manga = self.db.upsert_manga(scraped)?;
+        if let Some(previous) = previous.filter(|p| p.name != manga.name) {
+            self.follow_title_change(&previous, &mut manga, &scraped_normalized_name)?;
+        }
+        Ok(manga)
+    }
+
+    /// `base`, or `base_2`, `base_3`, ... if another manga already owns that
+    /// folder, either in the DB or through its sidecar, or it is a folder of
+    /// someone else's.
+    fn distinct_folder_name(&self, hash: &str, base: &str) -> MgdlResult<String> {
+        let mut candidate = base.to_string();
+        for n in 2.. {
+            if !self.folder_taken(hash, &candidate)? {
+                break;
+            }
+            candidate = format!("{base}_{n}");
+        }
+        Ok(candidate)
+    }
+
+    /// Whether `folder_name` belongs to anything but the manga `hash`. A
+    /// folder without a sidecar is only its own if the DB says so, as for
+    /// libraries from before sidecars.
+    fn folder_taken(&self, hash: &str, folder_name: &str) -> MgdlResult<bool> {
+        let owner = self.db.normalized_name_owner(folder_name)?;
+        if owner.as_deref().is_some_and(|owner| owner != hash) {
+            return Ok(true);
+        }
+        let path = self.manga_dir.join(folder_name);
+        if !path.exists() {
+            return Ok(false);
+        }
+        Ok(match Sidecar::read(&path)? {
+            Some(sidecar) => sidecar.hash != hash,
+            None => owner.is_none(),
+        })
+    }
+
+    /// The site renamed a series: move its folder to the new name so the
+    /// library doesn't split in two. If the new folder is already taken the
+    /// old one is kept.
+    fn follow_title_change(
+        &self,
+        previous: &Manga,
+        manga: &mut Manga,
+        new_normalized_name: &str,
+    ) -> MgdlResult<()> {
+        if manga.normalized_name == new_normalized_name {
+            return Ok(());
+        }
+
+        let old_path = self.manga_dir.join(&manga.normalized_name);
+        let new_path = self.manga_dir.join(new_normalized_name);
+        if new_path.exists() || self.folder_taken(&manga.hash, new_normalized_name)? {
+            self.logger.warn(format!(
+                "{} was renamed to {}; keeping folder {} since {} is taken",
+                &previous.name,
+                &manga.name,
+                old_path.display(),
+                new_path.display()
+            ));
+            return Ok(());
+        }
+
+        if old_path.exists() {
+            fs::rename(&old_path, &new_path)?;
+        }
+        self.db
+            .relink_manga(&manga.normalized_name, new_normalized_name)?;
+        manga.normalized_name = new_normalized_name.to_string();
+        self.logger.success(format!(
+            "{} was renamed to {}; moved folder to {}",
+            &previous.name,
+            &manga.name,
+            new_path.display()
+        ));
+        Ok(())
+    }
+
+    /// Add or download every manga of a list, carrying on past the entries
+    /// that fail, then report how each one went.
+    pub async fn run_batch(&self, entries: &[Entry], job: BatchJob<'_>) -> MgdlResult<()> {
+        let mut outcomes = Vec::new();
+        for entry in entries {
+            outcomes.push(self.batch_entry(entry, &job).await);
+        }
+
+        let mut failed = 0;
+        for (entry, outcome) in entries.iter().zip(&outcomes) {
+            let (series, error) = match outcome {
+                Ok(series) => (Some(series.as_str()), None),
+                Err(err) => {
+                    failed += 1;
+                    (None, Some(err.to_string()))
+                }
+            };
+            self.logger.event(Event::BatchEntry {
+                line: entry.line,
+                url: &entry.url,
+                series,
+                error,
+            });
+        }
+        self.logger.event(Event::BatchTotal {
+            entries: entries.len(),
+            failed,
+        });
+        Ok(())
+    }
+
+    /// Handle one entry of a list, returning the name of its series.
+    async fn batch_entry(&self, entry: &Entry, job: &BatchJob<'_>) -> MgdlResult<String> {
+        match *job {
+            BatchJob::Add => Ok(self.add(&entry.url).await?.0.name),
+            BatchJob::Download {
+                chapters,
+                force,
+                dry_run,
+            } => {
+                let own = entry.chapters()?;
+                let chapters = own.as_ref().or(chapters);
+                if dry_run {
+                    let plans = self.plan_download(&entry.url, chapters, force).await?;
+                    let plan = plans.into_iter().next();
+                    Ok(plan.map(|plan| plan.name).unwrap_or_default())
+                } else {
+                    Ok(self.download_manga(&entry.url, chapters, force).await?.name)
+                }
+            }
+        }
+    }
+
     pub async fn download_manga(
         &self,
         manga_url: &str,
@@ -83,12 +520,16 @@ impl Downloader {
         let (manga, chapters) = self.add(manga_url).await?;
         let chapters = Self::filter_by_range(chapters, chapter_range);
         let manga_path = self.manga_dir.join(&manga.normalized_name);
+        self.logger.event(Event::Series {
+            series: &manga.name,
+            chapters: chapters.len(),
+        });
 
         let spinner = self
             .logger
             .add_spinner(Some(format!("Downloading {}", &manga.name)))?;
 
-        self.download_chapters(&manga.name, &manga_path, &chapters, force)
+        self.download_chapters(&manga, &manga_path, &chapters, force)
             .await?;
 
         self.logger.finish_spinner(spinner);
@@ -98,38 +539,55 @@ impl Downloader {
     /// Download all pages for given chapters. If force=false, skip pages that already exist.
     async fn download_chapters(
         &self,
-        manga_name: &str,
+        manga: &Manga,
         manga_path: &Path,
         chapters: &[Chapter],
         force: bool,
     ) -> MgdlResult<()> {
         fs::create_dir_all(manga_path)?;
+        let chapter_dirs = self.layout.find_chapter_dirs(manga_path);
+        let cbz_files = self.layout.find_cbz_files(manga_path);
+        let mut chapter_paths = HashMap::new();
 
-        let semaphore = Arc::new(Semaphore::new(16));
+        let semaphore = Arc::clone(&self.page_requests);
+        let profile = self.series.profile(&manga.normalized_name)?.cloned();
+        let direction = self.direction(manga);
+        let blocklist = Blocklist::new(self.db.get_blocked_pages(&manga.hash)?, self.max_distance);
+        let filter = self
+            .series
+            .get(&manga.normalized_name)
+            .filter
+            .unwrap_or_default();
 
+        let volumes = VolumeMap::read(manga_path)?;
         let progress_bar = self.logger.add_bar(chapters.len() as u64)?;
         progress_bar.set_prefix("Fetching chapter metadata".to_string());
 
         // Phase 1: fetch page metadata sequentially, spawn chapter download tasks
-        let mut chapter_tasks: JoinSet<MgdlResult<(String, usize)>> = JoinSet::new();
+        let mut chapter_tasks: JoinSet<ChapterDownload> = JoinSet::new();
         for chapter in chapters {
             let ch_start = Instant::now();
-            let pages = scrape::get_chapter_pages(
-                &self.client,
-                &self.base_url,
-                &chapter.hash,
-                MAX_ATTEMPTS,
-            )
-            .await?;
+            let pages =
+                scrape::get_chapter_pages(&self.client, &self.base_url, &chapter.hash, self.retry)
+                    .await?;
             #[cfg(feature = "bench")]
             if let Some(bench) = &self.bench {
                 bench.record_chapter_discovered(ch_start.elapsed());
             }
             let _ = ch_start;
 
-            let chapter_path = manga_path.join(format!("chapter_{}", &chapter.number));
+            let volume = chapter
+                .major_number()
+                .and_then(|n| volumes.volume_of(n))
+                .or(chapter.volume);
+            let chapter_path = match chapter_dirs.get(&chapter.number) {
+                Some(found) => found.path.clone(),
+                None => manga_path.join(self.layout.chapter_dir(manga, &chapter.number, volume)),
+            };
+            chapter_paths.insert(chapter.number.clone(), (chapter_path.clone(), volume));
 
-            let existing = existing_page_numbers(&chapter_path);
+            let cbz_path = cbz_files.get(&chapter.number).map(|f| f.path.as_path());
+            let existing = self.done_pages(manga, &chapter.number, &chapter_path, cbz_path)?;
             let skipped_count = if force { 0 } else { existing.len() };
             let new_pages: Vec<_> = if force {
                 pages
@@ -146,9 +604,14 @@ impl Downloader {
                     bench.record_page_skipped();
                 }
             }
-            let _ = skipped_count;
 
             if new_pages.is_empty() {
+                self.finish_chapter(manga, manga_path, chapter, &chapter_path, volume, cbz_path)?;
+                self.logger.event(Event::ChapterSkipped {
+                    series: &manga.name,
+                    chapter: &chapter.number,
+                    pages: skipped_count,
+                });
                 #[cfg(feature = "bench")]
                 if let Some(bench) = &self.bench {
                     bench.record_chapter_skipped();
@@ -158,45 +621,95 @@ impl Downloader {
             }
 
             fs::create_dir_all(&chapter_path)?;
+            let relative = chapter_path
+                .strip_prefix(manga_path)
+                .unwrap_or(&chapter_path);
+            let steps = Arc::new(PageSteps {
+                layout: self.layout.clone(),
+                profile: profile.clone(),
+                direction,
+                blocklist: blocklist.clone(),
+                filter,
+                originals_path: manga_path.join(ORIGINALS_DIR).join(relative),
+                quarantine_path: manga_path.join(QUARANTINE_DIR).join(relative),
+                logger: Arc::clone(&self.logger),
+            });
+            let new_pages = new_pages
+                .into_iter()
+                .map(|page| {
+                    let ext = scrape::page_extension(&page.url)?;
+                    let file_path = chapter_path.join(self.layout.page_file_name(page.number, ext));
+                    Ok((page, file_path))
+                })
+                .collect::<MgdlResult<Vec<_>>>()?;
 
             let page_count = new_pages.len();
-            let label = format!("{} ch.{}", manga_name, &chapter.number);
+            self.logger.event(Event::ChapterQueued {
+                series: &manga.name,
+                chapter: &chapter.number,
+                pages: page_count,
+            });
+            let number = chapter.number.clone();
+            let series = manga.name.clone();
+            let logger = Arc::clone(&self.logger);
             let sem = Arc::clone(&semaphore);
             let client = self.client.clone();
+            let retry = self.retry;
             #[cfg(feature = "bench")]
             let bench = self.bench.clone();
             chapter_tasks.spawn(async move {
-                let mut page_set: JoinSet<MgdlResult<()>> = JoinSet::new();
-                for page in new_pages {
-                    let chapter_path = chapter_path.clone();
+                let mut page_set: JoinSet<MgdlResult<Option<usize>>> = JoinSet::new();
+                for (page, file_path) in new_pages {
                     let permit = Arc::clone(&sem);
                     let client = client.clone();
+                    let steps = Arc::clone(&steps);
                     #[cfg(feature = "bench")]
                     let bench = bench.clone();
                     page_set.spawn(async move {
                         let _permit = permit.acquire().await.unwrap();
                         let page_start = Instant::now();
-                        let bytes = scrape::download_page(
-                            &client,
-                            page.url,
-                            chapter_path,
-                            page.number,
-                            MAX_ATTEMPTS,
-                        )
-                        .await?;
+                        let bytes =
+                            scrape::download_page(&client, page.url, file_path.clone(), retry)
+                                .await?;
+                        let dropped = if steps.is_empty() {
+                            false
+                        } else {
+                            tokio::task::spawn_blocking(move || steps.run(page.number, &file_path))
+                                .await??
+                        };
                         #[cfg(feature = "bench")]
                         if let Some(bench) = &bench {
                             bench.record_page_downloaded(page_start.elapsed(), bytes);
                         }
                         let _ = page_start;
                         let _ = bytes;
-                        Ok(())
+                        Ok(dropped.then_some(page.number))
                     });
                 }
+                let mut download = ChapterDownload {
+                    number,
+                    pages: page_count,
+                    skipped: skipped_count,
+                    filtered: Vec::new(),
+                    failed: 0,
+                    error: None,
+                };
                 while let Some(res) = page_set.join_next().await {
-                    res??;
+                    match res.map_err(MgdlError::from).and_then(|page| page) {
+                        Ok(filtered) => {
+                            logger.event(Event::PageDownloaded {
+                                series: &series,
+                                chapter: &download.number,
+                            });
+                            download.filtered.extend(filtered);
+                        }
+                        Err(err) => {
+                            download.failed += 1;
+                            download.error.get_or_insert(err);
+                        }
+                    }
                 }
-                Ok((label, page_count))
+                download
             });
             progress_bar.inc(1);
         }
@@ -208,9 +721,66 @@ impl Downloader {
             let progress_bar = self.logger.add_bar(total)?;
             progress_bar.set_prefix("Downloading".to_string());
             while let Some(res) = chapter_tasks.join_next().await {
-                let (label, page_count) = res??;
+                let ChapterDownload {
+                    number,
+                    pages,
+                    skipped,
+                    filtered,
+                    failed,
+                    error,
+                } = res?;
+                for page in &filtered {
+                    self.db.add_filtered_page(&manga.hash, &number, *page)?;
+                }
                 progress_bar.inc(1);
-                progress_bar.success(format!("Downloaded {} ({} pages)", label, page_count));
+                // leave incomplete chapters unpacked for consolidate
+                if let Some(err) = error {
+                    self.logger.event(Event::ChapterFailed {
+                        series: &manga.name,
+                        chapter: &number,
+                        pages: pages - failed - filtered.len(),
+                        skipped,
+                        failed,
+                        error: err.to_string(),
+                    });
+                    continue;
+                }
+                let chapter = chapters.iter().find(|ch| ch.number == number);
+                if let (Some(chapter), Some((chapter_path, volume))) =
+                    (chapter, chapter_paths.get(&number))
+                {
+                    let cbz_path = cbz_files.get(&number).map(|f| f.path.as_path());
+                    // pages packed into a CBZ can't be shared
+                    if self
+                        .series
+                        .get(&manga.normalized_name)
+                        .pack
+                        .unwrap_or_default()
+                        == PackMode::Folder
+                    {
+                        dedupe::dedupe_chapter(
+                            &self.db,
+                            &self.layout,
+                            &self.manga_dir,
+                            chapter_path,
+                        )?;
+                    }
+                    self.finish_chapter(
+                        manga,
+                        manga_path,
+                        chapter,
+                        chapter_path,
+                        *volume,
+                        cbz_path,
+                    )?;
+                }
+                self.logger.event(Event::ChapterDownloaded {
+                    series: &manga.name,
+                    chapter: &number,
+                    pages: pages - filtered.len(),
+                    skipped,
+                    filtered: filtered.len(),
+                });
             }
             self.logger.finish_bar(progress_bar);
         }
@@ -218,30 +788,189 @@ impl Downloader {
         Ok(())
     }
 
+    /// Pages of a chapter on disk, plus those dropped by the blocklist.
+    fn done_pages(
+        &self,
+        manga: &Manga,
+        number: &str,
+        chapter_path: &Path,
+        cbz_path: Option<&Path>,
+    ) -> MgdlResult<HashSet<usize>> {
+        let mut pages = library::existing_chapter_pages(&self.layout, chapter_path, cbz_path);
+        pages.extend(self.db.get_filtered_pages(&manga.hash, number)?);
+        Ok(pages)
+    }
+
+    fn direction(&self, manga: &Manga) -> ReadingDirection {
+        self.series
+            .get(&manga.normalized_name)
+            .direction
+            .unwrap_or_default()
+    }
+
+    /// Pack a complete chapter when the series is stored as CBZ, and record
+    /// how many of its pages are on disk and its volume.
+    fn finish_chapter(
+        &self,
+        manga: &Manga,
+        manga_path: &Path,
+        chapter: &Chapter,
+        chapter_path: &Path,
+        volume: Option<u32>,
+        cbz_path: Option<&Path>,
+    ) -> MgdlResult<()> {
+        let cbz_path = cbz_path.map(Path::to_path_buf).unwrap_or_else(|| {
+            manga_path.join(self.layout.cbz_file(manga, &chapter.number, volume))
+        });
+
+        let pack = self.series.get(&manga.normalized_name).pack;
+        if chapter_path.is_dir() {
+            let info = self.comic_info(manga, chapter, volume);
+            if pack.unwrap_or_default() == PackMode::Cbz {
+                if let Some(parent) = cbz_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                cbz::pack_chapter(chapter_path, &cbz_path, info)?;
+            } else {
+                self.write_chapter_info(info, chapter_path)?;
+            }
+        }
+
+        let pages = self
+            .done_pages(manga, &chapter.number, chapter_path, Some(&cbz_path))?
+            .len();
+        self.db.upsert_chapter(
+            &manga.hash,
+            &LocalChapter {
+                number: chapter.number.clone(),
+                pages,
+                volume,
+            },
+        )
+    }
+
+    fn comic_info(&self, manga: &Manga, chapter: &Chapter, volume: Option<u32>) -> ComicInfo {
+        let mut info = ComicInfo::new(manga, chapter, 0);
+        info.volume = volume;
+        info.right_to_left = self.direction(manga) == ReadingDirection::Rtl;
+        info
+    }
+
+    /// Write the ComicInfo.xml of a chapter kept as a folder.
+    fn write_chapter_info(&self, mut info: ComicInfo, chapter_path: &Path) -> MgdlResult<()> {
+        info.page_count = library::page_file_count(&self.layout, chapter_path);
+        info.write(chapter_path)
+    }
+
+    /// Rewrite the ComicInfo.xml of every chapter folder of a series, e.g.
+    /// after the site changed its metadata.
+    fn refresh_chapter_infos(
+        &self,
+        manga: &Manga,
+        manga_path: &Path,
+        chapters: &[Chapter],
+    ) -> MgdlResult<()> {
+        let stored = self.db.get_chapters(&manga.hash)?;
+        for (number, found) in self.layout.find_chapter_dirs(manga_path) {
+            let Some(chapter) = chapters.iter().find(|ch| ch.number == number) else {
+                continue;
+            };
+            let volume = stored
+                .iter()
+                .find(|ch| ch.number == number)
+                .and_then(|ch| ch.volume)
+                .or(found.volume)
+                .or(chapter.volume);
+            self.write_chapter_info(self.comic_info(manga, chapter, volume), &found.path)?;
+        }
+        Ok(())
+    }
+
     /// Update: only download chapters that don't have a local directory yet.
     async fn update_manga(&self, manga: &Manga) -> MgdlResult<usize> {
         let manga_url = format!("{}/series/{}", &self.base_url, &manga.hash);
-        let (_, chapters) =
-            scrape::manga_from_url(&self.client, &self.base_url, &manga_url, MAX_ATTEMPTS).await?;
+        let (scraped, chapters) = self.scrape_manga(&manga_url).await?;
+        let previous = manga;
+        let manga = self.record_manga(scraped)?;
         let manga_path = self.manga_dir.join(&manga.normalized_name);
+        Self::write_series_files(&manga, &manga_url, &chapters, &manga_path)?;
+        if (&previous.name, &previous.authors, &previous.status)
+            != (&manga.name, &manga.authors, &manga.status)
+        {
+            self.refresh_chapter_infos(&manga, &manga_path, &chapters)?;
+        }
+        self.retag_specials(&manga, &manga_path, &chapters)?;
 
-        // Filter to only chapters without a local directory
-        let new_chapters: Vec<_> = chapters
-            .into_iter()
-            .filter(|ch| !manga_path.join(format!("chapter_{}", &ch.number)).exists())
-            .collect();
-
+        let new_chapters = self.new_chapters(&manga_path, chapters);
+        self.logger.event(Event::Series {
+            series: &manga.name,
+            chapters: new_chapters.len(),
+        });
         let count = new_chapters.len();
         if !new_chapters.is_empty() {
-            self.download_chapters(&manga.name, &manga_path, &new_chapters, false)
+            self.download_chapters(&manga, &manga_path, &new_chapters, false)
                 .await?;
         }
 
         Ok(count)
     }
 
+    /// Every tracked series, attached or not.
+    pub fn library(&self) -> MgdlResult<Vec<Manga>> {
+        self.db.get_all_manga()
+    }
+
+    /// The chapters the site lists for a tracked series.
+    pub async fn remote_chapters(&self, manga: &Manga) -> MgdlResult<Vec<Chapter>> {
+        let manga_url = format!("{}/series/{}", &self.base_url, &manga.hash);
+        let (_, chapters) = self.scrape_manga(&manga_url).await?;
+        Ok(chapters)
+    }
+
+    /// The chapters of a tracked series on disk; none if its folder is
+    /// missing.
+    pub fn local_chapters(&self, manga: &Manga) -> MgdlResult<Vec<LocalChapter>> {
+        let manga_path = self.manga_dir.join(&manga.normalized_name);
+        if !manga_path.is_dir() {
+            return Ok(Vec::new());
+        }
+        library::local_chapters(&self.layout, &manga_path)
+    }
+
+    /// Download chapters picked from a tracked series' list, e.g. in the
+    /// terminal UI.
+    pub async fn download_picked(&self, manga: &Manga, chapters: &[Chapter]) -> MgdlResult<()> {
+        self.ensure_layout()?;
+        let Some(manga) = self.attached(vec![manga.clone()])?.pop() else {
+            return Ok(());
+        };
+        let manga_path = self.manga_dir.join(&manga.normalized_name);
+        self.logger.event(Event::Series {
+            series: &manga.name,
+            chapters: chapters.len(),
+        });
+        self.download_chapters(&manga, &manga_path, chapters, false)
+            .await
+    }
+
+    /// Chapters without a local directory or CBZ yet.
+    fn new_chapters(&self, manga_path: &Path, chapters: Vec<Chapter>) -> Vec<Chapter> {
+        let chapter_dirs = self.layout.find_chapter_dirs(manga_path);
+        let cbz_files = self.layout.find_cbz_files(manga_path);
+        chapters
+            .into_iter()
+            .filter(|ch| {
+                !cbz_files.contains_key(&ch.number) && !chapter_dirs.contains_key(&ch.number)
+            })
+            .collect()
+    }
+
     pub async fn update(&self, manga_name: &str) -> MgdlResult<()> {
+        self.ensure_layout()?;
         let manga = self.db.get_manga_by_normalized_name(manga_name)?;
+        let Some(manga) = self.attached(vec![manga])?.pop() else {
+            return Ok(());
+        };
         let spinner = self
             .logger
             .add_spinner(Some(format!("Updating {}", &manga.name)))?;
@@ -251,7 +980,8 @@ impl Downloader {
     }
 
     pub async fn update_all(&self) -> MgdlResult<()> {
-        let ongoing_manga = self.cleanup_missing_manga_dirs()?;
+        self.ensure_layout()?;
+        let ongoing_manga = self.attached(self.db.get_ongoing_manga()?)?;
 
         for manga in ongoing_manga {
             let spinner = self
@@ -267,16 +997,22 @@ impl Downloader {
     /// Consolidate: check all chapters for missing pages and download them.
     async fn consolidate_manga(&self, manga: &Manga) -> MgdlResult<()> {
         let manga_url = format!("{}/series/{}", &self.base_url, &manga.hash);
-        let (_, chapters) =
-            scrape::manga_from_url(&self.client, &self.base_url, &manga_url, MAX_ATTEMPTS).await?;
+        let (scraped, chapters) = self.scrape_manga(&manga_url).await?;
+        let manga = self.record_manga(scraped)?;
         let manga_path = self.manga_dir.join(&manga.normalized_name);
-        self.download_chapters(&manga.name, &manga_path, &chapters, false)
+        self.retag_specials(&manga, &manga_path, &chapters)?;
+        self.logger.event(Event::Series {
+            series: &manga.name,
+            chapters: chapters.len(),
+        });
+        self.download_chapters(&manga, &manga_path, &chapters, false)
             .await?;
         Ok(())
     }
 
     pub async fn consolidate_all(&self) -> MgdlResult<()> {
-        let ongoing_manga = self.db.get_ongoing_manga()?;
+        self.ensure_layout()?;
+        let ongoing_manga = self.attached(self.db.get_ongoing_manga()?)?;
 
         for manga in ongoing_manga {
             let spinner = self
@@ -289,40 +1025,379 @@ impl Downloader {
         Ok(())
     }
 
-    fn cleanup_missing_manga_dirs(&self) -> MgdlResult<Vec<Manga>> {
-        let ongoing_manga = self.db.get_ongoing_manga()?;
-        let mut deleted_count = 0;
-        let mut remaining = Vec::new();
+    /// Report what `download` would fetch, without writing anything.
+    pub async fn plan_download(
+        &self,
+        manga_url: &str,
+        chapter_range: Option<&ChapterRange>,
+        force: bool,
+    ) -> MgdlResult<Vec<SeriesPlan>> {
+        self.ensure_layout()?;
+        let (mut manga, chapters) = self.scrape_manga(manga_url).await?;
+        manga.normalized_name = match self.db.get_manga_by_hash(&manga.hash)? {
+            Some(known) => known.normalized_name,
+            None => self.layout.series_folder(&manga.name, &manga.hash),
+        };
+        let chapters = Self::filter_by_range(chapters, chapter_range);
+        let plan = self.plan_chapters(&manga, &chapters, force).await?;
+        self.report_plans(vec![plan])
+    }
 
-        for manga in ongoing_manga {
+    /// Report what `update` would fetch for one manga, or every ongoing
+    /// one, without writing anything.
+    pub async fn plan_update(&self, manga_name: Option<&str>) -> MgdlResult<Vec<SeriesPlan>> {
+        self.plan_series(manga_name, true).await
+    }
+
+    /// Report what `consolidate` would fetch, without writing anything.
+    pub async fn plan_consolidate(&self) -> MgdlResult<Vec<SeriesPlan>> {
+        self.plan_series(None, false).await
+    }
+
+    async fn plan_series(
+        &self,
+        manga_name: Option<&str>,
+        only_new: bool,
+    ) -> MgdlResult<Vec<SeriesPlan>> {
+        self.ensure_layout()?;
+        let mangas = match manga_name {
+            Some(name) => vec![self.db.get_manga_by_normalized_name(name)?],
+            None => self.db.get_ongoing_manga()?,
+        };
+        let mut plans = Vec::new();
+        for manga in self.on_disk(mangas) {
+            let manga_url = format!("{}/series/{}", &self.base_url, &manga.hash);
+            let (_, chapters) = self.scrape_manga(&manga_url).await?;
+            let chapters = if only_new {
+                self.new_chapters(&self.manga_dir.join(&manga.normalized_name), chapters)
+            } else {
+                chapters
+            };
+            plans.push(self.plan_chapters(&manga, &chapters, false).await?);
+        }
+        self.report_plans(plans)
+    }
+
+    /// Count the pages of `chapters` that are present and those a download
+    /// would fetch, with their sizes where HEAD requests report them.
+    async fn plan_chapters(
+        &self,
+        manga: &Manga,
+        chapters: &[Chapter],
+        force: bool,
+    ) -> MgdlResult<SeriesPlan> {
+        let manga_path = self.manga_dir.join(&manga.normalized_name);
+        let chapter_dirs = self.layout.find_chapter_dirs(&manga_path);
+        let cbz_files = self.layout.find_cbz_files(&manga_path);
+        let semaphore = Arc::clone(&self.page_requests);
+        let mut plan = SeriesPlan::new(&manga.name);
+
+        let progress_bar = self.logger.add_bar(chapters.len() as u64)?;
+        progress_bar.set_prefix(format!("Planning {}", &manga.name));
+        for chapter in chapters {
+            let pages =
+                scrape::get_chapter_pages(&self.client, &self.base_url, &chapter.hash, self.retry)
+                    .await?;
+            let chapter_path = chapter_dirs
+                .get(&chapter.number)
+                .map(|found| found.path.clone())
+                .unwrap_or_default();
+            let cbz_path = cbz_files.get(&chapter.number).map(|f| f.path.as_path());
+            let existing = self.done_pages(manga, &chapter.number, &chapter_path, cbz_path)?;
+            let present = pages
+                .iter()
+                .filter(|page| existing.contains(&page.number))
+                .count();
+
+            let mut sizes = JoinSet::new();
+            for page in pages {
+                if !force && existing.contains(&page.number) {
+                    continue;
+                }
+                let permit = Arc::clone(&semaphore);
+                let client = self.client.clone();
+                sizes.spawn(async move {
+                    let _permit = permit.acquire().await.unwrap();
+                    scrape::page_size(&client, &page.url).await
+                });
+            }
+            let mut chapter_plan = ChapterPlan {
+                number: chapter.number.clone(),
+                to_fetch: sizes.len(),
+                present,
+                ..Default::default()
+            };
+            while let Some(size) = sizes.join_next().await {
+                match size? {
+                    Some(bytes) => chapter_plan.bytes += bytes,
+                    None => chapter_plan.unknown_size += 1,
+                }
+            }
+            plan.chapters.push(chapter_plan);
+            progress_bar.inc(1);
+        }
+        self.logger.finish_bar(progress_bar);
+        Ok(plan)
+    }
+
+    fn report_plans(&self, plans: Vec<SeriesPlan>) -> MgdlResult<Vec<SeriesPlan>> {
+        for plan in &plans {
+            self.logger.event(Event::Plan(plan));
+        }
+        if plans.len() > 1 {
+            self.logger.event(Event::PlanTotal {
+                series: plans.iter().filter(|plan| plan.to_fetch() > 0).count(),
+                to_fetch: plans.iter().map(SeriesPlan::to_fetch).sum(),
+                bytes: plans.iter().map(SeriesPlan::bytes).sum(),
+            });
+        }
+        Ok(plans)
+    }
+
+    /// Manga whose folder exists, warning about the others like `attached`
+    /// but without marking them.
+    fn on_disk(&self, mangas: Vec<Manga>) -> Vec<Manga> {
+        mangas
+            .into_iter()
+            .filter(|manga| {
+                let manga_path = self.manga_dir.join(&manga.normalized_name);
+                let exists = manga_path.exists();
+                if !exists {
+                    self.logger.event(Event::SeriesSkipped {
+                        series: &manga.name,
+                        reason: format!("folder {} is missing", manga_path.display()),
+                    });
+                }
+                exists
+            })
+            .collect()
+    }
+
+    /// Split off manga whose folder is missing: they are marked detached and
+    /// skipped with a warning instead of being deleted. Manga whose folder
+    /// came back are re-attached.
+    fn attached(&self, mangas: Vec<Manga>) -> MgdlResult<Vec<Manga>> {
+        let mut attached = Vec::new();
+
+        for mut manga in mangas {
             let manga_path = self.manga_dir.join(&manga.normalized_name);
             if !manga_path.exists() {
-                self.db
-                    .delete_manga_by_normalized_name(&manga.normalized_name)?;
-                deleted_count += 1;
+                if !manga.detached {
+                    self.db.set_detached(&manga.normalized_name, true)?;
+                }
+                self.logger.event(Event::SeriesSkipped {
+                    series: &manga.name,
+                    reason: format!(
+                        "folder {} is missing (use `mgdl relink` or `mgdl prune`)",
+                        manga_path.display()
+                    ),
+                });
             } else {
-                remaining.push(manga);
+                if manga.detached {
+                    self.db.set_detached(&manga.normalized_name, false)?;
+                    manga.detached = false;
+                }
+                attached.push(manga);
             }
         }
 
-        if deleted_count > 0 {
-            eprintln!(
-                "Cleaned up {} manga entries with missing directories",
-                deleted_count
-            );
+        Ok(attached)
+    }
+
+    /// Point a manga at a different folder inside `manga_dir`.
+    pub fn relink(&self, manga_name: &str, folder: &Path) -> MgdlResult<()> {
+        let manga = self.db.get_manga_by_normalized_name(manga_name)?;
+
+        let folder_path = if folder.is_absolute() {
+            folder.to_path_buf()
+        } else {
+            self.manga_dir.join(folder)
+        };
+        if !folder_path.is_dir() {
+            return Err(MgdlError::Config(format!(
+                "{} is not a directory",
+                folder_path.display()
+            )));
         }
+        let folder_name = folder_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .filter(|_| folder_path.parent() == Some(self.manga_dir.as_path()))
+            .ok_or_else(|| {
+                MgdlError::Config(format!(
+                    "{} is not a folder inside {}",
+                    folder_path.display(),
+                    self.manga_dir.display()
+                ))
+            })?;
 
-        Ok(remaining)
+        self.db.relink_manga(&manga.normalized_name, folder_name)?;
+        self.logger.success(format!(
+            "Relinked {} to {}",
+            &manga.name,
+            folder_path.display()
+        ));
+        Ok(())
     }
 
-    fn filter_by_range(chapters: Vec<Chapter>, range: Option<&ChapterRange>) -> Vec<Chapter> {
-        let Some(range) = range else {
-            return chapters;
+    /// Add sample pages to the blocklist, for the series whose folder they are
+    /// in, or for every series when `global` or outside `manga_dir`.
+    pub fn block(&self, pages: &[PathBuf], global: bool) -> MgdlResult<()> {
+        for page in pages {
+            let phash = filter::dhash_file(page)?;
+            let manga = if global { None } else { self.series_of(page)? };
+            self.db
+                .add_blocked_page(phash, manga.as_ref().map(|m| m.hash.as_str()))?;
+            self.logger.success(format!(
+                "Blocked pages like {} in {}",
+                page.display(),
+                manga.map_or("all series".to_string(), |m| m.name)
+            ));
+        }
+        Ok(())
+    }
+
+    /// The series whose folder holds `path`, if any.
+    fn series_of(&self, path: &Path) -> MgdlResult<Option<Manga>> {
+        let path = fs::canonicalize(path)?;
+        let manga_dir = fs::canonicalize(&self.manga_dir)?;
+        let folder = path
+            .strip_prefix(&manga_dir)
+            .ok()
+            .and_then(|relative| relative.iter().next())
+            .and_then(|folder| folder.to_str());
+        Ok(folder.and_then(|folder| self.db.get_manga_by_normalized_name(folder).ok()))
+    }
+
+    /// Hardlink identical pages across the library's chapter folders.
+    pub fn dedupe(&self) -> MgdlResult<DedupeReport> {
+        self.ensure_layout()?;
+        let mut report = DedupeReport::default();
+        for manga in self.attached(self.db.get_all_manga()?)? {
+            let spinner = self
+                .logger
+                .add_spinner(Some(format!("Deduplicating {}", &manga.name)))?;
+            let manga_path = self.manga_dir.join(&manga.normalized_name);
+            let mut chapters: Vec<_> = self
+                .layout
+                .find_chapter_dirs(&manga_path)
+                .into_iter()
+                .collect();
+            chapters.sort_by(|(a, _), (b, _)| cmp_chapter_keys(a, b));
+            for (_, found) in chapters {
+                report.add(dedupe::dedupe_chapter(
+                    &self.db,
+                    &self.layout,
+                    &self.manga_dir,
+                    &found.path,
+                )?);
+            }
+            self.logger.finish_spinner(spinner);
+        }
+        self.logger.success(format!(
+            "Linked {} of {} pages, saving {}",
+            report.linked,
+            report.pages,
+            HumanBytes(report.bytes_saved)
+        ));
+        Ok(report)
+    }
+
+    /// Delete detached manga from the DB. Only runs when explicitly asked.
+    pub fn prune(&self) -> MgdlResult<()> {
+        for manga in self.db.get_detached_manga()? {
+            self.db
+                .delete_manga_by_normalized_name(&manga.normalized_name)?;
+            self.logger.success(format!("Pruned {}", &manga.name));
+        }
+        Ok(())
+    }
+
+    /// Export the downloaded chapters of a series in `range` into `out_dir`,
+    /// as one file, or one per volume with `by_volume`.
+    pub fn export(
+        &self,
+        manga_name: &str,
+        format: ExportFormat,
+        range: Option<&ChapterRange>,
+        by_volume: bool,
+        out_dir: &Path,
+    ) -> MgdlResult<Vec<PathBuf>> {
+        self.ensure_layout()?;
+        let manga = self.db.get_manga_by_normalized_name(manga_name)?;
+        let manga_path = self.manga_dir.join(&manga.normalized_name);
+        let spinner = self
+            .logger
+            .add_spinner(Some(format!("Exporting {}", &manga.name)))?;
+
+        let mut chapters = export::load_chapters(&self.layout, &manga_path, range)?;
+        let blocklist = Blocklist::new(self.db.get_blocked_pages(&manga.hash)?, self.max_distance);
+        if !blocklist.is_empty() {
+            for chapter in &mut chapters {
+                chapter.pages.retain(|page| !blocklist.matches(&page.bytes));
+            }
+            chapters.retain(|chapter| !chapter.pages.is_empty());
+        }
+        if chapters.is_empty() {
+            return Err(MgdlError::Export(format!(
+                "No downloaded chapters of {} in range",
+                &manga.name
+            )));
+        }
+        export::assign_volumes(
+            &mut chapters,
+            &VolumeMap::read(&manga_path)?,
+            &self.db.get_chapters(&manga.hash)?,
+        );
+        let bundles = if by_volume {
+            export::group_by_volume(chapters)
+        } else {
+            vec![chapters]
         };
-        chapters
-            .into_iter()
-            .filter(|ch| ch.major_number().is_some_and(|n| range.contains(n)))
-            .collect()
+        if by_volume && bundles.last().is_some_and(|b| b[0].volume.is_none()) {
+            self.logger.warn(format!(
+                "Some chapters of {} have no volume; add them to {} in its folder",
+                &manga.name,
+                library::VOLUMES_FILE
+            ));
+        }
+
+        fs::create_dir_all(out_dir)?;
+        let cover = library::cover_image(&manga_path)?;
+        let direction = self.direction(&manga);
+        let mut paths = Vec::new();
+        for chapters in bundles {
+            let path = out_dir.join(export::export_file_name(&manga.name, &chapters, format));
+            match format {
+                ExportFormat::Epub => {
+                    epub::write_epub(&path, &manga, &chapters, cover.as_ref(), direction)?
+                }
+                ExportFormat::Pdf => pdf::write_pdf(&path, &manga, &chapters, direction)?,
+                ExportFormat::Cbz => {
+                    cbz::write_bundle(&self.layout, &path, &manga, &chapters, direction)?
+                }
+                ExportFormat::Folder => {
+                    folder::write_folder(&self.layout, &path, &manga, &chapters)?
+                }
+            }
+            self.logger.success(format!(
+                "Exported {} chapters of {} to {}",
+                chapters.len(),
+                &manga.name,
+                path.display()
+            ));
+            paths.push(path);
+        }
+
+        self.logger.finish_spinner(spinner);
+        Ok(paths)
+    }
+
+    fn filter_by_range(chapters: Vec<Chapter>, range: Option<&ChapterRange>) -> Vec<Chapter> {
+        match range {
+            Some(range) => range.select(chapters),
+            None => chapters,
+        }
     }
 
     pub fn reset_db(&self) -> MgdlResult<()> {
@@ -330,26 +1405,500 @@ impl Downloader {
             .logger
             .add_spinner(Some("Dropping local DB".to_owned()))?;
 
+        let backup = self.backups.snapshot(&self.db, "reset")?;
         self.db.drop_table()?;
 
         self.logger.finish_spinner(spinner);
+        self.logger
+            .success(format!("Dropped local DB (backup at {})", backup.display()));
         Ok(())
     }
+
+    /// Rebuild manga and chapter rows from the series folders in `manga_dir`.
+    /// Folders without a sidecar are reported so they can be relinked by hand.
+    pub fn scan(&self) -> MgdlResult<()> {
+        self.ensure_layout()?;
+        let spinner = self
+            .logger
+            .add_spinner(Some(format!("Scanning {}", self.manga_dir.display())))?;
+        self.backups.snapshot(&self.db, "scan")?;
+
+        let mut unmatched = Vec::new();
+        let mut dirs: Vec<_> = fs::read_dir(&self.manga_dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect();
+        dirs.sort();
+
+        for manga_path in dirs {
+            let Some(folder_name) = manga_path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let Some(sidecar) = Sidecar::read(&manga_path)? else {
+                unmatched.push(folder_name.to_string());
+                continue;
+            };
+
+            let mut manga = self.db.upsert_manga(sidecar.to_manga(folder_name))?;
+            if manga.normalized_name != folder_name {
+                self.db.relink_manga(&manga.normalized_name, folder_name)?;
+                manga.normalized_name = folder_name.to_string();
+            }
+            let chapters = library::local_chapters(&self.layout, &manga_path)?;
+            for stale in self.db.get_chapters(&manga.hash)? {
+                if !chapters.iter().any(|ch| ch.number == stale.number) {
+                    self.db.delete_chapter(&manga.hash, &stale.number)?;
+                }
+            }
+            for chapter in &chapters {
+                self.db.upsert_chapter(&manga.hash, chapter)?;
+            }
+            self.logger.success(format!(
+                "Scanned {} ({} chapters)",
+                &manga.name,
+                chapters.len()
+            ));
+        }
+
+        self.logger.finish_spinner(spinner);
+        for folder in unmatched {
+            self.logger.warn(format!(
+                "No {} in {}; match it by hand with `mgdl add` and `mgdl relink`",
+                library::SIDECAR_FILE,
+                self.manga_dir.join(folder).display()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Move the library from the layout recorded in the DB to the one in
+    /// config.toml, then record the new layout.
+    pub fn relayout(&mut self) -> MgdlResult<()> {
+        if self.disk_layout == *self.layout.config() {
+            self.logger
+                .success("Library already uses the configured layout".to_string());
+            return Ok(());
+        }
+        let old = Layout::new(self.disk_layout.clone())?;
+        let spinner = self
+            .logger
+            .add_spinner(Some("Moving library to the new layout".to_owned()))?;
+        self.backups.snapshot(&self.db, "relayout")?;
+
+        // folders picked with `relink` only move when the series template did
+        let rename_series = old.config().series != self.layout.config().series;
+        for mut manga in self.db.get_all_manga()? {
+            let mut manga_path = self.manga_dir.join(&manga.normalized_name);
+            if !manga_path.is_dir() {
+                self.logger.warn(format!(
+                    "Skipping {}: folder {} is missing",
+                    &manga.name,
+                    manga_path.display()
+                ));
+                continue;
+            }
+
+            let folder = self.layout.series_folder(&manga.name, &manga.hash);
+            let folder = if rename_series && folder != manga.normalized_name {
+                self.distinct_folder_name(&manga.hash, &folder)?
+            } else {
+                manga.normalized_name.clone()
+            };
+            if folder != manga.normalized_name {
+                let new_path = self.manga_dir.join(&folder);
+                if new_path.exists() {
+                    self.logger.warn(format!(
+                        "Keeping folder {} for {} since {} exists",
+                        manga_path.display(),
+                        &manga.name,
+                        new_path.display()
+                    ));
+                } else {
+                    fs::rename(&manga_path, &new_path)?;
+                    self.db.relink_manga(&manga.normalized_name, &folder)?;
+                    manga.normalized_name = folder;
+                    manga_path = new_path;
+                }
+            }
+
+            let chapters = self.relayout_chapters(&old, &manga, &manga_path)?;
+            self.logger.success(format!(
+                "Moved {} ({} chapters) to {}",
+                &manga.name,
+                chapters,
+                manga_path.display()
+            ));
+        }
+
+        self.db
+            .set_setting(LAYOUT_SETTING, &toml::to_string(self.layout.config())?)?;
+        self.disk_layout = self.layout.config().clone();
+        self.logger.finish_spinner(spinner);
+        Ok(())
+    }
+
+    /// Move the chapters of one series from the `old` layout to the current
+    /// one. Everything goes through a staging folder first so old and new
+    /// names can overlap.
+    fn relayout_chapters(
+        &self,
+        old: &Layout,
+        manga: &Manga,
+        manga_path: &Path,
+    ) -> MgdlResult<usize> {
+        let staging = manga_path.join(".mgdl-relayout");
+        let chapter_dirs = old.find_chapter_dirs(manga_path);
+        let cbz_files = old.find_cbz_files(manga_path);
+        let mut staged = Vec::new();
+
+        for (number, found) in chapter_dirs {
+            let target = staging.join(&number);
+            fs::create_dir_all(&target)?;
+            for entry in fs::read_dir(&found.path)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let new_name = self.layout.convert_page_name(old, &name);
+                fs::rename(entry.path(), target.join(new_name))?;
+            }
+            remove_empty_dirs(&found.path, manga_path);
+            staged.push((number, found.volume, target, false));
+        }
+        for (number, found) in cbz_files {
+            fs::create_dir_all(&staging)?;
+            let target = staging.join(format!("{number}.cbz"));
+            fs::rename(&found.path, &target)?;
+            if old.config().page != self.layout.config().page {
+                cbz::rename_pages(&target, |name| self.layout.convert_page_name(old, name))?;
+            }
+            if let Some(parent) = found.path.parent() {
+                remove_empty_dirs(parent, manga_path);
+            }
+            staged.push((number, found.volume, target, true));
+        }
+
+        let volumes = VolumeMap::read(manga_path)?;
+        let stored = self.db.get_chapters(&manga.hash)?;
+        let count = staged.len();
+        for (number, found_volume, staged_path, is_cbz) in staged {
+            let volume = major_number(&number)
+                .and_then(|n| volumes.volume_of(n))
+                .or_else(|| {
+                    stored
+                        .iter()
+                        .find(|ch| ch.number == number)
+                        .and_then(|ch| ch.volume)
+                })
+                .or(found_volume);
+            let relative = if is_cbz {
+                self.layout.cbz_file(manga, &number, volume)
+            } else {
+                self.layout.chapter_dir(manga, &number, volume)
+            };
+            let destination = manga_path.join(relative);
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(&staged_path, &destination)?;
+        }
+        if staging.exists() {
+            fs::remove_dir(&staging)?;
+        }
+
+        for chapter in library::local_chapters(&self.layout, manga_path)? {
+            self.db.upsert_chapter(&manga.hash, &chapter)?;
+        }
+        Ok(count)
+    }
+
+    pub fn backup_db(&self) -> MgdlResult<()> {
+        let backup = self.backups.snapshot(&self.db, "manual")?;
+        self.logger
+            .success(format!("Backed up local DB to {}", backup.display()));
+        Ok(())
+    }
+}
+
+/// Remove `dir` and then its parents while they are empty, stopping at
+/// `root`.
+fn remove_empty_dirs(dir: &Path, root: &Path) {
+    let mut dir = dir;
+    while dir != root && dir.starts_with(root) && fs::remove_dir(dir).is_ok() {
+        let Some(parent) = dir.parent() else {
+            break;
+        };
+        dir = parent;
+    }
+}
+
+/// What happens to each page of a chapter once downloaded.
+struct PageSteps {
+    layout: Layout,
+    profile: Option<Profile>,
+    direction: ReadingDirection,
+    blocklist: Blocklist,
+    filter: FilterAction,
+    /// the chapter's folders under `.originals` and `.quarantine`
+    originals_path: PathBuf,
+    quarantine_path: PathBuf,
+    logger: Arc<Logger>,
 }
 
-fn existing_page_numbers(chapter_path: &Path) -> HashSet<usize> {
-    let Ok(entries) = fs::read_dir(chapter_path) else {
-        return HashSet::new();
+impl PageSteps {
+    fn is_empty(&self) -> bool {
+        self.profile.is_none() && self.blocklist.is_empty()
+    }
+
+    /// Drop the page at `file_path` if it is blocked, else process it; pages
+    /// that can't be decoded are kept as downloaded. Returns whether it was
+    /// dropped.
+    fn run(&self, page: usize, file_path: &Path) -> MgdlResult<bool> {
+        let file_name = file_path.file_name().unwrap_or_default();
+        if self.blocklist.matches(&fs::read(file_path)?) {
+            match self.filter {
+                FilterAction::Skip => fs::remove_file(file_path)?,
+                FilterAction::Quarantine => {
+                    fs::create_dir_all(&self.quarantine_path)?;
+                    fs::rename(file_path, self.quarantine_path.join(file_name))?;
+                }
+            }
+            return Ok(true);
+        }
+
+        if let Some(profile) = &self.profile {
+            let original = profile
+                .keep_original
+                .then(|| self.originals_path.join(file_name));
+            let chapter_path = file_path.parent().unwrap_or(Path::new(""));
+            let processed = process::process_page(
+                profile,
+                self.direction,
+                file_path,
+                |slice, ext| {
+                    chapter_path.join(match slice {
+                        Some(slice) => self.layout.slice_file_name(page, slice, ext),
+                        None => self.layout.page_file_name(page, ext),
+                    })
+                },
+                original.as_deref(),
+            );
+            match processed {
+                Err(err @ MgdlError::Process(_)) => self.logger.warn(format!(
+                    "Keeping {} as downloaded: {err}",
+                    file_path.display()
+                )),
+                result => result?,
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// How the pages of one chapter went.
+struct ChapterDownload {
+    number: String,
+    /// pages fetched, including failed and filtered ones
+    pages: usize,
+    /// pages already present
+    skipped: usize,
+    filtered: Vec<usize>,
+    failed: usize,
+    /// first error among the failed pages
+    error: Option<MgdlError>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::{LogMode, OutputFormat};
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
     };
-    entries
-        .filter_map(|e| e.ok())
-        .filter_map(|e| {
-            e.file_name()
-                .to_str()?
-                .split('.')
-                .next()?
-                .parse::<usize>()
-                .ok()
-        })
-        .collect()
+    use tempfile::TempDir;
+
+    const MANGA_PAGE: &str = include_str!("../tests/common/fixtures/manga_page.html");
+    const CHAPTER_LIST: &str = include_str!("../tests/common/fixtures/chapter_list.html");
+    const CHAPTER_PAGES: &str = include_str!("../tests/common/fixtures/chapter_pages.html");
+
+    /// Serve the site fixtures on a local port, returning its base URL.
+    fn serve_fixtures() -> String {
+        serve(CHAPTER_LIST)
+    }
+
+    /// Serve the site fixtures with `chapter_list` as every series' list.
+    fn serve(chapter_list: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let images = format!("{base_url}/img/");
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split_whitespace().nth(1).unwrap_or("");
+                let body = if path.ends_with("/full-chapter-list") {
+                    chapter_list.to_string()
+                } else if path.starts_with("/series/") {
+                    MANGA_PAGE.to_string()
+                } else if path.starts_with("/chapters/") {
+                    CHAPTER_PAGES.replace("https://official.lowee.us/", &images)
+                } else {
+                    String::new()
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+        base_url
+    }
+
+    fn config(dir: &TempDir, base_url: &str) -> Config {
+        let config = Config {
+            path: dir.path().join("config.toml"),
+            manga_dir: dir.path().join("manga"),
+            db_dir: dir.path().join("db"),
+            base_url: base_url.to_string(),
+            backup_retention: 10,
+            retry: scrape::Retry {
+                attempts: 1,
+                delay: scrape::INITIAL_DELAY,
+            },
+            page_requests: 16,
+            max_distance: filter::MAX_DISTANCE,
+            series: SeriesSettings::default(),
+            layout: Layout::default(),
+            settings: Vec::new(),
+        };
+        fs::create_dir_all(&config.db_dir).unwrap();
+        config
+    }
+
+    fn quiet() -> Arc<Logger> {
+        Arc::new(Logger::new(LogMode::Quiet, false, OutputFormat::Text))
+    }
+
+    fn downloader(dir: &TempDir, base_url: &str) -> Downloader {
+        Downloader::new(
+            config(dir, base_url),
+            quiet(),
+            reqwest::Client::new(),
+            #[cfg(feature = "bench")]
+            None,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn add_keeps_clear_of_existing_folders() {
+        let dir = TempDir::new().unwrap();
+        let base_url = serve_fixtures();
+        let dldr = downloader(&dir, &base_url);
+        let own_folder = dir.path().join("manga/tokyo_alien_bros");
+        fs::create_dir_all(&own_folder).unwrap();
+        fs::write(own_folder.join("notes.txt"), "mine").unwrap();
+
+        let (manga, _) = dldr.add(&format!("{base_url}/series/abc")).await.unwrap();
+        assert_eq!(manga.normalized_name, "tokyo_alien_bros_2");
+        assert!(Sidecar::read(&own_folder).unwrap().is_none());
+        assert!(dir
+            .path()
+            .join("manga/tokyo_alien_bros_2/mgdl.toml")
+            .is_file());
+    }
+
+    /// Every file under `dir` with its contents.
+    fn snapshot(dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir).unwrap().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                files.push((path.clone(), Vec::new()));
+                files.extend(snapshot(&path));
+            } else {
+                files.push((path.clone(), fs::read(&path).unwrap()));
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[tokio::test]
+    async fn dry_run_writes_nothing() {
+        let dir = TempDir::new().unwrap();
+        let base_url = serve_fixtures();
+        let dldr = downloader(&dir, &base_url);
+        dldr.add(&format!("{base_url}/series/abc")).await.unwrap();
+        // both would be written on opening with `new`
+        dldr.db.delete_setting(LAYOUT_SETTING).unwrap();
+        dldr.db.set_setting(db::LEGACY_NAMES_SETTING, "1").unwrap();
+        drop(dldr);
+        let before = snapshot(dir.path());
+
+        let planner = Downloader::planner(
+            config(&dir, &base_url),
+            quiet(),
+            reqwest::Client::new(),
+            #[cfg(feature = "bench")]
+            None,
+        )
+        .unwrap();
+        let plans = planner
+            .plan_download(&format!("{base_url}/series/abc"), None, false)
+            .await
+            .unwrap();
+        drop(planner);
+
+        assert_eq!(plans.len(), 1);
+        assert_eq!(snapshot(dir.path()), before);
+    }
+
+    #[tokio::test]
+    async fn bare_special_keys_get_their_tag() {
+        let dir = TempDir::new().unwrap();
+        let base_url = serve(
+            r#"<div><a href="/chapters/01K1G9HZJ3G4WFRGRR9HJ0DTFQ"><span>Chapter Extra</span></a></div>"#,
+        );
+        let dldr = downloader(&dir, &base_url);
+        let manga_url = format!("{base_url}/series/abc");
+        let (manga, _) = dldr.add(&manga_url).await.unwrap();
+        let manga_path = dir.path().join("manga").join(&manga.normalized_name);
+        // as an earlier version keyed a special no other one shared a name with
+        let old = manga_path.join(dldr.layout.chapter_dir(&manga, "special-extra", None));
+        fs::create_dir_all(&old).unwrap();
+        fs::write(old.join("001.jpg"), "page").unwrap();
+        let chapter = LocalChapter {
+            number: "special-extra".to_string(),
+            pages: 1,
+            volume: None,
+        };
+        dldr.db.upsert_chapter(&manga.hash, &chapter).unwrap();
+
+        dldr.add(&manga_url).await.unwrap();
+
+        let new = manga_path.join(
+            dldr.layout
+                .chapter_dir(&manga, "special-extra-j0dtfq", None),
+        );
+        assert!(!old.exists());
+        assert_eq!(fs::read(new.join("001.jpg")).unwrap(), b"page");
+        let numbers: Vec<String> = dldr
+            .db
+            .get_chapters(&manga.hash)
+            .unwrap()
+            .into_iter()
+            .map(|ch| ch.number)
+            .collect();
+        assert_eq!(numbers, vec!["special-extra-j0dtfq"]);
+    }
 }
@@ -50,15 +50,21 @@ macro_rules! define_errors {
 define_errors! {
     wrap Io(std::io::Error)             => "Io error",
     wrap Toml(toml::de::Error)          => "Toml error",
+    wrap TomlSer(toml::ser::Error)      => "Toml error",
+    wrap Json(serde_json::Error)        => "Json error",
     wrap Reqwest(reqwest::Error)        => "Reqwest error",
     wrap Rusqlite(rusqlite::Error)      => "Rusqlite error",
     wrap Parse(std::num::ParseIntError) => "Parse error",
-    wrap Join(tokio::task::JoinError)   => "Join error";
+    wrap Join(tokio::task::JoinError)   => "Join error",
+    wrap Zip(zip::result::ZipError)     => "Zip error",
+    wrap Image(image::ImageError)       => "Image error";
     cfg_wrap [cfg(feature = "bench")] Csv(csv::Error) => "CSV error";
     msg Config     => "Config error",
     msg Db         => "DB error",
     msg Scrape     => "Scrape error",
-    msg Logger     => "Logger error"
+    msg Logger     => "Logger error",
+    msg Export     => "Export error",
+    msg Process    => "Process error"
 }
 
 impl std::error::Error for MgdlError {}
@@ -0,0 +1,151 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{Read, Write},
+    path::Path,
+};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use super::{
+    bundle_title,
+    comic_info::{ComicInfo, COMIC_INFO_FILE},
+    ExportChapter,
+};
+use crate::{
+    error::MgdlResult,
+    layout::Layout,
+    models::{Manga, ReadingDirection},
+};
+
+/// Names of all entries in a CBZ, except its ComicInfo.xml.
+pub fn entry_names(cbz_path: &Path) -> MgdlResult<Vec<String>> {
+    let archive = ZipArchive::new(File::open(cbz_path)?)?;
+    Ok(archive
+        .file_names()
+        .filter(|name| *name != COMIC_INFO_FILE)
+        .map(str::to_string)
+        .collect())
+}
+
+/// All entries of a CBZ except its ComicInfo.xml, as (name, bytes).
+pub fn read_pages(cbz_path: &Path) -> MgdlResult<Vec<(String, Vec<u8>)>> {
+    let mut archive = ZipArchive::new(File::open(cbz_path)?)?;
+    let mut pages = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() || entry.name() == COMIC_INFO_FILE {
+            continue;
+        }
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut bytes)?;
+        pages.push((entry.name().to_string(), bytes));
+    }
+    Ok(pages)
+}
+
+/// Pack the images in `chapter_path` into `cbz_path` with an embedded
+/// ComicInfo.xml, then remove the folder. Pages already in an existing
+/// archive are kept, so a chapter can be completed after it was packed.
+pub fn pack_chapter(chapter_path: &Path, cbz_path: &Path, mut info: ComicInfo) -> MgdlResult<()> {
+    let mut pages = BTreeMap::new();
+
+    if cbz_path.is_file() {
+        pages.extend(read_pages(cbz_path)?);
+    }
+
+    for entry in fs::read_dir(chapter_path)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        // the folder's ComicInfo.xml is replaced by `info`
+        if path.is_file() && name != COMIC_INFO_FILE {
+            pages.insert(name.to_string(), fs::read(&path)?);
+        }
+    }
+
+    info.page_count = pages.len();
+    let part_path = cbz_path.with_extension("cbz.part");
+    let mut zip = ZipWriter::new(File::create(&part_path)?);
+    // images are already compressed, so store everything as-is
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    zip.start_file(COMIC_INFO_FILE, options)?;
+    zip.write_all(info.to_xml().as_bytes())?;
+    for (name, bytes) in pages {
+        zip.start_file(name, options)?;
+        zip.write_all(&bytes)?;
+    }
+    zip.finish()?;
+
+    fs::rename(&part_path, cbz_path)?;
+    fs::remove_dir_all(chapter_path)?;
+    Ok(())
+}
+
+/// Rename the page entries of a CBZ with `rename`, keeping its
+/// ComicInfo.xml as-is.
+pub fn rename_pages(cbz_path: &Path, rename: impl Fn(&str) -> String) -> MgdlResult<()> {
+    let mut archive = ZipArchive::new(File::open(cbz_path)?)?;
+    let mut comic_info = None;
+    if let Ok(mut entry) = archive.by_name(COMIC_INFO_FILE) {
+        let mut xml = Vec::new();
+        entry.read_to_end(&mut xml)?;
+        comic_info = Some(xml);
+    }
+    let pages = read_pages(cbz_path)?;
+
+    let part_path = cbz_path.with_extension("cbz.part");
+    let mut zip = ZipWriter::new(File::create(&part_path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    if let Some(xml) = comic_info {
+        zip.start_file(COMIC_INFO_FILE, options)?;
+        zip.write_all(&xml)?;
+    }
+    for (name, bytes) in pages {
+        zip.start_file(rename(&name), options)?;
+        zip.write_all(&bytes)?;
+    }
+    zip.finish()?;
+    fs::rename(&part_path, cbz_path)?;
+    Ok(())
+}
+
+/// Write `chapters` into a single CBZ with one chapter folder each, e.g. for
+/// a volume.
+pub fn write_bundle(
+    layout: &Layout,
+    path: &Path,
+    manga: &Manga,
+    chapters: &[ExportChapter],
+    direction: ReadingDirection,
+) -> MgdlResult<()> {
+    let volume = chapters.first().and_then(|ch| ch.volume);
+    let volume = volume.filter(|v| chapters.iter().all(|ch| ch.volume == Some(*v)));
+    let info = ComicInfo {
+        title: bundle_title(&manga.name, chapters),
+        series: manga.name.clone(),
+        number: volume.map(|v| v.to_string()).unwrap_or_default(),
+        volume,
+        writer: manga.authors.clone(),
+        notes: format!("Status: {}", manga.status),
+        release_date: None,
+        page_count: chapters.iter().map(|ch| ch.pages.len()).sum(),
+        right_to_left: direction == ReadingDirection::Rtl,
+    };
+
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    zip.start_file(COMIC_INFO_FILE, options)?;
+    zip.write_all(info.to_xml().as_bytes())?;
+    for chapter in chapters {
+        let dir = layout.chapter_dir(manga, &chapter.number, chapter.volume);
+        let dir = dir.to_string_lossy().replace('\\', "/");
+        for page in &chapter.pages {
+            zip.start_file(format!("{dir}/{}", page.name), options)?;
+            zip.write_all(&page.bytes)?;
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use std::{fs, path::Path};
+
+use crate::{
+    error::MgdlResult,
+    models::{Chapter, ChapterNumber, Manga},
+};
+
+pub const COMIC_INFO_FILE: &str = "ComicInfo.xml";
+
+/// The subset of the ComicInfo.xml schema read by comic servers and readers.
+pub struct ComicInfo {
+    pub title: String,
+    pub series: String,
+    pub number: String,
+    pub volume: Option<u32>,
+    pub writer: String,
+    pub notes: String,
+    pub release_date: Option<String>,
+    pub page_count: usize,
+    pub right_to_left: bool,
+}
+
+impl ComicInfo {
+    pub fn new(manga: &Manga, chapter: &Chapter, page_count: usize) -> Self {
+        let number = display_number(&chapter.number);
+        Self {
+            title: format!("Chapter {number}"),
+            series: manga.name.clone(),
+            number,
+            volume: chapter.volume,
+            writer: manga.authors.clone(),
+            notes: format!("Status: {}", manga.status),
+            release_date: chapter.released.clone(),
+            page_count,
+            right_to_left: false,
+        }
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <ComicInfo xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" \
+             xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n",
+        );
+        push_element(&mut xml, "Title", &self.title);
+        push_element(&mut xml, "Series", &self.series);
+        push_element(&mut xml, "Number", &self.number);
+        if let Some(volume) = self.volume {
+            push_element(&mut xml, "Volume", &volume.to_string());
+        }
+        push_element(&mut xml, "Writer", &self.writer);
+        push_element(&mut xml, "Notes", &self.notes);
+        if let Some((year, month, day)) = self.release_date.as_deref().and_then(split_date) {
+            push_element(&mut xml, "Year", year);
+            push_element(&mut xml, "Month", month);
+            push_element(&mut xml, "Day", day);
+        }
+        push_element(&mut xml, "PageCount", &self.page_count.to_string());
+        if self.right_to_left {
+            push_element(&mut xml, "Manga", "YesAndRightToLeft");
+        }
+        xml.push_str("</ComicInfo>\n");
+        xml
+    }
+
+    /// Write beside the pages of a chapter kept as a folder.
+    pub fn write(&self, chapter_path: &Path) -> MgdlResult<()> {
+        fs::write(chapter_path.join(COMIC_INFO_FILE), self.to_xml())?;
+        Ok(())
+    }
+}
+
+/// `0010` -> `10`, `0005.5` -> `5.5`, `special-extra` -> `extra`. Numbers
+/// that don't parse are shown as stored.
+pub fn display_number(number: &str) -> String {
+    ChapterNumber::from_key(number).map_or_else(|| number.to_string(), |n| n.to_string())
+}
+
+fn split_date(date: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = date.get(..10)?.split('-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    Some((
+        year,
+        month.trim_start_matches('0'),
+        day.trim_start_matches('0'),
+    ))
+}
+
+fn push_element(xml: &mut String, name: &str, value: &str) {
+    if value.is_empty() {
+        return;
+    }
+    xml.push_str(&format!("  <{name}>{}</{name}>\n", escape_xml(value)));
+}
+
+pub fn escape_xml(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_number_whole_and_decimal() {
+        assert_eq!(display_number("0010"), "10");
+        assert_eq!(display_number("0005.5"), "5.5");
+        assert_eq!(display_number("0000"), "0");
+        assert_eq!(display_number("0010a"), "10a");
+        assert_eq!(display_number("special-extra"), "extra");
+        // stored before the current numbering
+        assert_eq!(display_number("0005-05"), "5.5");
+    }
+
+    #[test]
+    fn split_date_from_iso_timestamp() {
+        assert_eq!(
+            split_date("2025-07-31T13:08:16.067Z"),
+            Some(("2025", "7", "31"))
+        );
+        assert_eq!(split_date("bad"), None);
+    }
+
+    #[test]
+    fn xml_is_escaped() {
+        let info = ComicInfo {
+            title: "Chapter 1".to_string(),
+            series: "Tom & Jerry <3".to_string(),
+            number: "1".to_string(),
+            volume: Some(2),
+            writer: String::new(),
+            notes: String::new(),
+            release_date: Some("2025-01-02".to_string()),
+            page_count: 3,
+            right_to_left: true,
+        };
+        let xml = info.to_xml();
+        assert!(xml.contains("<Series>Tom &amp; Jerry &lt;3</Series>"));
+        assert!(xml.contains("<Month>1</Month>"));
+        assert!(xml.contains("<Volume>2</Volume>"));
+        assert!(xml.contains("<Manga>YesAndRightToLeft</Manga>"));
+        assert!(!xml.contains("<Writer>"));
+    }
+}
@@ -0,0 +1,247 @@
+use std::{fs::File, io::Write, path::Path};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use super::{
+    bundle_label, bundle_title,
+    comic_info::{display_number, escape_xml},
+    ExportChapter, ImageInfo,
+};
+use crate::{
+    error::{MgdlError, MgdlResult},
+    library::PageImage,
+    models::{Manga, ReadingDirection},
+    utils::UtcDateTime,
+};
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// One image in the book and the fixed-layout page that shows it.
+struct BookPage {
+    id: String,
+    image_href: String,
+    page_href: String,
+    info: ImageInfo,
+}
+
+impl BookPage {
+    fn new(id: String, page: &PageImage) -> MgdlResult<Self> {
+        let info = ImageInfo::read(&page.bytes)?;
+        Ok(Self {
+            image_href: format!("images/{id}.{}", info.extension()),
+            page_href: format!("pages/{id}.xhtml"),
+            id,
+            info,
+        })
+    }
+
+    fn xhtml(&self, title: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head>
+  <title>{title}</title>
+  <meta name="viewport" content="width={w}, height={h}"/>
+  <style>html, body {{ margin: 0; padding: 0; }} img {{ display: block; width: 100%; height: 100%; }}</style>
+</head>
+<body><img src="../{src}" alt="{title}"/></body>
+</html>
+"#,
+            title = escape_xml(title),
+            w = self.info.width,
+            h = self.info.height,
+            src = self.image_href,
+        )
+    }
+}
+
+/// Write `chapters` as a fixed-layout EPUB 3 with one page per image, paged
+/// in `direction`. The cover is `cover` when given, else the first page.
+pub fn write_epub(
+    path: &Path,
+    manga: &Manga,
+    chapters: &[ExportChapter],
+    cover: Option<&PageImage>,
+    direction: ReadingDirection,
+) -> MgdlResult<()> {
+    if chapters.is_empty() {
+        return Err(MgdlError::Export("No chapters to export".to_string()));
+    }
+    let title = bundle_title(&manga.name, chapters);
+
+    let mut zip = ZipWriter::new(File::create(path)?);
+    // readers expect the uncompressed mimetype entry first
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    zip.start_file("mimetype", options)?;
+    zip.write_all(b"application/epub+zip")?;
+    zip.start_file("META-INF/container.xml", options)?;
+    zip.write_all(CONTAINER_XML.as_bytes())?;
+
+    let mut spine = Vec::new();
+    let mut nav_entries = Vec::new();
+    let mut cover_id = None;
+
+    if let Some(cover) = cover {
+        let page = BookPage::new("cover".to_string(), cover)?;
+        write_page(&mut zip, options, &page, cover, &manga.name)?;
+        cover_id = Some(page.id.clone());
+        spine.push(page);
+    }
+
+    for (ci, chapter) in chapters.iter().enumerate() {
+        let chapter_title = format!("Chapter {}", display_number(&chapter.number));
+        for (pi, image) in chapter.pages.iter().enumerate() {
+            let page = BookPage::new(format!("c{:04}_p{:04}", ci + 1, pi + 1), image)?;
+            let page_title = format!("{} - Page {}", chapter_title, pi + 1);
+            write_page(&mut zip, options, &page, image, &page_title)?;
+            if pi == 0 {
+                nav_entries.push((page.page_href.clone(), chapter_title.clone()));
+            }
+            cover_id.get_or_insert_with(|| page.id.clone());
+            spine.push(page);
+        }
+    }
+
+    zip.start_file("OEBPS/nav.xhtml", options)?;
+    zip.write_all(nav_xhtml(&title, &nav_entries).as_bytes())?;
+    zip.start_file("OEBPS/content.opf", options)?;
+    zip.write_all(
+        content_opf(
+            manga,
+            &title,
+            &bundle_label(chapters),
+            &spine,
+            cover_id.as_deref(),
+            direction,
+        )
+        .as_bytes(),
+    )?;
+    zip.finish()?;
+    Ok(())
+}
+
+fn write_page(
+    zip: &mut ZipWriter<File>,
+    options: SimpleFileOptions,
+    page: &BookPage,
+    image: &PageImage,
+    title: &str,
+) -> MgdlResult<()> {
+    zip.start_file(format!("OEBPS/{}", page.image_href), options)?;
+    zip.write_all(&image.bytes)?;
+    zip.start_file(format!("OEBPS/{}", page.page_href), options)?;
+    zip.write_all(page.xhtml(title).as_bytes())?;
+    Ok(())
+}
+
+fn nav_xhtml(title: &str, entries: &[(String, String)]) -> String {
+    let items: String = entries
+        .iter()
+        .map(|(href, label)| {
+            format!(
+                "      <li><a href=\"{}\">{}</a></li>\n",
+                href,
+                escape_xml(label)
+            )
+        })
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>{title}</title></head>
+<body>
+  <nav epub:type="toc" id="toc">
+    <h1>{title}</h1>
+    <ol>
+{items}    </ol>
+  </nav>
+</body>
+</html>
+"#,
+        title = escape_xml(title),
+    )
+}
+
+fn content_opf(
+    manga: &Manga,
+    title: &str,
+    label: &str,
+    spine: &[BookPage],
+    cover_id: Option<&str>,
+    direction: ReadingDirection,
+) -> String {
+    let mut metadata = format!(
+        "    <dc:identifier id=\"book-id\">urn:mgdl:{}:{}</dc:identifier>\n\
+         \x20   <dc:title>{}</dc:title>\n\
+         \x20   <dc:language>und</dc:language>\n",
+        escape_xml(&manga.hash),
+        escape_xml(&label.replace(' ', "-")),
+        escape_xml(title),
+    );
+    for author in manga.authors.split(", ").filter(|a| !a.is_empty()) {
+        metadata.push_str(&format!(
+            "    <dc:creator>{}</dc:creator>\n",
+            escape_xml(author)
+        ));
+    }
+    if !manga.status.is_empty() {
+        metadata.push_str(&format!(
+            "    <dc:description>Status: {}</dc:description>\n",
+            escape_xml(&manga.status)
+        ));
+    }
+    metadata.push_str(&format!(
+        "    <meta property=\"dcterms:modified\">{}</meta>\n\
+         \x20   <meta property=\"rendition:layout\">pre-paginated</meta>\n\
+         \x20   <meta property=\"rendition:orientation\">auto</meta>\n\
+         \x20   <meta property=\"rendition:spread\">none</meta>\n",
+        UtcDateTime::now().to_iso8601()
+    ));
+    if let Some(cover_id) = cover_id {
+        metadata.push_str(&format!(
+            "    <meta name=\"cover\" content=\"img_{cover_id}\"/>\n"
+        ));
+    }
+
+    let mut manifest = String::from(
+        "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+    );
+    let mut itemrefs = String::new();
+    for page in spine {
+        let properties = if Some(page.id.as_str()) == cover_id {
+            " properties=\"cover-image\""
+        } else {
+            ""
+        };
+        manifest.push_str(&format!(
+            "    <item id=\"img_{id}\" href=\"{href}\" media-type=\"{mime}\"{properties}/>\n\
+             \x20   <item id=\"page_{id}\" href=\"{page}\" media-type=\"application/xhtml+xml\"/>\n",
+            id = page.id,
+            href = page.image_href,
+            mime = page.info.format.to_mime_type(),
+            page = page.page_href,
+        ));
+        itemrefs.push_str(&format!("    <itemref idref=\"page_{}\"/>\n", page.id));
+    }
+
+    let direction = direction.as_str();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" prefix="rendition: http://www.idpf.org/vocab/rendition/#">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+{metadata}  </metadata>
+  <manifest>
+{manifest}  </manifest>
+  <spine page-progression-direction="{direction}">
+{itemrefs}  </spine>
+</package>
+"#
+    )
+}
@@ -0,0 +1,22 @@
+use std::{fs, path::Path};
+
+use super::ExportChapter;
+use crate::{error::MgdlResult, layout::Layout, models::Manga};
+
+/// Copy `chapters` into `path`, one chapter folder each as named by the
+/// layout.
+pub fn write_folder(
+    layout: &Layout,
+    path: &Path,
+    manga: &Manga,
+    chapters: &[ExportChapter],
+) -> MgdlResult<()> {
+    for chapter in chapters {
+        let chapter_path = path.join(layout.chapter_dir(manga, &chapter.number, chapter.volume));
+        fs::create_dir_all(&chapter_path)?;
+        for page in &chapter.pages {
+            fs::write(chapter_path.join(&page.name), &page.bytes)?;
+        }
+    }
+    Ok(())
+}
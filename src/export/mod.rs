@@ -0,0 +1,211 @@
+pub mod cbz;
+pub mod comic_info;
+pub mod epub;
+pub mod folder;
+pub mod pdf;
+pub mod series_json;
+
+use clap::ValueEnum;
+use image::{ImageFormat, ImageReader};
+use serde::Deserialize;
+use std::{collections::BTreeMap, io::Cursor, path::Path};
+
+use crate::{
+    error::{MgdlError, MgdlResult},
+    layout::Layout,
+    library::{self, PageImage, VolumeMap},
+    models::{major_number, ChapterNumber, ChapterRange, LocalChapter},
+    utils::sanitize_file_name,
+};
+
+/// How finished chapters are stored under the series folder.
+#[derive(Clone, Copy, ValueEnum, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PackMode {
+    /// `chapter_NNNN-NN/NNN.ext` image folders
+    #[default]
+    Folder,
+    /// one `Series - Ch NNNN.cbz` archive per chapter
+    Cbz,
+}
+
+/// File formats a range of downloaded chapters can be exported to.
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// fixed-layout EPUB 3
+    Epub,
+    /// PDF with one page per image
+    Pdf,
+    /// CBZ with one folder per chapter
+    Cbz,
+    /// plain folder with one subfolder per chapter
+    Folder,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            ExportFormat::Epub => Some("epub"),
+            ExportFormat::Pdf => Some("pdf"),
+            ExportFormat::Cbz => Some("cbz"),
+            ExportFormat::Folder => None,
+        }
+    }
+}
+
+/// A downloaded chapter with its page images loaded.
+pub struct ExportChapter {
+    pub number: String,
+    pub volume: Option<u32>,
+    pub pages: Vec<PageImage>,
+}
+
+/// Load the local chapters of a series that fall in `range`.
+pub fn load_chapters(
+    layout: &Layout,
+    manga_path: &Path,
+    range: Option<&ChapterRange>,
+) -> MgdlResult<Vec<ExportChapter>> {
+    let dirs = layout.find_chapter_dirs(manga_path);
+    let cbz_files = layout.find_cbz_files(manga_path);
+    let chapters = library::local_chapters(layout, manga_path)?;
+    let chapters = match range {
+        Some(range) => range.select(chapters),
+        None => chapters,
+    };
+    chapters
+        .into_iter()
+        .map(|ch| {
+            let chapter_path = dirs.get(&ch.number).map(|d| d.path.clone());
+            let cbz_path = cbz_files.get(&ch.number).map(|c| c.path.as_path());
+            Ok(ExportChapter {
+                pages: library::chapter_pages(layout, &chapter_path.unwrap_or_default(), cbz_path)?,
+                number: ch.number,
+                volume: ch.volume,
+            })
+        })
+        .collect()
+}
+
+/// Set each chapter's volume from `volumes.toml`, falling back to the
+/// volume recorded in the DB.
+pub fn assign_volumes(
+    chapters: &mut [ExportChapter],
+    volumes: &VolumeMap,
+    stored: &[LocalChapter],
+) {
+    for chapter in chapters {
+        let major = major_number(&chapter.number);
+        chapter.volume = major.and_then(|n| volumes.volume_of(n)).or_else(|| {
+            stored
+                .iter()
+                .find(|ch| ch.number == chapter.number)
+                .and_then(|ch| ch.volume)
+        });
+    }
+}
+
+/// Split chapters into one bundle per volume, in volume order. Chapters
+/// without a volume go into a last bundle of their own.
+pub fn group_by_volume(chapters: Vec<ExportChapter>) -> Vec<Vec<ExportChapter>> {
+    let mut volumes: BTreeMap<u32, Vec<ExportChapter>> = BTreeMap::new();
+    let mut loose = Vec::new();
+    for chapter in chapters {
+        match chapter.volume {
+            Some(volume) => volumes.entry(volume).or_default().push(chapter),
+            None => loose.push(chapter),
+        }
+    }
+    let mut bundles: Vec<_> = volumes.into_values().collect();
+    if !loose.is_empty() {
+        bundles.push(loose);
+    }
+    bundles
+}
+
+/// The volume shared by every chapter of a bundle, if any.
+fn bundle_volume(chapters: &[ExportChapter]) -> Option<u32> {
+    let volume = chapters.first()?.volume?;
+    chapters
+        .iter()
+        .all(|ch| ch.volume == Some(volume))
+        .then_some(volume)
+}
+
+/// `Volume 01` for a single volume, `Ch 0001-0010` otherwise.
+pub fn bundle_label(chapters: &[ExportChapter]) -> String {
+    if let Some(volume) = bundle_volume(chapters) {
+        return format!("Volume {volume:02}");
+    }
+    let major = |ch: &ExportChapter| match ChapterNumber::from_key(&ch.number) {
+        Some(number) => number
+            .major()
+            .map_or(number.to_string(), |major| format!("{major:04}")),
+        None => ch.number.clone(),
+    };
+    let first = chapters.first().map(major).unwrap_or_default();
+    let last = chapters.last().map(major).unwrap_or_default();
+    if first == last {
+        format!("Ch {first}")
+    } else {
+        format!("Ch {first}-{last}")
+    }
+}
+
+/// `Series Vol. 1` for a single volume, `Series (Ch. 1-10)` otherwise.
+pub fn bundle_title(manga_name: &str, chapters: &[ExportChapter]) -> String {
+    if let Some(volume) = bundle_volume(chapters) {
+        return format!("{manga_name} Vol. {volume}");
+    }
+    let first = chapters
+        .first()
+        .map(|ch| comic_info::display_number(&ch.number));
+    let last = chapters
+        .last()
+        .map(|ch| comic_info::display_number(&ch.number));
+    match (first, last) {
+        (Some(first), Some(last)) if first != last => format!("{manga_name} (Ch. {first}-{last})"),
+        (Some(first), _) => format!("{manga_name} (Ch. {first})"),
+        _ => manga_name.to_string(),
+    }
+}
+
+/// `Series - Ch 0001-0010.epub`, `Series - Volume 01.cbz`, or without an
+/// extension for folders.
+pub fn export_file_name(title: &str, chapters: &[ExportChapter], format: ExportFormat) -> String {
+    let name = format!("{} - {}", sanitize_file_name(title), bundle_label(chapters));
+    match format.extension() {
+        Some(extension) => format!("{name}.{extension}"),
+        None => name,
+    }
+}
+
+/// Format and pixel size of an encoded page image.
+pub struct ImageInfo {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageInfo {
+    pub fn read(bytes: &[u8]) -> MgdlResult<Self> {
+        let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+        let format = reader
+            .format()
+            .ok_or_else(|| MgdlError::Export("Unrecognized image format".to_string()))?;
+        let (width, height) = reader.into_dimensions()?;
+        Ok(Self {
+            format,
+            width,
+            height,
+        })
+    }
+
+    pub fn extension(&self) -> &'static str {
+        self.format
+            .extensions_str()
+            .first()
+            .copied()
+            .unwrap_or("img")
+    }
+}
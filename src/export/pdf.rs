@@ -0,0 +1,375 @@
+use flate2::{write::ZlibEncoder, Compression};
+use image::{DynamicImage, ImageFormat};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use super::{bundle_title, comic_info::display_number, ExportChapter, ImageInfo};
+use crate::{
+    error::{MgdlError, MgdlResult},
+    library::PageImage,
+    models::{Manga, ReadingDirection},
+    utils::UtcDateTime,
+};
+
+const CATALOG_ID: usize = 1;
+const PAGES_ID: usize = 2;
+const OUTLINES_ID: usize = 3;
+const INFO_ID: usize = 4;
+
+/// Minimal PDF object writer that records byte offsets for the xref table.
+struct PdfWriter<W: Write> {
+    out: W,
+    written: usize,
+    offsets: Vec<usize>,
+}
+
+impl<W: Write> PdfWriter<W> {
+    fn new(out: W) -> MgdlResult<Self> {
+        let mut writer = Self {
+            out,
+            written: 0,
+            offsets: vec![0; INFO_ID + 1],
+        };
+        // the binary comment marks the file as 8-bit for transfer tools
+        writer.write(b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n")?;
+        Ok(writer)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> MgdlResult<()> {
+        self.out.write_all(bytes)?;
+        self.written += bytes.len();
+        Ok(())
+    }
+
+    fn reserve(&mut self) -> usize {
+        self.offsets.push(0);
+        self.offsets.len() - 1
+    }
+
+    fn object(&mut self, id: usize, dict: &str) -> MgdlResult<()> {
+        self.offsets[id] = self.written;
+        self.write(format!("{id} 0 obj\n{dict}\nendobj\n").as_bytes())
+    }
+
+    /// Stream object; `entries` are the dictionary entries besides `/Length`.
+    fn stream(&mut self, id: usize, entries: &str, data: &[u8]) -> MgdlResult<()> {
+        self.offsets[id] = self.written;
+        let header = format!(
+            "{id} 0 obj\n<< {entries} /Length {} >>\nstream\n",
+            data.len()
+        );
+        self.write(header.as_bytes())?;
+        self.write(data)?;
+        self.write(b"\nendstream\nendobj\n")
+    }
+
+    fn finish(mut self) -> MgdlResult<W> {
+        let xref = self.written;
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len());
+        for offset in &self.offsets[1..] {
+            table.push_str(&format!("{offset:010} 00000 n \n"));
+        }
+        table.push_str(&format!(
+            "trailer\n<< /Size {} /Root {CATALOG_ID} 0 R /Info {INFO_ID} 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            self.offsets.len()
+        ));
+        self.write(table.as_bytes())?;
+        Ok(self.out)
+    }
+}
+
+/// A page image ready to embed: JPEGs are passed through as-is, everything
+/// else is stored as raw samples at their own bit depth, so no quality is
+/// lost.
+struct PdfImage {
+    width: u32,
+    height: u32,
+    color_space: &'static str,
+    bits: u8,
+    filter: &'static str,
+    /// `/Decode` array, for Adobe CMYK JPEGs that store inverted samples.
+    decode: Option<&'static str>,
+    data: Vec<u8>,
+    alpha: Option<Vec<u8>>,
+}
+
+impl PdfImage {
+    fn new(page: &PageImage) -> MgdlResult<Self> {
+        let info = ImageInfo::read(&page.bytes)?;
+        if info.format == ImageFormat::Jpeg {
+            let (components, adobe) = jpeg_components(&page.bytes);
+            let (color_space, decode) = match components {
+                1 => ("/DeviceGray", None),
+                4 if adobe => ("/DeviceCMYK", Some("[1 0 1 0 1 0 1 0]")),
+                4 => ("/DeviceCMYK", None),
+                _ => ("/DeviceRGB", None),
+            };
+            return Ok(Self {
+                width: info.width,
+                height: info.height,
+                color_space,
+                bits: 8,
+                filter: "/DCTDecode",
+                decode,
+                data: page.bytes.clone(),
+                alpha: None,
+            });
+        }
+
+        let image = image::load_from_memory(&page.bytes)?;
+        let (color_space, bits, samples, alpha) = match image {
+            DynamicImage::ImageLuma8(gray) => ("/DeviceGray", 8, gray.into_raw(), None),
+            DynamicImage::ImageLumaA8(gray) => {
+                let (samples, alpha) = split_alpha(gray.as_raw(), 1);
+                ("/DeviceGray", 8, samples, Some(alpha))
+            }
+            DynamicImage::ImageLuma16(gray) => ("/DeviceGray", 16, big_endian(gray.as_raw()), None),
+            DynamicImage::ImageLumaA16(gray) => {
+                let (samples, alpha) = split_alpha(gray.as_raw(), 1);
+                (
+                    "/DeviceGray",
+                    16,
+                    big_endian(&samples),
+                    Some(big_endian(&alpha)),
+                )
+            }
+            DynamicImage::ImageRgb16(rgb) => ("/DeviceRGB", 16, big_endian(rgb.as_raw()), None),
+            DynamicImage::ImageRgba16(rgba) => {
+                let (samples, alpha) = split_alpha(rgba.as_raw(), 3);
+                (
+                    "/DeviceRGB",
+                    16,
+                    big_endian(&samples),
+                    Some(big_endian(&alpha)),
+                )
+            }
+            image if image.color().has_alpha() => {
+                let (samples, alpha) = split_alpha(image.to_rgba8().as_raw(), 3);
+                ("/DeviceRGB", 8, samples, Some(alpha))
+            }
+            image => ("/DeviceRGB", 8, image.to_rgb8().into_raw(), None),
+        };
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            color_space,
+            bits,
+            filter: "/FlateDecode",
+            decode: None,
+            data: deflate(&samples)?,
+            alpha: alpha.map(|alpha| deflate(&alpha)).transpose()?,
+        })
+    }
+}
+
+/// Component count from a JPEG's frame header, and whether it carries an
+/// Adobe APP14 marker. Adobe CMYK JPEGs store their samples inverted.
+fn jpeg_components(bytes: &[u8]) -> (u8, bool) {
+    let mut adobe = false;
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xff {
+            break;
+        }
+        let marker = bytes[pos + 1];
+        if marker == 0xff {
+            pos += 1;
+            continue;
+        }
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let segment = &bytes[pos + 4..(pos + 2 + len).min(bytes.len())];
+        match marker {
+            0xee if segment.starts_with(b"Adobe") => adobe = true,
+            // SOF0..SOF15, minus DHT (c4), JPG (c8) and DAC (cc)
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                return (segment.get(5).copied().unwrap_or(3), adobe);
+            }
+            0xda => break,
+            _ => {}
+        }
+        pos += 2 + len;
+    }
+    (3, adobe)
+}
+
+/// Split interleaved samples with a trailing alpha channel into color and
+/// alpha planes.
+fn split_alpha<T: Copy>(samples: &[T], channels: usize) -> (Vec<T>, Vec<T>) {
+    let mut color = Vec::with_capacity(samples.len() / (channels + 1) * channels);
+    let mut alpha = Vec::with_capacity(samples.len() / (channels + 1));
+    for pixel in samples.chunks_exact(channels + 1) {
+        color.extend_from_slice(&pixel[..channels]);
+        alpha.push(pixel[channels]);
+    }
+    (color, alpha)
+}
+
+/// 16-bit samples in the big-endian byte order PDF expects.
+fn big_endian(samples: &[u16]) -> Vec<u8> {
+    samples.iter().flat_map(|s| s.to_be_bytes()).collect()
+}
+
+fn deflate(data: &[u8]) -> MgdlResult<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// PDF text string: a literal for plain ASCII, UTF-16BE hex otherwise.
+fn pdf_text(text: &str) -> String {
+    if text.bytes().all(|b| (0x20..0x7f).contains(&b)) {
+        let escaped = text
+            .replace('\\', "\\\\")
+            .replace('(', "\\(")
+            .replace(')', "\\)");
+        return format!("({escaped})");
+    }
+    let hex: String = text
+        .encode_utf16()
+        .map(|unit| format!("{unit:04X}"))
+        .collect();
+    format!("<FEFF{hex}>")
+}
+
+fn pdf_date(date: &UtcDateTime) -> String {
+    format!(
+        "(D:{:04}{:02}{:02}{:02}{:02}{:02}Z)",
+        date.year, date.month, date.day, date.hour, date.minute, date.second
+    )
+}
+
+/// Write `chapters` as a PDF with one page per image, each page sized to
+/// its image, and an outline entry per chapter.
+pub fn write_pdf(
+    path: &Path,
+    manga: &Manga,
+    chapters: &[ExportChapter],
+    direction: ReadingDirection,
+) -> MgdlResult<()> {
+    if chapters.iter().all(|ch| ch.pages.is_empty()) {
+        return Err(MgdlError::Export("No pages to export".to_string()));
+    }
+
+    let mut pdf = PdfWriter::new(BufWriter::new(File::create(path)?))?;
+    let mut page_ids = Vec::new();
+    let mut chapter_starts = Vec::new();
+
+    for chapter in chapters {
+        for (i, page) in chapter.pages.iter().enumerate() {
+            let image = PdfImage::new(page)?;
+            let (page_id, content_id, image_id) = (pdf.reserve(), pdf.reserve(), pdf.reserve());
+
+            let smask = match &image.alpha {
+                Some(alpha) => {
+                    let smask_id = pdf.reserve();
+                    pdf.stream(
+                        smask_id,
+                        &format!(
+                            "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceGray /BitsPerComponent {} /Filter /FlateDecode",
+                            image.width, image.height, image.bits
+                        ),
+                        alpha,
+                    )?;
+                    format!(" /SMask {smask_id} 0 R")
+                }
+                None => String::new(),
+            };
+            let decode = image
+                .decode
+                .map(|decode| format!(" /Decode {decode}"))
+                .unwrap_or_default();
+            pdf.stream(
+                image_id,
+                &format!(
+                    "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} /BitsPerComponent {} /Filter {}{decode}{smask}",
+                    image.width, image.height, image.color_space, image.bits, image.filter
+                ),
+                &image.data,
+            )?;
+            pdf.stream(
+                content_id,
+                "",
+                format!("q {} 0 0 {} 0 0 cm /Im0 Do Q", image.width, image.height).as_bytes(),
+            )?;
+            pdf.object(
+                page_id,
+                &format!(
+                    "<< /Type /Page /Parent {PAGES_ID} 0 R /MediaBox [0 0 {} {}] /Resources << /XObject << /Im0 {image_id} 0 R >> >> /Contents {content_id} 0 R >>",
+                    image.width, image.height
+                ),
+            )?;
+
+            if i == 0 {
+                chapter_starts.push((chapter.number.as_str(), page_id));
+            }
+            page_ids.push(page_id);
+        }
+    }
+
+    let outline_ids: Vec<usize> = chapter_starts.iter().map(|_| pdf.reserve()).collect();
+    for (i, (number, page_id)) in chapter_starts.iter().enumerate() {
+        let mut dict = format!(
+            "<< /Title {} /Parent {OUTLINES_ID} 0 R /Dest [{page_id} 0 R /Fit]",
+            pdf_text(&format!("Chapter {}", display_number(number)))
+        );
+        if i > 0 {
+            dict.push_str(&format!(" /Prev {} 0 R", outline_ids[i - 1]));
+        }
+        if let Some(next) = outline_ids.get(i + 1) {
+            dict.push_str(&format!(" /Next {next} 0 R"));
+        }
+        dict.push_str(" >>");
+        pdf.object(outline_ids[i], &dict)?;
+    }
+    let outlines = match (outline_ids.first(), outline_ids.last()) {
+        (Some(first), Some(last)) => format!(
+            "<< /Type /Outlines /First {first} 0 R /Last {last} 0 R /Count {} >>",
+            outline_ids.len()
+        ),
+        _ => "<< /Type /Outlines /Count 0 >>".to_string(),
+    };
+    pdf.object(OUTLINES_ID, &outlines)?;
+
+    let kids: Vec<String> = page_ids.iter().map(|id| format!("{id} 0 R")).collect();
+    pdf.object(
+        PAGES_ID,
+        &format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            page_ids.len()
+        ),
+    )?;
+    let preferences = match direction {
+        ReadingDirection::Rtl => " /ViewerPreferences << /Direction /R2L >>",
+        ReadingDirection::Ltr => "",
+    };
+    pdf.object(
+        CATALOG_ID,
+        &format!(
+            "<< /Type /Catalog /Pages {PAGES_ID} 0 R /Outlines {OUTLINES_ID} 0 R /PageMode /UseOutlines{preferences} >>"
+        ),
+    )?;
+
+    let mut info = format!(
+        "<< /Title {} /Creator (mgdl) /Producer (mgdl) /CreationDate {}",
+        pdf_text(&bundle_title(&manga.name, chapters)),
+        pdf_date(&UtcDateTime::now())
+    );
+    if !manga.authors.is_empty() {
+        info.push_str(&format!(" /Author {}", pdf_text(&manga.authors)));
+    }
+    if !manga.status.is_empty() {
+        info.push_str(&format!(
+            " /Subject {}",
+            pdf_text(&format!("Status: {}", manga.status))
+        ));
+    }
+    info.push_str(" >>");
+    pdf.object(INFO_ID, &info)?;
+
+    pdf.finish()?.flush()?;
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use serde::Serialize;
+use std::{fs, path::Path};
+
+use crate::{
+    error::MgdlResult,
+    models::{Chapter, Manga},
+};
+
+/// Series metadata file read by Komga and Mylar, next to `mgdl.toml`.
+pub const SERIES_JSON_FILE: &str = "series.json";
+
+const SCHEMA_VERSION: &str = "1.0.2";
+
+/// A `series.json` in the Mylar schema.
+#[derive(Serialize, Debug)]
+pub struct SeriesJson {
+    pub version: &'static str,
+    pub metadata: SeriesMetadata,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SeriesMetadata {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub publisher: String,
+    pub name: String,
+    /// year of the first release the site lists
+    pub year: Option<u32>,
+    pub description_text: Option<String>,
+    pub booktype: &'static str,
+    pub total_issues: usize,
+    /// `Continuing` or `Ended`
+    pub status: &'static str,
+}
+
+impl SeriesJson {
+    pub fn new(manga: &Manga, chapters: &[Chapter]) -> Self {
+        let year = chapters
+            .iter()
+            .filter_map(|ch| ch.released.as_deref()?.get(..4)?.parse().ok())
+            .min();
+        let description = (!manga.authors.is_empty()).then(|| format!("By {}", manga.authors));
+        Self {
+            version: SCHEMA_VERSION,
+            metadata: SeriesMetadata {
+                kind: "comicSeries",
+                publisher: String::new(),
+                name: manga.name.clone(),
+                year,
+                description_text: description,
+                booktype: "Print",
+                total_issues: chapters.len(),
+                status: series_status(&manga.status),
+            },
+        }
+    }
+
+    pub fn write(&self, manga_path: &Path) -> MgdlResult<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(manga_path.join(SERIES_JSON_FILE), json + "\n")?;
+        Ok(())
+    }
+}
+
+/// The site's status (`Ongoing`, `Completed`, `Hiatus`, ...) as one of the
+/// two the schema knows.
+fn series_status(status: &str) -> &'static str {
+    let status = status.to_lowercase();
+    let ended = ["complete", "ended", "finished", "cancel", "discontinued"];
+    if ended.iter().any(|word| status.contains(word)) {
+        "Ended"
+    } else {
+        "Continuing"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_maps_to_schema() {
+        assert_eq!(series_status("Completed"), "Ended");
+        assert_eq!(series_status("Cancelled"), "Ended");
+        assert_eq!(series_status("Ongoing"), "Continuing");
+        assert_eq!(series_status("Hiatus"), "Continuing");
+        assert_eq!(series_status(""), "Continuing");
+    }
+}
@@ -0,0 +1,81 @@
+use image::{imageops::FilterType, DynamicImage};
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::error::MgdlResult;
+
+/// Folder inside a series folder where quarantined pages are moved, under
+/// the same chapter path.
+pub const QUARANTINE_DIR: &str = ".quarantine";
+/// Most bits two dHashes may differ by for the pages to count as the same,
+/// by default.
+pub const MAX_DISTANCE: u32 = 8;
+
+/// What happens to a downloaded page that matches the blocklist.
+#[derive(Clone, Copy, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    /// delete it
+    #[default]
+    Skip,
+    /// move it under `.quarantine/` in the series folder
+    Quarantine,
+}
+
+/// 64-bit difference hash: each bit tells whether a pixel of the page shrunk
+/// to 9x8 grayscale is brighter than its right neighbour. Scaling, recompression
+/// and small edits change few bits.
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0];
+            hash = hash << 1 | brighter as u64;
+        }
+    }
+    hash
+}
+
+pub fn dhash_file(path: &Path) -> MgdlResult<u64> {
+    Ok(dhash(&image::open(path)?))
+}
+
+/// Perceptual hashes of pages not to keep, such as group credits and ads.
+#[derive(Debug, Clone)]
+pub struct Blocklist {
+    hashes: Vec<u64>,
+    /// most bits a page's hash may differ from a blocked one by to match
+    max_distance: u32,
+}
+
+impl Default for Blocklist {
+    fn default() -> Self {
+        Self::new(Vec::new(), MAX_DISTANCE)
+    }
+}
+
+impl Blocklist {
+    pub fn new(hashes: Vec<u64>, max_distance: u32) -> Self {
+        Self {
+            hashes,
+            max_distance,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    pub fn contains(&self, hash: u64) -> bool {
+        self.hashes
+            .iter()
+            .any(|blocked| (blocked ^ hash).count_ones() <= self.max_distance)
+    }
+
+    /// Whether an encoded page matches. Pages that can't be decoded don't.
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        !self.is_empty()
+            && image::load_from_memory(bytes).is_ok_and(|image| self.contains(dhash(&image)))
+    }
+}
@@ -0,0 +1,586 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{MgdlError, MgdlResult},
+    models::{ChapterNumber, Manga},
+    utils::{folder_name, sanitize_file_name},
+};
+
+/// Path templates for a library, from the `[layout]` table of config.toml.
+/// The defaults reproduce the historical fixed layout.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    /// series folder inside `manga_dir`
+    pub series: String,
+    /// chapter folder inside the series folder; may contain `/`
+    pub chapter: String,
+    /// page file name inside the chapter folder or CBZ
+    pub page: String,
+    /// packed chapter inside the series folder, without `.cbz`
+    pub cbz: String,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            series: "{name}".to_string(),
+            chapter: "chapter_{number}".to_string(),
+            page: "{page:03}.{ext}".to_string(),
+            cbz: "{title} - Ch {chapter:04}".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Title,
+    Name,
+    Hash,
+    Number,
+    Chapter,
+    Volume,
+    Page,
+    Ext,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "title" => Field::Title,
+            "name" => Field::Name,
+            "hash" => Field::Hash,
+            "number" => Field::Number,
+            "chapter" => Field::Chapter,
+            "volume" => Field::Volume,
+            "page" => Field::Page,
+            "ext" => Field::Ext,
+            _ => return None,
+        })
+    }
+
+    /// Whether `c` can appear in a rendered value of this field.
+    fn accepts(self, c: char) -> bool {
+        match self {
+            Field::Title | Field::Name | Field::Hash => true,
+            Field::Number => c.is_ascii_digit() || c.is_ascii_lowercase() || ".-".contains(c),
+            Field::Chapter => c.is_ascii_alphanumeric() || ".-".contains(c),
+            Field::Volume => c.is_ascii_digit(),
+            // `_` separates the slice of a split page
+            Field::Page => c.is_ascii_digit() || c == '_',
+            Field::Ext => c.is_ascii_alphanumeric(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Field { field: Field, width: usize },
+}
+
+/// Values to fill a template with.
+#[derive(Default)]
+pub struct Values<'a> {
+    pub title: &'a str,
+    pub hash: &'a str,
+    pub number: &'a str,
+    pub volume: Option<u32>,
+    pub page: usize,
+    /// slice of a page split into several, rendered as `{page}_NN`
+    pub slice: Option<usize>,
+    pub ext: &'a str,
+}
+
+/// Values read back from a rendered name.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Captures {
+    /// stored chapter number, e.g. `0005.5`
+    pub number: Option<String>,
+    pub volume: Option<u32>,
+    pub page: Option<usize>,
+    pub slice: Option<usize>,
+    pub ext: Option<String>,
+}
+
+/// One path segment of a template, e.g. `{page:03}.{ext}`.
+#[derive(Debug, Clone)]
+struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    fn parse(source: &str) -> MgdlResult<Self> {
+        let invalid = |msg: &str| MgdlError::Config(format!("Invalid template {source:?}: {msg}"));
+        let mut parts = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| invalid("unclosed '{'"))?
+                + start;
+            let spec = &rest[start + 1..end];
+            let (name, width) = spec.split_once(':').unwrap_or((spec, "0"));
+            let field =
+                Field::parse(name).ok_or_else(|| invalid(&format!("unknown field {{{name}}}")))?;
+            let width = width
+                .parse()
+                .map_err(|_| invalid(&format!("bad padding in {{{spec}}}")))?;
+            if matches!(parts.last(), Some(Part::Field { .. })) {
+                return Err(invalid("fields must be separated by text"));
+            }
+            parts.push(Part::Field { field, width });
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(invalid("unmatched '}'"));
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        if parts.is_empty() {
+            return Err(invalid("empty segment"));
+        }
+        Ok(Self { parts })
+    }
+
+    fn fields(&self) -> impl Iterator<Item = Field> + '_ {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Field { field, .. } => Some(*field),
+            Part::Literal(_) => None,
+        })
+    }
+
+    fn render(&self, values: &Values) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Field { field, width } => {
+                    let width = *width;
+                    let value = match field {
+                        Field::Title => sanitize_file_name(values.title),
+                        Field::Name => folder_name(values.title, values.hash),
+                        Field::Hash => sanitize_file_name(values.hash),
+                        Field::Number => values.number.to_string(),
+                        Field::Chapter => ChapterNumber::from_key(values.number)
+                            .map_or_else(|| values.number.to_string(), |n| n.padded(width)),
+                        Field::Volume => format!("{:0width$}", values.volume.unwrap_or(0)),
+                        Field::Page => match values.slice {
+                            Some(slice) => format!("{:0width$}_{slice:02}", values.page),
+                            None => format!("{:0width$}", values.page),
+                        },
+                        Field::Ext => values.ext.to_string(),
+                    };
+                    out.push_str(&value);
+                }
+            }
+        }
+        out
+    }
+
+    fn capture(&self, name: &str) -> Option<Captures> {
+        let mut captures = Captures::default();
+        capture_parts(&self.parts, name, &mut captures).then_some(captures)
+    }
+}
+
+/// Match `text` against `parts`, trying the longest value for each field
+/// first and backtracking when the rest does not match.
+fn capture_parts(parts: &[Part], text: &str, captures: &mut Captures) -> bool {
+    let Some((part, rest)) = parts.split_first() else {
+        return text.is_empty();
+    };
+    match part {
+        Part::Literal(literal) => text
+            .strip_prefix(literal.as_str())
+            .is_some_and(|text| capture_parts(rest, text, captures)),
+        Part::Field { field, .. } => {
+            let max = text
+                .char_indices()
+                .find(|(_, c)| !field.accepts(*c))
+                .map_or(text.len(), |(i, _)| i);
+            let ends = text[..max]
+                .char_indices()
+                .map(|(i, c)| i + c.len_utf8())
+                .rev();
+            for end in ends {
+                let mut attempt = captures.clone();
+                if set_field(&mut attempt, *field, &text[..end])
+                    && capture_parts(rest, &text[end..], &mut attempt)
+                {
+                    *captures = attempt;
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+fn set_field(captures: &mut Captures, field: Field, value: &str) -> bool {
+    match field {
+        Field::Title | Field::Name | Field::Hash => true,
+        // old `NNNN-NN` names read back as the current key
+        Field::Number => match ChapterNumber::from_key(value) {
+            Some(number) => {
+                captures.number = Some(number.key());
+                true
+            }
+            None => false,
+        },
+        Field::Chapter => match ChapterNumber::parse(value) {
+            Some(number) => {
+                captures.number = Some(number.key());
+                true
+            }
+            None => false,
+        },
+        Field::Volume => value
+            .parse()
+            .map(|volume| captures.volume = Some(volume))
+            .is_ok(),
+        Field::Page => {
+            let (page, slice) = match value.split_once('_') {
+                Some((page, slice)) => match slice.parse() {
+                    Ok(slice) => (page, Some(slice)),
+                    Err(_) => return false,
+                },
+                None => (value, None),
+            };
+            page.parse()
+                .map(|page| {
+                    captures.page = Some(page);
+                    captures.slice = slice;
+                })
+                .is_ok()
+        }
+        Field::Ext => {
+            captures.ext = Some(value.to_string());
+            true
+        }
+    }
+}
+
+/// Parse a template that may span several folders.
+fn parse_path(source: &str) -> MgdlResult<Vec<Template>> {
+    source.split('/').map(Template::parse).collect()
+}
+
+fn check_fields(
+    kind: &str,
+    templates: &[Template],
+    allowed: &[Field],
+    required: &[&[Field]],
+) -> MgdlResult<()> {
+    let fields: Vec<Field> = templates.iter().flat_map(|t| t.fields()).collect();
+    if let Some(field) = fields.iter().find(|f| !allowed.contains(f)) {
+        return Err(MgdlError::Config(format!(
+            "{field:?} can't be used in the {kind} template"
+        )));
+    }
+    for options in required {
+        if !options.iter().any(|f| fields.contains(f)) {
+            let names: Vec<String> = options
+                .iter()
+                .map(|f| format!("{{{}}}", format!("{f:?}").to_lowercase()))
+                .collect();
+            return Err(MgdlError::Config(format!(
+                "The {kind} template needs {}",
+                names.join(" or ")
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// A found chapter folder or CBZ and the values read from its path.
+#[derive(Debug)]
+pub struct Found {
+    pub volume: Option<u32>,
+    pub path: PathBuf,
+}
+
+/// Compiled path templates: where series, chapters and pages live on disk,
+/// and how to read chapter and page numbers back from those paths.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    config: LayoutConfig,
+    series: Template,
+    chapter: Vec<Template>,
+    page: Template,
+    cbz: Vec<Template>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::new(LayoutConfig::default()).expect("default layout is valid")
+    }
+}
+
+impl Layout {
+    pub fn new(config: LayoutConfig) -> MgdlResult<Self> {
+        use Field::*;
+        let manga_fields = [Title, Name, Hash];
+        let chapter_fields = [Title, Name, Hash, Number, Chapter, Volume];
+
+        if config.series.contains('/') || config.page.contains('/') {
+            return Err(MgdlError::Config(
+                "The series and page templates can't contain '/'".to_string(),
+            ));
+        }
+        let series = Template::parse(&config.series)?;
+        check_fields("series", std::slice::from_ref(&series), &manga_fields, &[])?;
+        let chapter = parse_path(&config.chapter)?;
+        check_fields("chapter", &chapter, &chapter_fields, &[&[Number, Chapter]])?;
+        let cbz = parse_path(&config.cbz)?;
+        check_fields("cbz", &cbz, &chapter_fields, &[&[Number, Chapter]])?;
+        let page = Template::parse(&config.page)?;
+        check_fields(
+            "page",
+            std::slice::from_ref(&page),
+            &[Page, Ext],
+            &[&[Page], &[Ext]],
+        )?;
+
+        Ok(Self {
+            config,
+            series,
+            chapter,
+            page,
+            cbz,
+        })
+    }
+
+    pub fn config(&self) -> &LayoutConfig {
+        &self.config
+    }
+
+    /// Folder name of a series inside `manga_dir`.
+    pub fn series_folder(&self, title: &str, hash: &str) -> String {
+        self.series.render(&Values {
+            title,
+            hash,
+            ..Default::default()
+        })
+    }
+
+    fn render_path(templates: &[Template], values: &Values) -> PathBuf {
+        templates.iter().map(|t| t.render(values)).collect()
+    }
+
+    /// Chapter folder, relative to the series folder.
+    pub fn chapter_dir(&self, manga: &Manga, number: &str, volume: Option<u32>) -> PathBuf {
+        Self::render_path(
+            &self.chapter,
+            &Values {
+                title: &manga.name,
+                hash: &manga.hash,
+                number,
+                volume,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Packed chapter, relative to the series folder.
+    pub fn cbz_file(&self, manga: &Manga, number: &str, volume: Option<u32>) -> PathBuf {
+        let mut path = Self::render_path(
+            &self.cbz,
+            &Values {
+                title: &manga.name,
+                hash: &manga.hash,
+                number,
+                volume,
+                ..Default::default()
+            },
+        );
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".cbz");
+        path.set_file_name(file_name);
+        path
+    }
+
+    pub fn page_file_name(&self, page: usize, ext: &str) -> String {
+        self.page.render(&Values {
+            page,
+            ext,
+            ..Default::default()
+        })
+    }
+
+    /// File name of one slice of a page split into several, numbered from 1.
+    /// It sorts between the page before and after, and reads back as `page`.
+    pub fn slice_file_name(&self, page: usize, slice: usize, ext: &str) -> String {
+        self.page.render(&Values {
+            page,
+            slice: Some(slice),
+            ext,
+            ..Default::default()
+        })
+    }
+
+    /// Page number of a page file name; slices of a split page give the
+    /// number of the page they came from.
+    pub fn page_number(&self, file_name: &str) -> Option<usize> {
+        self.page.capture(file_name)?.page
+    }
+
+    /// A page file named under the `old` layout, renamed for this one. Files
+    /// that aren't pages keep their name.
+    pub fn convert_page_name(&self, old: &Layout, file_name: &str) -> String {
+        match old.page.capture(file_name) {
+            Some(Captures {
+                page: Some(page),
+                slice,
+                ext: Some(ext),
+                ..
+            }) => self.page.render(&Values {
+                page,
+                slice,
+                ext: &ext,
+                ..Default::default()
+            }),
+            _ => file_name.to_string(),
+        }
+    }
+
+    /// Chapter folders under a series folder, by chapter number.
+    pub fn find_chapter_dirs(&self, manga_path: &Path) -> HashMap<String, Found> {
+        find(manga_path, &self.chapter, |path, name| {
+            path.is_dir().then_some(name)
+        })
+    }
+
+    /// Packed chapters under a series folder, by chapter number.
+    pub fn find_cbz_files(&self, manga_path: &Path) -> HashMap<String, Found> {
+        find(manga_path, &self.cbz, |path, name| {
+            if path.is_file() {
+                name.strip_suffix(".cbz")
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// Walk `root` one template segment per folder level and collect the paths
+/// whose last component, as filtered by `leaf`, matches the last segment.
+fn find(
+    root: &Path,
+    templates: &[Template],
+    leaf: impl for<'a> Fn(&Path, &'a str) -> Option<&'a str> + Copy,
+) -> HashMap<String, Found> {
+    let mut found = HashMap::new();
+    find_into(root, templates, Captures::default(), leaf, &mut found);
+    found
+}
+
+fn find_into(
+    dir: &Path,
+    templates: &[Template],
+    captures: Captures,
+    leaf: impl for<'a> Fn(&Path, &'a str) -> Option<&'a str> + Copy,
+    found: &mut HashMap<String, Found>,
+) {
+    let Some((template, rest)) = templates.split_first() else {
+        return;
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        let name = if rest.is_empty() {
+            match leaf(&path, &name) {
+                Some(name) => name.to_string(),
+                None => continue,
+            }
+        } else if path.is_dir() {
+            name
+        } else {
+            continue;
+        };
+        let Some(segment) = template.capture(&name) else {
+            continue;
+        };
+        let captures = Captures {
+            number: segment.number.or(captures.number.clone()),
+            volume: segment.volume.or(captures.volume),
+            ..Default::default()
+        };
+        if !rest.is_empty() {
+            find_into(&path, rest, captures, leaf, found);
+        } else if let Some(number) = captures.number {
+            found.insert(
+                number,
+                Found {
+                    volume: captures.volume,
+                    path,
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_parse_errors() {
+        assert!(Template::parse("{page").is_err());
+        assert!(Template::parse("{nope}").is_err());
+        assert!(Template::parse("{page:x}").is_err());
+        assert!(Template::parse("{page}{ext}").is_err());
+        assert!(Template::parse("page}").is_err());
+    }
+
+    #[test]
+    fn capture_backtracks_over_greedy_fields() {
+        let template = Template::parse("{title} - Ch {chapter:04}").unwrap();
+        let captures = template.capture("A - Ch 1 - Ch 0005.5").unwrap();
+        assert_eq!(captures.number.as_deref(), Some("0005.5"));
+        let captures = template.capture("A - Ch Extra").unwrap();
+        assert_eq!(captures.number.as_deref(), Some("special-extra"));
+    }
+
+    #[test]
+    fn number_field_reads_old_names() {
+        let template = Template::parse("chapter_{number}").unwrap();
+        let number = |name| template.capture(name).unwrap().number.unwrap();
+        assert_eq!(number("chapter_0005-01"), "0005");
+        assert_eq!(number("chapter_0005-05"), "0005.5");
+        assert_eq!(number("chapter_0005.10"), "0005.10");
+        assert_eq!(number("chapter_special-oneshot"), "special-oneshot");
+        assert!(template.capture("chapter_0005-x").is_none());
+    }
+
+    #[test]
+    fn chapter_field_renders_padded_display_number() {
+        let template = Template::parse("{chapter:03}").unwrap();
+        let render = |number| {
+            template.render(&Values {
+                number,
+                ..Default::default()
+            })
+        };
+        assert_eq!(render("0010"), "010");
+        assert_eq!(render("0005.5"), "005.5");
+        assert_eq!(render("1234"), "1234");
+        assert_eq!(render("0010a"), "010a");
+        assert_eq!(render("special-extra"), "extra");
+        assert_eq!(render("0005-05"), "005.5");
+    }
+}
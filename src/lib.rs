@@ -1,5 +1,12 @@
+pub mod backup;
 pub mod db;
+pub mod dedupe;
 pub mod error;
+pub mod export;
+pub mod filter;
+pub mod layout;
+pub mod library;
 pub mod models;
+pub mod process;
 pub mod scrape;
 pub mod utils;
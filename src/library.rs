@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{MgdlError, MgdlResult},
+    export::cbz,
+    layout::Layout,
+    models::{cmp_chapter_keys, ChapterRange, LocalChapter, Manga},
+};
+
+/// Per-series metadata file written next to the chapters, so the library
+/// can be rebuilt from `manga_dir` alone.
+pub const SIDECAR_FILE: &str = "mgdl.toml";
+
+/// Optional per-series file mapping volumes to chapter ranges.
+pub const VOLUMES_FILE: &str = "volumes.toml";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Sidecar {
+    pub hash: String,
+    pub url: String,
+    pub title: String,
+    #[serde(default)]
+    pub authors: String,
+    #[serde(default)]
+    pub status: String,
+}
+
+impl Sidecar {
+    pub fn new(manga: &Manga, url: &str) -> Self {
+        Self {
+            hash: manga.hash.clone(),
+            url: url.to_string(),
+            title: manga.name.clone(),
+            authors: manga.authors.clone(),
+            status: manga.status.clone(),
+        }
+    }
+
+    /// Rebuild the manga row for a series stored in `folder_name`.
+    pub fn to_manga(&self, folder_name: &str) -> Manga {
+        Manga::new(
+            &self.hash,
+            &self.title,
+            folder_name,
+            &self.authors,
+            &self.status,
+        )
+    }
+
+    pub fn read(manga_path: &Path) -> MgdlResult<Option<Self>> {
+        let path = manga_path.join(SIDECAR_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(toml::from_str(&fs::read_to_string(path)?)?))
+    }
+
+    pub fn write(&self, manga_path: &Path) -> MgdlResult<()> {
+        fs::write(manga_path.join(SIDECAR_FILE), toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// Volume to chapter range mapping from `volumes.toml`, e.g. `1 = "1..8"`.
+/// It takes precedence over volumes scraped from the site.
+#[derive(Debug, Default)]
+pub struct VolumeMap {
+    ranges: Vec<(u32, ChapterRange)>,
+}
+
+impl VolumeMap {
+    /// The series' mapping, or an empty one when it has no `volumes.toml`.
+    pub fn read(manga_path: &Path) -> MgdlResult<Self> {
+        let path = manga_path.join(VOLUMES_FILE);
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let raw: HashMap<String, String> = toml::from_str(&fs::read_to_string(&path)?)?;
+        let invalid = |msg: String| MgdlError::Config(format!("{}: {}", path.display(), msg));
+
+        let mut ranges = raw
+            .into_iter()
+            .map(|(volume, range)| {
+                let volume = volume
+                    .parse::<u32>()
+                    .map_err(|_| invalid(format!("invalid volume {volume:?}")))?;
+                let range = ChapterRange::parse(&range)
+                    .map_err(|e| invalid(format!("invalid range {range:?} ({e})")))
+                    .and_then(|parsed| match parsed.is_numeric() {
+                        true => Ok(parsed),
+                        false => Err(invalid(format!(
+                            "range {range:?} may only use chapter numbers"
+                        ))),
+                    })?;
+                Ok((volume, range))
+            })
+            .collect::<MgdlResult<Vec<_>>>()?;
+        ranges.sort_by_key(|(volume, _)| *volume);
+        Ok(Self { ranges })
+    }
+
+    pub fn volume_of(&self, major_number: usize) -> Option<u32> {
+        self.ranges
+            .iter()
+            .find(|(_, range)| range.contains(major_number))
+            .map(|(volume, _)| *volume)
+    }
+}
+
+pub fn existing_page_numbers(layout: &Layout, chapter_path: &Path) -> HashSet<usize> {
+    let Ok(entries) = fs::read_dir(chapter_path) else {
+        return HashSet::new();
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| layout.page_number(e.file_name().to_str()?))
+        .collect()
+}
+
+/// Number of page files in a chapter folder; split pages count once per slice.
+pub fn page_file_count(layout: &Layout, chapter_path: &Path) -> usize {
+    let Ok(entries) = fs::read_dir(chapter_path) else {
+        return 0;
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.file_name()
+                .to_str()
+                .is_some_and(|name| layout.page_number(name).is_some())
+        })
+        .count()
+}
+
+/// Pages of a chapter that are done, whether loose in its folder or packed
+/// into its CBZ.
+pub fn existing_chapter_pages(
+    layout: &Layout,
+    chapter_path: &Path,
+    cbz_path: Option<&Path>,
+) -> HashSet<usize> {
+    let mut pages = existing_page_numbers(layout, chapter_path);
+    if let Some(names) = cbz_path.and_then(|path| cbz::entry_names(path).ok()) {
+        pages.extend(names.iter().filter_map(|name| layout.page_number(name)));
+    }
+    pages
+}
+
+/// A page image of a downloaded chapter, named as on disk (`007.jpg`).
+pub struct PageImage {
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+/// Page images of a chapter, from its folder and/or its CBZ, in page order.
+pub fn chapter_pages(
+    layout: &Layout,
+    chapter_path: &Path,
+    cbz_path: Option<&Path>,
+) -> MgdlResult<Vec<PageImage>> {
+    let mut pages = BTreeMap::new();
+    if let Some(cbz_path) = cbz_path {
+        pages.extend(cbz::read_pages(cbz_path)?);
+    }
+    if chapter_path.is_dir() {
+        for entry in fs::read_dir(chapter_path)?.filter_map(|e| e.ok()) {
+            if let Some(name) = entry.file_name().to_str() {
+                pages.insert(name.to_string(), fs::read(entry.path())?);
+            }
+        }
+    }
+
+    let mut pages: Vec<(usize, PageImage)> = pages
+        .into_iter()
+        .filter_map(|(name, bytes)| Some((layout.page_number(&name)?, PageImage { name, bytes })))
+        .collect();
+    pages.sort_by(|(a, a_page), (b, b_page)| a.cmp(b).then(a_page.name.cmp(&b_page.name)));
+    Ok(pages.into_iter().map(|(_, page)| page).collect())
+}
+
+/// Cover art saved as `cover.<ext>` in the series folder, if any.
+pub fn cover_image(manga_path: &Path) -> MgdlResult<Option<PageImage>> {
+    let Ok(entries) = fs::read_dir(manga_path) else {
+        return Ok(None);
+    };
+    let mut covers: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.file_stem().is_some_and(|stem| stem == "cover"))
+        .collect();
+    covers.sort();
+    let Some(path) = covers.into_iter().next() else {
+        return Ok(None);
+    };
+    Ok(Some(PageImage {
+        name: path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("cover")
+            .to_string(),
+        bytes: fs::read(&path)?,
+    }))
+}
+
+/// Chapters present on disk for a series, as folders or CBZs, sorted by
+/// number.
+pub fn local_chapters(layout: &Layout, manga_path: &Path) -> MgdlResult<Vec<LocalChapter>> {
+    if !manga_path.is_dir() {
+        return Err(MgdlError::Config(format!(
+            "{} is not a directory",
+            manga_path.display()
+        )));
+    }
+    let mut found = layout.find_cbz_files(manga_path);
+    let dirs = layout.find_chapter_dirs(manga_path);
+
+    let mut chapters: Vec<LocalChapter> = dirs
+        .keys()
+        .chain(found.keys())
+        .cloned()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .map(|number| {
+            let dir = dirs.get(&number);
+            let cbz = found.remove(&number);
+            let chapter_path = dir.map(|d| d.path.clone()).unwrap_or_default();
+            let pages = existing_chapter_pages(
+                layout,
+                &chapter_path,
+                cbz.as_ref().map(|c| c.path.as_path()),
+            )
+            .len();
+            let volume = dir.and_then(|d| d.volume).or(cbz.and_then(|c| c.volume));
+            LocalChapter {
+                number,
+                pages,
+                volume,
+            }
+        })
+        .collect();
+    chapters.sort_by(|a, b| cmp_chapter_keys(&a.number, &b.number));
+    Ok(chapters)
+}
@@ -1,24 +1,252 @@
 use clap::ValueEnum;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::time::Duration;
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::Sender,
+        Mutex,
+    },
+    time::Duration,
+};
 
-use crate::error::{MgdlError, MgdlResult};
+use crate::{
+    error::{MgdlError, MgdlResult},
+    plan::SeriesPlan,
+    tui::Update,
+};
+
+/// Version of the `--output json` schema, bumped when a field changes
+/// meaning or goes away.
+pub const SCHEMA_VERSION: u32 = 2;
 
 #[derive(Clone, Copy, ValueEnum, Debug, PartialEq)]
 pub enum LogMode {
     Quiet,
     Plain,
     Fancy,
+    /// feeds `mgdl tui` instead of writing to the terminal
+    #[value(skip)]
+    Tui,
+}
+
+/// How results are written to stdout.
+#[derive(Clone, Copy, ValueEnum, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// messages for people, shaped by --log
+    #[default]
+    Text,
+    /// one JSON object per line (NDJSON), ending with a summary
+    Json,
+}
+
+/// Something that happened during a run. As text these are the usual
+/// messages; as JSON each one is an object tagged by its `event` field.
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// a series is checked for chapters to download
+    Series {
+        series: &'a str,
+        chapters: usize,
+    },
+    ChapterDownloaded {
+        series: &'a str,
+        chapter: &'a str,
+        pages: usize,
+        skipped: usize,
+        filtered: usize,
+    },
+    /// every page of the chapter was already there
+    ChapterSkipped {
+        series: &'a str,
+        chapter: &'a str,
+        pages: usize,
+    },
+    /// some pages couldn't be downloaded; the chapter is left for
+    /// `consolidate`
+    ChapterFailed {
+        series: &'a str,
+        chapter: &'a str,
+        pages: usize,
+        skipped: usize,
+        failed: usize,
+        error: String,
+    },
+    /// a chapter's pages are about to be fetched; only the terminal UI
+    /// shows this and `page_downloaded`
+    ChapterQueued {
+        series: &'a str,
+        chapter: &'a str,
+        pages: usize,
+    },
+    PageDownloaded {
+        series: &'a str,
+        chapter: &'a str,
+    },
+    SeriesSkipped {
+        series: &'a str,
+        reason: String,
+    },
+    /// an entry of the series' chapter list was skipped
+    ChapterListWarning {
+        series: &'a str,
+        message: String,
+    },
+    /// what a dry run would fetch for a series
+    Plan(&'a SeriesPlan),
+    PlanTotal {
+        series: usize,
+        to_fetch: usize,
+        bytes: u64,
+    },
+    /// how an entry of a list of URLs went, reported once the list is done
+    BatchEntry {
+        line: usize,
+        url: &'a str,
+        /// the series, when the entry went through
+        series: Option<&'a str>,
+        error: Option<String>,
+    },
+    BatchTotal {
+        entries: usize,
+        failed: usize,
+    },
+    /// a resolved setting, from `mgdl config show`
+    Setting {
+        name: &'a str,
+        value: &'a str,
+        source: String,
+    },
+    Message {
+        message: String,
+    },
+    Warning {
+        message: String,
+    },
+}
+
+#[derive(Serialize, Default)]
+struct Tally {
+    downloaded: usize,
+    skipped: usize,
+    failed: usize,
+}
+
+#[derive(Serialize, Default)]
+struct PageTally {
+    downloaded: usize,
+    skipped: usize,
+    failed: usize,
+    filtered: usize,
+}
+
+/// Last line of `--output json`.
+#[derive(Serialize)]
+struct Summary {
+    event: &'static str,
+    schema: u32,
+    ok: bool,
+    error: Option<String>,
+    series: usize,
+    chapters: Tally,
+    pages: PageTally,
+    /// warning and chapter_list_warning events
+    warnings: Vec<SummaryWarning>,
+}
+
+/// A warning as the summary lists it.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct SummaryWarning {
+    /// the series being worked on, if any
+    series: Option<String>,
+    message: String,
+}
+
+#[derive(Default)]
+struct Counters {
+    series: AtomicUsize,
+    chapters_downloaded: AtomicUsize,
+    chapters_skipped: AtomicUsize,
+    chapters_failed: AtomicUsize,
+    pages_downloaded: AtomicUsize,
+    pages_skipped: AtomicUsize,
+    pages_failed: AtomicUsize,
+    pages_filtered: AtomicUsize,
+    warnings: Mutex<Vec<SummaryWarning>>,
+    entries_failed: AtomicUsize,
+}
+
+impl Counters {
+    /// Tally `event`; warnings are kept with `series`, the series being
+    /// worked on, unless they name their own.
+    fn count(&self, event: &Event, series: Option<&str>) {
+        let add = |counter: &AtomicUsize, n: usize| {
+            counter.fetch_add(n, Ordering::Relaxed);
+        };
+        match event {
+            Event::Series { .. } => add(&self.series, 1),
+            Event::ChapterDownloaded {
+                pages,
+                skipped,
+                filtered,
+                ..
+            } => {
+                add(&self.chapters_downloaded, 1);
+                add(&self.pages_downloaded, *pages);
+                add(&self.pages_skipped, *skipped);
+                add(&self.pages_filtered, *filtered);
+            }
+            Event::ChapterSkipped { pages, .. } => {
+                add(&self.chapters_skipped, 1);
+                add(&self.pages_skipped, *pages);
+            }
+            Event::ChapterFailed {
+                pages,
+                skipped,
+                failed,
+                ..
+            } => {
+                add(&self.chapters_failed, 1);
+                add(&self.pages_downloaded, *pages);
+                add(&self.pages_skipped, *skipped);
+                add(&self.pages_failed, *failed);
+            }
+            Event::Warning { message } => self.warn(series, message),
+            Event::ChapterListWarning { series, message } => self.warn(Some(series), message),
+            Event::BatchEntry { error: Some(_), .. } => add(&self.entries_failed, 1),
+            _ => {}
+        }
+    }
+
+    fn warn(&self, series: Option<&str>, message: &str) {
+        self.warnings.lock().unwrap().push(SummaryWarning {
+            series: series.map(str::to_string),
+            message: message.to_string(),
+        });
+    }
+
+    fn get(counter: &AtomicUsize) -> usize {
+        counter.load(Ordering::Relaxed)
+    }
 }
 
 pub struct Logger {
     mode: LogMode,
     verbose: bool,
+    json: bool,
     multi: Option<MultiProgress>,
+    counters: Counters,
+    /// series being worked on, to give errors context
+    series: Mutex<Option<String>>,
+    ui: Option<Sender<Update>>,
 }
 
 impl Logger {
-    pub fn new(mode: LogMode, verbose: bool) -> Self {
+    pub fn new(mode: LogMode, verbose: bool, output: OutputFormat) -> Self {
+        let json = output == OutputFormat::Json;
+        // spinners and bars would garble the JSON lines
+        let mode = if json { LogMode::Quiet } else { mode };
         let multi = match mode {
             LogMode::Fancy => Some(MultiProgress::new()),
             _ => None,
@@ -26,8 +254,197 @@ impl Logger {
         Self {
             mode,
             verbose,
+            json,
             multi,
+            counters: Counters::default(),
+            series: Mutex::new(None),
+            ui: None,
+        }
+    }
+
+    /// A logger for `mgdl tui`: events become updates for the UI, which owns
+    /// the terminal.
+    pub fn for_ui(ui: Sender<Update>) -> Self {
+        Self {
+            mode: LogMode::Tui,
+            verbose: false,
+            json: false,
+            multi: None,
+            counters: Counters::default(),
+            series: Mutex::new(None),
+            ui: Some(ui),
+        }
+    }
+
+    pub fn event(&self, event: Event) {
+        {
+            let mut current = self.series.lock().unwrap();
+            self.counters.count(&event, current.as_deref());
+            if let Event::Series { series, .. } = &event {
+                *current = Some(series.to_string());
+            }
+        }
+        if self.json {
+            if !matches!(
+                event,
+                Event::ChapterQueued { .. } | Event::PageDownloaded { .. }
+            ) {
+                print_json(&event);
+            }
+            return;
+        }
+        match event {
+            Event::Series { .. } => {}
+            Event::ChapterSkipped {
+                series, chapter, ..
+            } => self.send(Update::ChapterDone {
+                series: series.to_string(),
+                chapter: chapter.to_string(),
+                failed: false,
+            }),
+            Event::ChapterDownloaded {
+                series,
+                chapter,
+                pages,
+                filtered,
+                ..
+            } => {
+                let dropped = match filtered {
+                    0 => String::new(),
+                    count => format!(", {count} filtered"),
+                };
+                self.print_success(format!(
+                    "Downloaded {series} ch.{chapter} ({} pages{dropped})",
+                    pages + filtered
+                ));
+                self.send(Update::ChapterDone {
+                    series: series.to_string(),
+                    chapter: chapter.to_string(),
+                    failed: false,
+                });
+            }
+            Event::ChapterFailed {
+                series,
+                chapter,
+                failed,
+                error,
+                ..
+            } => {
+                self.print_warn(format!(
+                    "Failed to download {failed} pages of {series} ch.{chapter}: {error}"
+                ));
+                self.send(Update::ChapterDone {
+                    series: series.to_string(),
+                    chapter: chapter.to_string(),
+                    failed: true,
+                });
+            }
+            Event::ChapterQueued {
+                series,
+                chapter,
+                pages,
+            } => self.send(Update::ChapterQueued {
+                series: series.to_string(),
+                chapter: chapter.to_string(),
+                pages,
+            }),
+            Event::PageDownloaded { series, chapter } => self.send(Update::PageDone {
+                series: series.to_string(),
+                chapter: chapter.to_string(),
+            }),
+            Event::SeriesSkipped { series, reason } => {
+                self.print_warn(format!("Skipping {series}: {reason}"))
+            }
+            Event::ChapterListWarning { series, message } => {
+                self.print_warn(format!("{series}: skipped chapter list {message}"))
+            }
+            Event::Plan(plan) => {
+                for line in plan.lines() {
+                    self.print_report(line);
+                }
+            }
+            Event::PlanTotal {
+                series,
+                to_fetch,
+                bytes,
+            } => self.print_report(format!(
+                "Total: {to_fetch} pages to fetch (~{}) in {series} series",
+                HumanBytes(bytes)
+            )),
+            Event::BatchEntry {
+                line,
+                url,
+                series,
+                error,
+            } => match (series, error) {
+                (_, Some(error)) => {
+                    self.print_report(format!("  failed  line {line}: {url}: {error}"))
+                }
+                (series, None) => {
+                    self.print_report(format!("  ok      line {line}: {}", series.unwrap_or(url)))
+                }
+            },
+            Event::BatchTotal { entries, failed } => self.print_report(format!(
+                "{} of {entries} entries went through, {failed} failed",
+                entries - failed
+            )),
+            Event::Setting {
+                name,
+                value,
+                source,
+            } => self.print_report(format!("{name:<16} = {value}  ({source})")),
+            Event::Message { message } => self.print_success(message),
+            Event::Warning { message } => self.print_warn(message),
+        }
+    }
+
+    /// End the run: report a fatal error, and with JSON output write the
+    /// summary. Returns whether the run went without failures.
+    pub fn finish(&self, error: Option<&MgdlError>) -> bool {
+        let counters = &self.counters;
+        let failed = Counters::get(&counters.chapters_failed);
+        let ok = error.is_none() && failed == 0 && Counters::get(&counters.entries_failed) == 0;
+        if !self.json {
+            if let Some(err) = error {
+                eprintln!("Fatal error: {}", err);
+            }
+            return ok;
         }
+
+        if let Some(err) = error {
+            #[derive(Serialize)]
+            struct ErrorEvent<'a> {
+                event: &'static str,
+                message: String,
+                series: Option<&'a str>,
+            }
+            let series = self.series.lock().unwrap();
+            print_json(&ErrorEvent {
+                event: "error",
+                message: err.to_string(),
+                series: series.as_deref(),
+            });
+        }
+        print_json(&Summary {
+            event: "summary",
+            schema: SCHEMA_VERSION,
+            ok,
+            error: error.map(|err| err.to_string()),
+            series: Counters::get(&counters.series),
+            chapters: Tally {
+                downloaded: Counters::get(&counters.chapters_downloaded),
+                skipped: Counters::get(&counters.chapters_skipped),
+                failed,
+            },
+            pages: PageTally {
+                downloaded: Counters::get(&counters.pages_downloaded),
+                skipped: Counters::get(&counters.pages_skipped),
+                failed: Counters::get(&counters.pages_failed),
+                filtered: Counters::get(&counters.pages_filtered),
+            },
+            warnings: counters.warnings.lock().unwrap().clone(),
+        });
+        ok
     }
 
     pub fn add_spinner(&self, msg: Option<String>) -> MgdlResult<MaybeSpinner> {
@@ -51,6 +468,12 @@ impl Logger {
                 }
                 None
             }
+            LogMode::Tui => {
+                if let Some(msg) = msg {
+                    self.send(Update::Status(msg));
+                }
+                None
+            }
             LogMode::Quiet => None,
         };
 
@@ -58,10 +481,14 @@ impl Logger {
             inner,
             mode: self.mode,
             verbose: self.verbose,
+            ui: self.ui.clone(),
         })
     }
 
     pub fn finish_spinner(&self, spinner: MaybeSpinner) {
+        if self.mode == LogMode::Tui {
+            self.send(Update::Status(String::new()));
+        }
         if let Some(bar) = spinner.inner {
             if let Some(ref mp) = self.multi {
                 bar.finish_and_clear();
@@ -70,6 +497,60 @@ impl Logger {
         }
     }
 
+    pub fn warn(&self, msg: String) {
+        self.event(Event::Warning { message: msg });
+    }
+
+    pub fn success(&self, msg: String) {
+        self.event(Event::Message { message: msg });
+    }
+
+    fn send(&self, update: Update) {
+        if let Some(ref ui) = self.ui {
+            // the UI may already be gone while a download winds down
+            let _ = ui.send(update);
+        }
+    }
+
+    /// Warnings are printed in every mode, including quiet.
+    fn print_warn(&self, msg: String) {
+        if self.mode == LogMode::Tui {
+            return self.send(Update::Warning(msg));
+        }
+        match &self.multi {
+            Some(mp) => {
+                let _ = mp.println(format!("[WARN] {msg}"));
+            }
+            None => eprintln!("[WARN] {msg}"),
+        }
+    }
+
+    fn print_success(&self, msg: String) {
+        match self.mode {
+            LogMode::Fancy => {
+                if let Some(ref mp) = self.multi {
+                    let _ = mp.println(format!("[SUCCESS] {msg}"));
+                }
+            }
+            LogMode::Plain => println!("[SUCCESS] {msg}"),
+            LogMode::Tui => self.send(Update::Message(msg)),
+            LogMode::Quiet => {}
+        }
+    }
+
+    /// Reports asked for, such as a dry run's, are printed in every mode.
+    fn print_report(&self, msg: String) {
+        if self.mode == LogMode::Tui {
+            return self.send(Update::Message(msg));
+        }
+        match &self.multi {
+            Some(mp) => {
+                let _ = mp.println(&msg);
+            }
+            None => println!("{msg}"),
+        }
+    }
+
     pub fn add_bar(&self, size: u64) -> MgdlResult<MaybeBar> {
         let inner = match self.mode {
             LogMode::Fancy => {
@@ -84,7 +565,7 @@ impl Logger {
 
         Ok(MaybeBar {
             inner,
-            mode: self.mode,
+            ui: self.ui.clone(),
         })
     }
 
@@ -104,6 +585,7 @@ pub struct MaybeSpinner {
     inner: Option<ProgressBar>,
     mode: LogMode,
     verbose: bool,
+    ui: Option<Sender<Update>>,
 }
 
 impl MaybeSpinner {
@@ -119,6 +601,11 @@ impl MaybeSpinner {
                     println!("[INFO] {msg}");
                 }
             }
+            LogMode::Tui => {
+                if let Some(ref ui) = self.ui {
+                    let _ = ui.send(Update::Status(msg));
+                }
+            }
             LogMode::Quiet => {}
         }
     }
@@ -126,11 +613,14 @@ impl MaybeSpinner {
 
 pub struct MaybeBar {
     inner: Option<ProgressBar>,
-    mode: LogMode,
+    ui: Option<Sender<Update>>,
 }
 
 impl MaybeBar {
     pub fn set_prefix(&self, msg: String) {
+        if let Some(ref ui) = self.ui {
+            let _ = ui.send(Update::Status(msg.clone()));
+        }
         if let Some(ref bar) = self.inner {
             bar.set_prefix(msg);
         }
@@ -141,17 +631,11 @@ impl MaybeBar {
             bar.inc(delta);
         }
     }
+}
 
-    pub fn success(&self, msg: String) {
-        match self.mode {
-            LogMode::Fancy => {
-                if let Some(ref bar) = self.inner {
-                    bar.println(format!("[SUCCESS] {msg}"));
-                }
-            }
-            LogMode::Plain => println!("[SUCCESS] {msg}"),
-            LogMode::Quiet => {}
-        }
+fn print_json(value: &impl Serialize) {
+    if let Ok(line) = serde_json::to_string(value) {
+        println!("{line}");
     }
 }
 
@@ -173,3 +657,150 @@ fn new_spinner() -> MgdlResult<ProgressBar> {
     spinner.enable_steady_tick(Duration::from_millis(50));
     Ok(spinner)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::ChapterPlan;
+    use serde_json::json;
+
+    #[test]
+    fn events_are_tagged_objects() {
+        let event = Event::ChapterDownloaded {
+            series: "My Manga",
+            chapter: "0001",
+            pages: 18,
+            skipped: 2,
+            filtered: 1,
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({
+                "event": "chapter_downloaded",
+                "series": "My Manga",
+                "chapter": "0001",
+                "pages": 18,
+                "skipped": 2,
+                "filtered": 1,
+            })
+        );
+
+        let mut plan = SeriesPlan::new("My Manga");
+        plan.chapters.push(ChapterPlan {
+            number: "0002".to_string(),
+            to_fetch: 3,
+            present: 1,
+            bytes: 300,
+            unknown_size: 0,
+        });
+        assert_eq!(
+            serde_json::to_value(Event::Plan(&plan)).unwrap(),
+            json!({
+                "event": "plan",
+                "series": "My Manga",
+                "chapters": [{
+                    "chapter": "0002",
+                    "to_fetch": 3,
+                    "present": 1,
+                    "bytes": 300,
+                    "unknown_size": 0,
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn counters_tally_chapters_and_pages() {
+        let counters = Counters::default();
+        for event in [
+            Event::Series {
+                series: "a",
+                chapters: 3,
+            },
+            Event::ChapterDownloaded {
+                series: "a",
+                chapter: "1",
+                pages: 10,
+                skipped: 0,
+                filtered: 2,
+            },
+            Event::ChapterSkipped {
+                series: "a",
+                chapter: "2",
+                pages: 20,
+            },
+            Event::ChapterFailed {
+                series: "a",
+                chapter: "3",
+                pages: 5,
+                skipped: 1,
+                failed: 4,
+                error: "timeout".to_string(),
+            },
+            Event::ChapterListWarning {
+                series: "a",
+                message: "entry 4 (/chapters/x): Chapter number not found".to_string(),
+            },
+            Event::Warning {
+                message: "folder missing".to_string(),
+            },
+        ] {
+            counters.count(&event, None);
+        }
+        assert_eq!(Counters::get(&counters.series), 1);
+        assert_eq!(Counters::get(&counters.chapters_downloaded), 1);
+        assert_eq!(Counters::get(&counters.chapters_skipped), 1);
+        assert_eq!(Counters::get(&counters.chapters_failed), 1);
+        assert_eq!(Counters::get(&counters.pages_downloaded), 15);
+        assert_eq!(Counters::get(&counters.pages_skipped), 21);
+        assert_eq!(Counters::get(&counters.pages_failed), 4);
+        assert_eq!(Counters::get(&counters.pages_filtered), 2);
+        assert_eq!(
+            *counters.warnings.lock().unwrap(),
+            vec![
+                SummaryWarning {
+                    series: Some("a".to_string()),
+                    message: "entry 4 (/chapters/x): Chapter number not found".to_string(),
+                },
+                SummaryWarning {
+                    series: None,
+                    message: "folder missing".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_chapters_fail_the_run() {
+        let logger = Logger::new(LogMode::Quiet, false, OutputFormat::Text);
+        assert!(logger.finish(None));
+        logger.event(Event::ChapterFailed {
+            series: "a",
+            chapter: "1",
+            pages: 0,
+            skipped: 0,
+            failed: 1,
+            error: "timeout".to_string(),
+        });
+        assert!(!logger.finish(None));
+    }
+
+    #[test]
+    fn failed_list_entries_fail_the_run() {
+        let logger = Logger::new(LogMode::Quiet, false, OutputFormat::Text);
+        logger.event(Event::BatchEntry {
+            line: 1,
+            url: "https://example.com/series/a",
+            series: Some("A"),
+            error: None,
+        });
+        assert!(logger.finish(None));
+        logger.event(Event::BatchEntry {
+            line: 2,
+            url: "https://example.com/series/b",
+            series: None,
+            error: Some("Scrape error: Manga name not found".to_string()),
+        });
+        assert!(!logger.finish(None));
+    }
+}
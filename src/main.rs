@@ -1,31 +1,106 @@
+mod backup;
+mod batch;
 #[cfg(feature = "bench")]
 mod bench;
 mod cli;
 mod config;
 mod db;
+mod dedupe;
 mod downloader;
 mod error;
+mod export;
+mod filter;
+mod layout;
+mod library;
 mod logger;
 mod models;
+mod plan;
+mod process;
 mod scrape;
+mod tui;
 mod utils;
 
-use std::time::Duration;
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::Duration,
+};
 
+use cli::{Command, ConfigCommand};
+use config::{CliOverrides, Config};
+use downloader::BatchJob;
 use error::MgdlResult;
+use logger::{Event, Logger};
 
 #[tokio::main]
 async fn main() {
-    if let Err(err) = run().await {
-        eprintln!("Fatal error: {}", err);
+    let args = cli::parse();
+    // the terminal UI shows the downloader's events itself
+    let (ui, updates) = mpsc::channel();
+    let logger = Arc::new(match args.command {
+        Some(Command::Tui) => Logger::for_ui(ui.clone()),
+        _ => Logger::new(args.log, args.verbose, args.output),
+    });
+    let result = run(args, Arc::clone(&logger), ui, updates).await;
+    if !logger.finish(result.as_ref().err()) {
         std::process::exit(1);
     }
 }
 
-async fn run() -> MgdlResult<()> {
-    let args = cli::parse();
-    let config = config::Config::load()?;
-    let base_url = config.base_url;
+async fn run(
+    args: cli::Args,
+    logger: Arc<Logger>,
+    ui: Sender<tui::Update>,
+    updates: Receiver<tui::Update>,
+) -> MgdlResult<()> {
+    let Some(command) = args.command else {
+        return cli::print_help();
+    };
+    let overrides = CliOverrides {
+        config: args.config,
+        manga_dir: args.manga_dir,
+    };
+    // these work without a usable config
+    match command {
+        Command::Init { force } => {
+            let path = config::init(&overrides, force)?;
+            logger.success(format!(
+                "Wrote a starter config to {}; set base_url in it before downloading",
+                path.display()
+            ));
+            return Ok(());
+        }
+        Command::Config {
+            command: ConfigCommand::Show,
+        } => {
+            for setting in Config::resolve(&overrides)?.settings {
+                logger.event(Event::Setting {
+                    name: setting.name,
+                    value: &setting.value,
+                    source: setting.source.to_string(),
+                });
+            }
+            return Ok(());
+        }
+        // before anything opens the DB, which may be what needs restoring
+        Command::Restore { file } => {
+            let config = Config::resolve(&overrides)?;
+            let source = config
+                .backups()
+                .restore(&config.db_path(), file.as_deref())?;
+            logger.success(format!("Restored local DB from {}", source.display()));
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let mut config = Config::load(&overrides)?;
+    if let Some(storage) = command.storage() {
+        config.series.overrides.pack = storage.pack;
+        config.series.overrides.profile = storage.profile.clone();
+    }
 
     let client = reqwest::Client::builder()
         .timeout(Duration::from_secs(30))
@@ -40,54 +115,105 @@ async fn run() -> MgdlResult<()> {
 
     #[cfg(feature = "bench")]
     let config_dir = config.db_dir.clone();
+    #[cfg(feature = "bench")]
+    let base_url = config.base_url.clone();
 
-    let dldr = downloader::Downloader::new(
-        config.manga_dir,
-        config.db_dir,
-        base_url.clone(),
-        args.log,
-        args.verbose,
+    let build = if command.dry_run() {
+        downloader::Downloader::planner
+    } else {
+        downloader::Downloader::new
+    };
+    let mut dldr = build(
+        config,
+        logger,
         client.clone(),
         #[cfg(feature = "bench")]
         bench.clone(),
     )?;
 
-    if args.reset {
-        dldr.reset_db()?;
-    } else if args.consolidate {
-        dldr.consolidate_all().await?;
-    } else if let Some(manga_url) = args.add {
-        dldr.add(&manga_url).await?;
-    } else if let Some(manga_url) = args.download {
-        let manga = dldr
-            .download_manga(&manga_url, args.chapters.as_ref(), args.force)
-            .await?;
-        #[cfg(feature = "bench")]
-        if let Some(bench) = bench {
-            let report = bench.finish(&manga.name);
-            report.print_summary();
-            report.write_json(&config_dir);
+    match command {
+        Command::Update {
+            name,
+            dry_run: true,
+            ..
+        } => {
+            dldr.plan_update(name.as_deref()).await?;
+        }
+        Command::Consolidate { dry_run: true, .. } => {
+            dldr.plan_consolidate().await?;
         }
-        let _ = manga;
-    } else if let Some(manga) = args.update {
-        if let Some(manga_name) = manga {
-            match manga_name.as_str() {
-                "all" => dldr.update_all().await?,
-                manga_name => {
-                    dldr.update(manga_name).await?;
+        Command::Add { urls } => match urls.single() {
+            Some(url) => {
+                dldr.add(url).await?;
+            }
+            None => dldr.run_batch(&urls.read_list()?, BatchJob::Add).await?,
+        },
+        Command::Download {
+            urls,
+            chapters,
+            force,
+            dry_run,
+            ..
+        } => match urls.single() {
+            None => {
+                let job = BatchJob::Download {
+                    chapters: chapters.as_ref(),
+                    force,
+                    dry_run,
+                };
+                dldr.run_batch(&urls.read_list()?, job).await?;
+            }
+            Some(url) if dry_run => {
+                dldr.plan_download(url, chapters.as_ref(), force).await?;
+            }
+            Some(url) => {
+                let manga = dldr.download_manga(url, chapters.as_ref(), force).await?;
+                #[cfg(feature = "bench")]
+                if let Some(bench) = bench {
+                    let report = bench.finish(&manga.name);
+                    report.print_summary();
+                    report.write_json(&config_dir);
                 }
-            };
+                let _ = manga;
+            }
+        },
+        Command::Update { name: None, .. } => dldr.update_all().await?,
+        Command::Update {
+            name: Some(name), ..
+        } => dldr.update(&name).await?,
+        Command::Consolidate { .. } => dldr.consolidate_all().await?,
+        Command::Export {
+            name,
+            format,
+            chapters,
+            by_volume,
+            out,
+        } => {
+            dldr.export(&name, format, chapters.as_ref(), by_volume, &out)?;
         }
-    } else if let Some(manga_url) = args.scrape {
-        #[cfg(feature = "bench")]
-        scrape::scrape_to_csv(&client, &base_url, &manga_url, None).await?;
-        #[cfg(not(feature = "bench"))]
-        {
-            let _ = manga_url;
-            eprintln!("scrape-to-csv requires the 'bench' feature: cargo run --features bench -- -s <URL>");
+        Command::Scrape { url } => {
+            #[cfg(feature = "bench")]
+            scrape::scrape_to_csv(&client, &base_url, &url, None).await?;
+            #[cfg(not(feature = "bench"))]
+            {
+                let _ = url;
+                eprintln!("scrape-to-csv requires the 'bench' feature: cargo run --features bench -- scrape <URL>");
+            }
+        }
+        Command::Reset => dldr.reset_db()?,
+        Command::Backup => dldr.backup_db()?,
+        Command::Scan => dldr.scan()?,
+        Command::Relayout => dldr.relayout()?,
+        Command::Relink { name, folder } => dldr.relink(&name, &folder)?,
+        Command::Block { pages, global } => dldr.block(&pages, global)?,
+        Command::Dedupe => {
+            dldr.dedupe()?;
+        }
+        Command::Prune => dldr.prune()?,
+        Command::Tui => tui::run(&dldr, ui, updates).await?,
+        Command::Init { .. } | Command::Config { .. } | Command::Restore { .. } => {
+            unreachable!("handled above")
         }
-    } else {
-        cli::print_help()?;
     }
 
     Ok(())
@@ -1,9 +1,14 @@
-use std::fmt;
+use serde::Deserialize;
+use std::{cmp::Ordering, fmt};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Chapter {
     pub hash: String,
     pub number: String,
+    /// Release date as `YYYY-MM-DD`, when the site lists one.
+    pub released: Option<String>,
+    /// Volume the chapter belongs to, when the site lists one.
+    pub volume: Option<u32>,
 }
 
 impl Chapter {
@@ -11,55 +16,445 @@ impl Chapter {
         Self {
             hash: hash.to_string(),
             number: number.to_string(),
+            released: None,
+            volume: None,
         }
     }
 
     pub fn major_number(&self) -> Option<usize> {
-        self.number.split('-').next()?.parse().ok()
+        major_number(&self.number)
     }
 }
 
-#[derive(Debug, Clone)]
+/// Prefix of the stored number of a chapter without one.
+const SPECIAL_PREFIX: &str = "special-";
+
+/// A chapter number as the site labels it: whole (`5`), with a decimal part
+/// kept as written (`5.1`, `5.10`), with a letter suffix (`10a`), or a special
+/// chapter without a number (`Extra`, `Oneshot`).
+///
+/// It is stored as a key that sorts like the numbers do: `0005`, `0005.1`,
+/// `0005.10`, `0010a`, `special-extra`. Keys of the old `NNNN-NN` scheme,
+/// where `-01` marked a whole chapter, still read back.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ChapterNumber {
+    Numbered {
+        major: u32,
+        /// digits after the point, as written
+        minor: Option<String>,
+        /// lowercase letters after the number
+        suffix: String,
+    },
+    /// lowercase words joined by `-`, starting with a letter
+    Special(String),
+}
+
+impl ChapterNumber {
+    /// A chapter label such as `5`, `5.10`, `10A` or `Extra`, or a number as
+    /// rendered in a file name (`0005.5`). Labels starting with a digit must
+    /// be a number.
+    pub fn parse(label: &str) -> Option<Self> {
+        let label = label.trim().to_lowercase();
+        if label.starts_with(|c: char| c.is_ascii_digit()) {
+            return Self::numbered(&label);
+        }
+        let words: Vec<&str> = label
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+        match words.first() {
+            Some(first) if first.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                Some(ChapterNumber::Special(words.join("-")))
+            }
+            _ => None,
+        }
+    }
+
+    /// `5`, `5.10`, `10a`.
+    fn numbered(s: &str) -> Option<Self> {
+        let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let major = s[..digits].parse().ok()?;
+        let mut rest = &s[digits..];
+        let mut minor = None;
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(after.len());
+            if end == 0 {
+                return None;
+            }
+            minor = Some(after[..end].to_string());
+            rest = &after[end..];
+        }
+        if !rest.bytes().all(|b| b.is_ascii_lowercase()) {
+            return None;
+        }
+        Some(ChapterNumber::Numbered {
+            major,
+            minor,
+            suffix: rest.to_string(),
+        })
+    }
+
+    /// A stored number, in the current scheme or the old `NNNN-NN` one.
+    pub fn from_key(key: &str) -> Option<Self> {
+        if let Some(slug) = key.strip_prefix(SPECIAL_PREFIX) {
+            return Self::parse(slug).filter(|n| matches!(n, ChapterNumber::Special(_)));
+        }
+        if let Some((major, minor)) = key.split_once('-') {
+            let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+            if !is_digits(major) || !is_digits(minor) {
+                return None;
+            }
+            let minor: u32 = minor.parse().ok()?;
+            return Some(ChapterNumber::Numbered {
+                major: major.parse().ok()?,
+                minor: (minor != 1).then(|| minor.to_string()),
+                suffix: String::new(),
+            });
+        }
+        Self::numbered(key)
+    }
+
+    /// The key the chapter is stored and found on disk under.
+    pub fn key(&self) -> String {
+        match self {
+            ChapterNumber::Numbered { .. } => self.padded(4),
+            ChapterNumber::Special(slug) => format!("{SPECIAL_PREFIX}{slug}"),
+        }
+    }
+
+    /// The number with its whole part zero-padded to `width`; specials are
+    /// their name.
+    pub fn padded(&self, width: usize) -> String {
+        match self {
+            ChapterNumber::Numbered {
+                major,
+                minor,
+                suffix,
+            } => match minor {
+                Some(minor) => format!("{major:0width$}.{minor}{suffix}"),
+                None => format!("{major:0width$}{suffix}"),
+            },
+            ChapterNumber::Special(slug) => slug.clone(),
+        }
+    }
+
+    pub fn major(&self) -> Option<u32> {
+        match self {
+            ChapterNumber::Numbered { major, .. } => Some(*major),
+            ChapterNumber::Special(_) => None,
+        }
+    }
+}
+
+/// Numbers in decimal order, a decimal part written longer after an equal
+/// shorter one (`5.1` < `5.10` < `5.5`), then by suffix; specials last.
+impl Ord for ChapterNumber {
+    fn cmp(&self, other: &Self) -> Ordering {
+        use ChapterNumber::*;
+        match (self, other) {
+            (
+                Numbered {
+                    major,
+                    minor,
+                    suffix,
+                },
+                Numbered {
+                    major: other_major,
+                    minor: other_minor,
+                    suffix: other_suffix,
+                },
+            ) => major
+                .cmp(other_major)
+                .then_with(|| {
+                    // digits compare as a fraction; a whole chapter has none
+                    let a = minor.as_deref().unwrap_or("");
+                    let b = other_minor.as_deref().unwrap_or("");
+                    let width = a.len().max(b.len());
+                    format!("{a:0<width$}")
+                        .cmp(&format!("{b:0<width$}"))
+                        .then(a.len().cmp(&b.len()))
+                })
+                .then_with(|| suffix.cmp(other_suffix)),
+            (Numbered { .. }, Special(_)) => Ordering::Less,
+            (Special(_), Numbered { .. }) => Ordering::Greater,
+            (Special(a), Special(b)) => a.cmp(b),
+        }
+    }
+}
+
+impl PartialOrd for ChapterNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// `5`, `5.10`, `10a`, `extra`.
+impl fmt::Display for ChapterNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.padded(0))
+    }
+}
+
+/// Order of two stored chapter numbers; ones that don't parse go last.
+pub fn cmp_chapter_keys(a: &str, b: &str) -> Ordering {
+    match (ChapterNumber::from_key(a), ChapterNumber::from_key(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+/// Whole part of a stored chapter number: `0005.5` -> 5.
+pub fn major_number(number: &str) -> Option<usize> {
+    Some(ChapterNumber::from_key(number)?.major()? as usize)
+}
+
+/// What a chapter selection looks at.
+pub trait Selectable {
+    /// stored chapter number, e.g. `0005.5`
+    fn number(&self) -> &str;
+    /// release date as `YYYY-MM-DD`, when known
+    fn released(&self) -> Option<&str>;
+}
+
+impl Selectable for Chapter {
+    fn number(&self) -> &str {
+        &self.number
+    }
+
+    fn released(&self) -> Option<&str> {
+        self.released.as_deref()
+    }
+}
+
+/// One end of a chapter number range. A whole number covers the chapter's
+/// decimal parts too, so `..10` includes 10.5.
+#[derive(Debug, Clone, PartialEq)]
+struct Bound {
+    number: ChapterNumber,
+    whole: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Numbers {
+        start: Option<Bound>,
+        end: Option<Bound>,
+    },
+    /// release dates, inclusive, compared as `YYYY-MM-DD` strings
+    Released {
+        start: Option<String>,
+        end: Option<String>,
+    },
+    /// the N highest chapter numbers
+    Latest(usize),
+}
+
+impl Term {
+    fn parse(s: &str) -> Result<Self, String> {
+        if let Some(count) = s.strip_prefix("latest:") {
+            return match count.trim().parse::<usize>() {
+                Ok(0) | Err(_) => Err(format!("`{s}`: latest:N needs a positive count")),
+                Ok(count) => Ok(Term::Latest(count)),
+            };
+        }
+        let (start, end) = s.split_once("..").unwrap_or((s, s));
+        let (start, end) = (start.trim(), end.trim());
+        if start.is_empty() && end.is_empty() {
+            return Err(format!("`{s}` needs a start or an end"));
+        }
+        if [start, end].iter().any(|bound| is_date(bound)) {
+            let date = |bound: &str| match bound {
+                "" => Ok(None),
+                bound if is_date(bound) => Ok(Some(bound.to_string())),
+                bound => Err(format!("`{bound}` is not a date (YYYY-MM-DD)")),
+            };
+            let (start, end) = (date(start)?, date(end)?);
+            if let (Some(start), Some(end)) = (&start, &end) {
+                if start > end {
+                    return Err(format!("`{s}` ends before it starts"));
+                }
+            }
+            return Ok(Term::Released { start, end });
+        }
+        let (start, end) = (parse_bound(start)?, parse_bound(end)?);
+        if let (Some(start), Some(end)) = (&start, &end) {
+            if start.number > end.number {
+                return Err(format!("`{s}` ends before it starts"));
+            }
+        }
+        Ok(Term::Numbers { start, end })
+    }
+
+    fn matches(&self, chapter: &Candidate) -> bool {
+        match self {
+            Term::Numbers { start, end } => chapter.number.as_ref().is_some_and(|number| {
+                start.as_ref().is_none_or(|start| *number >= start.number)
+                    && end.as_ref().is_none_or(|end| match end.whole {
+                        true => number.major() <= end.number.major(),
+                        false => *number <= end.number,
+                    })
+            }),
+            Term::Released { start, end } => chapter
+                .released
+                .and_then(|date| date.get(..10))
+                .is_some_and(|date| {
+                    start.as_deref().is_none_or(|start| date >= start)
+                        && end.as_deref().is_none_or(|end| date <= end)
+                }),
+            Term::Latest(count) => {
+                let newest = chapter.newest;
+                let lowest = newest.get(count - 1).or(newest.last());
+                chapter
+                    .number
+                    .as_ref()
+                    .zip(lowest)
+                    .is_some_and(|(number, lowest)| number >= lowest)
+            }
+        }
+    }
+}
+
+/// A chapter as a selection sees it.
+struct Candidate<'a> {
+    /// `None` for specials
+    number: Option<ChapterNumber>,
+    released: Option<&'a str>,
+    /// distinct chapter numbers of the whole list, highest first
+    newest: &'a [ChapterNumber],
+}
+
+fn parse_bound(s: &str) -> Result<Option<Bound>, String> {
+    if s.is_empty() {
+        return Ok(None);
+    }
+    match ChapterNumber::parse(s) {
+        Some(number @ ChapterNumber::Numbered { .. }) => Ok(Some(Bound {
+            whole: !s.contains('.'),
+            number,
+        })),
+        _ => Err(format!(
+            "`{s}` is not a chapter number, a date (YYYY-MM-DD) or latest:N"
+        )),
+    }
+}
+
+fn is_date(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    let shaped = bytes.len() == 10
+        && bytes.iter().enumerate().all(|(i, b)| match i {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        });
+    shaped
+        && s[5..7]
+            .parse()
+            .is_ok_and(|month: u32| (1..=12).contains(&month))
+        && s[8..10]
+            .parse()
+            .is_ok_and(|day: u32| (1..=31).contains(&day))
+}
+
+/// A chapter selection: comma-separated terms, each a chapter number (`5`,
+/// `5.5`), a range (`5..10`, `5..`, `..10`), `latest:N`, or a release date
+/// or date range (`2025-06-01..`). A chapter is selected if it matches any
+/// term, or there are only exclusions; terms prefixed with `!` exclude.
+#[derive(Debug, Clone, PartialEq)]
 pub struct ChapterRange {
-    pub start: Option<usize>,
-    pub end: Option<usize>,
+    include: Vec<Term>,
+    exclude: Vec<Term>,
 }
 
 impl ChapterRange {
     pub fn parse(s: &str) -> Result<Self, String> {
-        if let Some((start, end)) = s.split_once("..") {
-            let start = if start.is_empty() {
-                None
-            } else {
-                Some(start.parse::<usize>().map_err(|e| e.to_string())?)
-            };
-            let end = if end.is_empty() {
-                None
-            } else {
-                Some(end.parse::<usize>().map_err(|e| e.to_string())?)
-            };
-            Ok(Self { start, end })
-        } else {
-            let n = s.parse::<usize>().map_err(|e| e.to_string())?;
-            Ok(Self {
-                start: Some(n),
-                end: Some(n),
-            })
+        let mut range = Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+        };
+        for term in s.split(',').map(str::trim) {
+            if term.is_empty() {
+                return Err(format!("empty term in `{s}`"));
+            }
+            match term.strip_prefix('!') {
+                Some(excluded) => range.exclude.push(Term::parse(excluded.trim())?),
+                None => range.include.push(Term::parse(term)?),
+            }
         }
+        Ok(range)
+    }
+
+    /// Whether the selection only uses chapter numbers, so it can be checked
+    /// one chapter at a time with `contains`.
+    pub fn is_numeric(&self) -> bool {
+        self.include
+            .iter()
+            .chain(&self.exclude)
+            .all(|term| matches!(term, Term::Numbers { .. }))
     }
 
+    /// Whether chapter number `chapter` is selected, by its number alone.
+    /// A number too large for any bound is above all of them.
     pub fn contains(&self, chapter: usize) -> bool {
-        self.start.is_none_or(|s| chapter >= s) && self.end.is_none_or(|e| chapter <= e)
+        let Ok(major) = u32::try_from(chapter) else {
+            let above = |term: &Term| matches!(term, Term::Numbers { end: None, .. });
+            return (self.include.is_empty() || self.include.iter().any(above))
+                && !self.exclude.iter().any(above);
+        };
+        self.matches(&Candidate {
+            number: Some(ChapterNumber::Numbered {
+                major,
+                minor: None,
+                suffix: String::new(),
+            }),
+            released: None,
+            newest: &[],
+        })
+    }
+
+    fn matches(&self, chapter: &Candidate) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|term| term.matches(chapter)))
+            && !self.exclude.iter().any(|term| term.matches(chapter))
+    }
+
+    /// The selected chapters, in their order. `latest:N` counts from the
+    /// highest numbers among `chapters`.
+    pub fn select<T: Selectable>(&self, chapters: Vec<T>) -> Vec<T> {
+        let numbers: Vec<Option<ChapterNumber>> = chapters
+            .iter()
+            .map(|ch| ChapterNumber::from_key(ch.number()).filter(|n| n.major().is_some()))
+            .collect();
+        let mut newest: Vec<ChapterNumber> = numbers.iter().flatten().cloned().collect();
+        newest.sort_by(|a, b| b.cmp(a));
+        newest.dedup();
+
+        chapters
+            .into_iter()
+            .zip(numbers)
+            .filter(|(chapter, number)| {
+                self.matches(&Candidate {
+                    number: number.clone(),
+                    released: chapter.released(),
+                    newest: &newest,
+                })
+            })
+            .map(|(chapter, _)| chapter)
+            .collect()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Manga {
     pub hash: String,
     pub name: String,
     pub normalized_name: String,
     pub authors: String,
     pub status: String,
+    /// Set when the manga's folder went missing; detached manga are skipped
+    /// by `update` until relinked or pruned.
+    pub detached: bool,
 }
 
 impl Manga {
@@ -70,6 +465,7 @@ impl Manga {
             normalized_name: normalized_name.to_string(),
             authors: authors.to_string(),
             status: status.to_string(),
+            detached: false,
         }
     }
 }
@@ -84,6 +480,44 @@ impl fmt::Display for Manga {
     }
 }
 
+/// A chapter as recorded on disk: its number, how many pages it holds and
+/// its volume, if known.
+#[derive(Debug, PartialEq)]
+pub struct LocalChapter {
+    pub number: String,
+    pub pages: usize,
+    pub volume: Option<u32>,
+}
+
+/// Chapters on disk have no release date, so date terms never match them.
+impl Selectable for LocalChapter {
+    fn number(&self) -> &str {
+        &self.number
+    }
+
+    fn released(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// The order a series' pages are read in.
+#[derive(Clone, Copy, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadingDirection {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+impl ReadingDirection {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReadingDirection::Ltr => "ltr",
+            ReadingDirection::Rtl => "rtl",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Page {
     pub url: String,
@@ -97,29 +531,35 @@ mod tests {
     #[test]
     fn chapter_range_full_range() {
         let r = ChapterRange::parse("5..10").unwrap();
-        assert_eq!(r.start, Some(5));
-        assert_eq!(r.end, Some(10));
+        assert!(!r.contains(4));
+        assert!(r.contains(5));
+        assert!(r.contains(10));
+        assert!(!r.contains(11));
     }
 
     #[test]
     fn chapter_range_open_end() {
         let r = ChapterRange::parse("5..").unwrap();
-        assert_eq!(r.start, Some(5));
-        assert_eq!(r.end, None);
+        assert!(!r.contains(4));
+        assert!(r.contains(usize::MAX));
+        assert!(r.contains((1 << 32) + 3));
     }
 
     #[test]
     fn chapter_range_open_start() {
         let r = ChapterRange::parse("..10").unwrap();
-        assert_eq!(r.start, None);
-        assert_eq!(r.end, Some(10));
+        assert!(r.contains(0));
+        assert!(!r.contains(11));
+        // wrapped to u32 this would be chapter 3
+        assert!(!r.contains((1 << 32) + 3));
     }
 
     #[test]
     fn chapter_range_single() {
         let r = ChapterRange::parse("5").unwrap();
-        assert_eq!(r.start, Some(5));
-        assert_eq!(r.end, Some(5));
+        assert!(r.contains(5));
+        assert!(!r.contains(4) && !r.contains(6));
+        assert!(!r.contains((1 << 32) + 5));
     }
 
     #[test]
@@ -156,21 +596,21 @@ mod tests {
 
     #[test]
     fn chapter_major_number() {
-        let ch = Chapter::new("hash", "0010-01");
+        let ch = Chapter::new("hash", "0010");
         assert_eq!(ch.major_number(), Some(10));
     }
 
     #[test]
     fn chapter_major_number_decimal() {
-        let ch = Chapter::new("hash", "0005-05");
+        let ch = Chapter::new("hash", "0005.5");
         assert_eq!(ch.major_number(), Some(5));
     }
 
     #[test]
     fn chapter_new_stores_fields() {
-        let ch = Chapter::new("abc123", "0001-01");
+        let ch = Chapter::new("abc123", "0001");
         assert_eq!(ch.hash, "abc123");
-        assert_eq!(ch.number, "0001-01");
+        assert_eq!(ch.number, "0001");
     }
 
     #[test]
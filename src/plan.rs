@@ -0,0 +1,127 @@
+use indicatif::HumanBytes;
+use serde::Serialize;
+
+/// What downloading one chapter would do.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct ChapterPlan {
+    #[serde(rename = "chapter")]
+    pub number: String,
+    pub to_fetch: usize,
+    pub present: usize,
+    /// summed sizes of the pages to fetch that the server reported
+    pub bytes: u64,
+    /// pages to fetch whose size the server didn't report
+    pub unknown_size: usize,
+}
+
+/// What downloading a series would do, chapter by chapter.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct SeriesPlan {
+    #[serde(rename = "series")]
+    pub name: String,
+    pub chapters: Vec<ChapterPlan>,
+}
+
+impl SeriesPlan {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            chapters: Vec::new(),
+        }
+    }
+
+    pub fn to_fetch(&self) -> usize {
+        self.chapters.iter().map(|ch| ch.to_fetch).sum()
+    }
+
+    pub fn present(&self) -> usize {
+        self.chapters.iter().map(|ch| ch.present).sum()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.chapters.iter().map(|ch| ch.bytes).sum()
+    }
+
+    pub fn unknown_size(&self) -> usize {
+        self.chapters.iter().map(|ch| ch.unknown_size).sum()
+    }
+
+    /// One line per chapter with pages to fetch, then the series total.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .chapters
+            .iter()
+            .filter(|ch| ch.to_fetch > 0)
+            .map(|ch| {
+                format!(
+                    "  ch.{}: {}",
+                    ch.number,
+                    counts(ch.to_fetch, ch.present, ch.bytes, ch.unknown_size)
+                )
+            })
+            .collect();
+        lines.push(format!(
+            "{}: {} chapters, {}",
+            self.name,
+            self.chapters.iter().filter(|ch| ch.to_fetch > 0).count(),
+            counts(
+                self.to_fetch(),
+                self.present(),
+                self.bytes(),
+                self.unknown_size()
+            )
+        ));
+        lines
+    }
+}
+
+/// `12 to fetch (~3.4 MiB), 8 present`; the estimate is marked as partial
+/// when some sizes are unknown.
+fn counts(to_fetch: usize, present: usize, bytes: u64, unknown_size: usize) -> String {
+    let estimate = match (to_fetch, unknown_size) {
+        (0, _) => String::new(),
+        (_, 0) => format!(" (~{})", HumanBytes(bytes)),
+        (_, unknown_size) if unknown_size == to_fetch => " (size unknown)".to_string(),
+        (_, unknown_size) => format!(" (~{} + {unknown_size} of unknown size)", HumanBytes(bytes)),
+    };
+    format!("{to_fetch} to fetch{estimate}, {present} present")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(
+        number: &str,
+        to_fetch: usize,
+        present: usize,
+        bytes: u64,
+        unknown_size: usize,
+    ) -> ChapterPlan {
+        ChapterPlan {
+            number: number.to_string(),
+            to_fetch,
+            present,
+            bytes,
+            unknown_size,
+        }
+    }
+
+    #[test]
+    fn lines_skip_complete_chapters_and_total() {
+        let mut plan = SeriesPlan::new("My Manga");
+        plan.chapters = vec![
+            chapter("0001", 0, 20, 0, 0),
+            chapter("0002", 18, 2, 2048, 0),
+            chapter("0003", 20, 0, 0, 20),
+        ];
+        assert_eq!(
+            plan.lines(),
+            vec![
+                "  ch.0002: 18 to fetch (~2.00 KiB), 2 present",
+                "  ch.0003: 20 to fetch (size unknown), 0 present",
+                "My Manga: 2 chapters, 38 to fetch (~2.00 KiB + 20 of unknown size), 22 present",
+            ]
+        );
+    }
+}
@@ -0,0 +1,314 @@
+use image::{
+    codecs::{avif::AvifEncoder, jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    DynamicImage, GenericImageView, GrayImage, ImageFormat, ImageReader,
+};
+use serde::Deserialize;
+use std::{
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{MgdlError, MgdlResult},
+    models::ReadingDirection,
+};
+
+/// Folder inside a series folder where originals of processed pages are kept,
+/// under the same chapter path.
+pub const ORIGINALS_DIR: &str = ".originals";
+/// Profile name that turns processing off, e.g. for one series when a
+/// default profile is set.
+pub const NO_PROFILE: &str = "none";
+
+const DEFAULT_QUALITY: u8 = 85;
+/// ravif speed, 1 (slowest, smallest) to 10.
+const AVIF_SPEED: u8 = 6;
+/// Pages taller than this many screens are strips to split.
+const STRIP_SCREENS: u32 = 2;
+/// Most a row's luma may vary for it to count as a gap between panels.
+const SOLID_ROW_TOLERANCE: u8 = 8;
+
+/// Image format processed pages are written in.
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PageFormat {
+    Jpeg,
+    Png,
+    Webp,
+    Avif,
+}
+
+impl PageFormat {
+    fn from_image_format(format: ImageFormat) -> Option<Self> {
+        match format {
+            ImageFormat::Jpeg => Some(PageFormat::Jpeg),
+            ImageFormat::Png => Some(PageFormat::Png),
+            ImageFormat::WebP => Some(PageFormat::Webp),
+            ImageFormat::Avif => Some(PageFormat::Avif),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            PageFormat::Jpeg => "jpg",
+            PageFormat::Png => "png",
+            PageFormat::Webp => "webp",
+            PageFormat::Avif => "avif",
+        }
+    }
+}
+
+/// How downloaded pages are post-processed, from a `[profiles.<name>]` table
+/// of `config.toml`. Unset fields leave that aspect of the page alone.
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// convert pages to this format
+    pub format: Option<PageFormat>,
+    /// shrink pages to fit this width, keeping the aspect ratio
+    pub width: Option<u32>,
+    /// shrink pages to fit this height, keeping the aspect ratio
+    pub height: Option<u32>,
+    pub grayscale: bool,
+    /// contrast change in percent; negative values lower it
+    pub contrast: f32,
+    /// JPEG and AVIF quality, 1 to 100; WebP is always lossless
+    pub quality: Option<u8>,
+    /// cut strips taller than two screens into screen-sized pages; the
+    /// screen is `width` x `height`, or `height` pixels tall
+    pub split_tall: bool,
+    /// split landscape pages into their two halves, in reading order
+    pub split_spreads: bool,
+    /// keep the downloaded page under `.originals/`
+    pub keep_original: bool,
+}
+
+/// A page after processing: one image, or the slices of a split page in
+/// reading order.
+pub struct Processed {
+    pub format: PageFormat,
+    pub pages: Vec<Vec<u8>>,
+}
+
+impl Profile {
+    pub fn validate(&self, name: &str) -> MgdlResult<()> {
+        let invalid = |what: &str| {
+            Err(MgdlError::Config(format!(
+                "Profile {name}: {what} must be positive"
+            )))
+        };
+        if self.width == Some(0) {
+            return invalid("width");
+        }
+        if self.height == Some(0) {
+            return invalid("height");
+        }
+        if self.split_tall && self.height.is_none() {
+            return Err(MgdlError::Config(format!(
+                "Profile {name}: split_tall needs a height"
+            )));
+        }
+        if matches!(self.quality, Some(q) if q == 0 || q > 100) {
+            return Err(MgdlError::Config(format!(
+                "Profile {name}: quality must be between 1 and 100"
+            )));
+        }
+        if self.format == Some(PageFormat::Webp) && self.quality.is_some() {
+            return Err(MgdlError::Config(format!(
+                "Profile {name}: webp is lossless and takes no quality"
+            )));
+        }
+        Ok(())
+    }
+
+    fn fits(&self, width: u32, height: u32) -> bool {
+        width <= self.width.unwrap_or(u32::MAX) && height <= self.height.unwrap_or(u32::MAX)
+    }
+
+    /// Height of a screen in pixels of a page `width` wide, so that slices
+    /// fill the screen once fit to it.
+    fn screen_height(&self, width: u32) -> Option<u32> {
+        let height = self.height?;
+        let screen = match self.width {
+            Some(screen_width) => (width as u64 * height as u64 / screen_width as u64) as u32,
+            None => height,
+        };
+        Some(screen.max(1))
+    }
+
+    /// Process an encoded page of a series read in `direction`. `None` when
+    /// the page already matches the profile and is left as downloaded; a
+    /// `Process` error when it can't be decoded, as with AVIF.
+    pub fn apply(
+        &self,
+        bytes: &[u8],
+        direction: ReadingDirection,
+    ) -> MgdlResult<Option<Processed>> {
+        let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+        let source = reader
+            .format()
+            .ok_or_else(|| MgdlError::Process("Unrecognized image format".to_string()))?;
+        // the avif feature only brings an encoder
+        if source == ImageFormat::Avif || !source.reading_enabled() {
+            return Err(MgdlError::Process(format!(
+                "Can't decode {} pages",
+                source.extensions_str()[0]
+            )));
+        }
+        // formats we can't write, such as GIF, become PNG
+        let target = self
+            .format
+            .or_else(|| PageFormat::from_image_format(source))
+            .unwrap_or(PageFormat::Png);
+        let (width, height) = reader.into_dimensions()?;
+        let spread = self.split_spreads && width > height;
+        let screen = self
+            .screen_height(width)
+            .filter(|screen| self.split_tall && height > screen * STRIP_SCREENS);
+        let unchanged = Some(target) == PageFormat::from_image_format(source)
+            && !self.grayscale
+            && self.contrast == 0.0
+            && self.quality.is_none();
+        if unchanged && !spread && screen.is_none() && self.fits(width, height) {
+            return Ok(None);
+        }
+
+        let image = image::load_from_memory_with_format(bytes, source)?;
+        let images = if spread {
+            split_spread(&image, direction)
+        } else if let Some(screen) = screen {
+            split_tall(&image, screen)
+        } else {
+            vec![image]
+        };
+        let pages = images
+            .into_iter()
+            .map(|image| self.encode(self.adjust(image), target))
+            .collect::<MgdlResult<_>>()?;
+        Ok(Some(Processed {
+            format: target,
+            pages,
+        }))
+    }
+
+    fn adjust(&self, mut image: DynamicImage) -> DynamicImage {
+        if !self.fits(image.width(), image.height()) {
+            image = image.resize(
+                self.width.unwrap_or(u32::MAX),
+                self.height.unwrap_or(u32::MAX),
+                FilterType::Lanczos3,
+            );
+        }
+        if self.grayscale {
+            image = image.grayscale();
+        }
+        if self.contrast != 0.0 {
+            image = image.adjust_contrast(self.contrast);
+        }
+        image
+    }
+
+    fn encode(&self, image: DynamicImage, format: PageFormat) -> MgdlResult<Vec<u8>> {
+        let quality = self.quality.unwrap_or(DEFAULT_QUALITY);
+        let mut bytes = Vec::new();
+        match format {
+            PageFormat::Jpeg => {
+                // JPEG has no alpha channel
+                let image = if image.color().has_color() {
+                    DynamicImage::ImageRgb8(image.to_rgb8())
+                } else {
+                    DynamicImage::ImageLuma8(image.to_luma8())
+                };
+                image.write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality))?
+            }
+            PageFormat::Png => image.write_with_encoder(PngEncoder::new(&mut bytes))?,
+            PageFormat::Webp => image.write_with_encoder(WebPEncoder::new_lossless(&mut bytes))?,
+            PageFormat::Avif => image.write_with_encoder(AvifEncoder::new_with_speed_quality(
+                &mut bytes, AVIF_SPEED, quality,
+            ))?,
+        }
+        Ok(bytes)
+    }
+}
+
+/// Cut a tall strip into slices about `screen` pixels tall. Each cut goes at
+/// the lowest solid row in the last quarter of its slice, falling back to a
+/// fixed offset; a short remainder stays with the last slice.
+fn split_tall(image: &DynamicImage, screen: u32) -> Vec<DynamicImage> {
+    let luma = image.to_luma8();
+    let (width, height) = image.dimensions();
+    let mut slices = Vec::new();
+    let mut top = 0;
+    while height - top > screen + screen / 4 {
+        let bottom = top + screen;
+        let cut = (top + screen * 3 / 4..bottom)
+            .rev()
+            .find(|&y| is_solid_row(&luma, y))
+            .unwrap_or(bottom);
+        slices.push(image.crop_imm(0, top, width, cut - top));
+        top = cut;
+    }
+    slices.push(image.crop_imm(0, top, width, height - top));
+    slices
+}
+
+/// The two pages of a spread, in reading order.
+fn split_spread(image: &DynamicImage, direction: ReadingDirection) -> Vec<DynamicImage> {
+    let (width, height) = image.dimensions();
+    let left = image.crop_imm(0, 0, width / 2, height);
+    let right = image.crop_imm(width / 2, 0, width - width / 2, height);
+    match direction {
+        ReadingDirection::Ltr => vec![left, right],
+        ReadingDirection::Rtl => vec![right, left],
+    }
+}
+
+fn is_solid_row(luma: &GrayImage, y: u32) -> bool {
+    let (min, max) = (0..luma.width())
+        .map(|x| luma.get_pixel(x, y)[0])
+        .fold((u8::MAX, u8::MIN), |(min, max), v| (min.min(v), max.max(v)));
+    max - min <= SOLID_ROW_TOLERANCE
+}
+
+/// Process the page at `path` of a series read in `direction` with
+/// `profile`. Each resulting page is written to the path `target` gives for
+/// its slice number (`None` unless the page was split) and extension. The
+/// downloaded file is moved to `original` when given, else removed unless it
+/// was overwritten.
+pub fn process_page(
+    profile: &Profile,
+    direction: ReadingDirection,
+    path: &Path,
+    target: impl Fn(Option<usize>, &str) -> PathBuf,
+    original: Option<&Path>,
+) -> MgdlResult<()> {
+    let bytes = fs::read(path)?;
+    let Some(processed) = profile.apply(&bytes, direction)? else {
+        return Ok(());
+    };
+    let ext = processed.format.extension();
+    let targets: Vec<PathBuf> = match processed.pages.len() {
+        1 => vec![target(None, ext)],
+        count => (1..=count).map(|slice| target(Some(slice), ext)).collect(),
+    };
+
+    if let Some(original) = original {
+        if let Some(parent) = original.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(path, original)?;
+    } else if !targets.iter().any(|target| target == path) {
+        fs::remove_file(path)?;
+    }
+    for (target, page) in targets.iter().zip(processed.pages) {
+        // replace rather than write through a hardlink to identical pages
+        if target.is_file() {
+            fs::remove_file(target)?;
+        }
+        fs::write(target, page)?;
+    }
+    Ok(())
+}
@@ -2,7 +2,7 @@ mod html;
 
 use html::{extract_attr, extract_tag_content, find_all_tags, strip_tags};
 use reqwest::Client;
-use std::{path::PathBuf, time::Duration};
+use std::{collections::HashSet, path::PathBuf, time::Duration};
 use tokio::{fs, io::AsyncWriteExt, time::sleep};
 
 #[cfg(feature = "bench")]
@@ -10,11 +10,24 @@ use {csv::Writer, uuid::Uuid};
 
 use crate::{
     error::{MgdlError, MgdlResult},
-    models::{Chapter, Manga, Page},
-    utils::{extract_hash, normalize},
+    export::comic_info::display_number,
+    models::{Chapter, ChapterNumber, Manga, Page},
+    utils::{extract_hash, folder_name},
 };
 
-const INITIAL_DELAY: u64 = 300;
+/// Milliseconds before the first retry of a request, by default.
+pub const INITIAL_DELAY: u64 = 300;
+/// Characters of a chapter hash that tell same-named specials apart.
+const SPECIAL_TAG_LEN: usize = 6;
+
+/// How often a failed request is tried again.
+#[derive(Clone, Copy, Debug)]
+pub struct Retry {
+    /// tries in all, the first included
+    pub attempts: usize,
+    /// milliseconds before the first retry, doubled before each next one
+    pub delay: u64,
+}
 
 /// Parse page image data from pre-fetched HTML (the chapter images page).
 pub fn parse_pages_from_html(html: &str) -> MgdlResult<Vec<Page>> {
@@ -48,65 +61,166 @@ pub async fn get_chapter_pages(
     client: &Client,
     base_url: &str,
     chapter_hash: &str,
-    max_attempts: usize,
+    retry: Retry,
 ) -> MgdlResult<Vec<Page>> {
     let url = format!(
         "{}/chapters/{}/images?is_prev=False&current_page=1&reading_style=long_strip",
         base_url, chapter_hash
     );
 
-    let html = get_with_retry(client, &url, max_attempts).await?;
+    let html = get_with_retry(client, &url, retry).await?;
     parse_pages_from_html(&html)
 }
 
+/// Chapters read from a chapter list, and what was wrong with the entries
+/// left out of it.
+#[derive(Debug, Default)]
+pub struct ChapterList {
+    pub chapters: Vec<Chapter>,
+    /// one per skipped entry, e.g. `entry 3 (.../chapters/abc): ...`
+    pub warnings: Vec<String>,
+}
+
 /// Parse chapter list from pre-fetched HTML (the full-chapter-list page).
-pub fn parse_chapters_from_html(html: &str) -> MgdlResult<Vec<Chapter>> {
-    let mut chapters = Vec::new();
+/// Entries that can't be read are skipped with a warning, as are repeats of
+/// a chapter number; it fails only if there were entries and none could be
+/// read. Specials carry the end of their hash (`special-extra-j0dtfq`), so
+/// ones sharing a name, like an `Extra` in each volume, are told apart and
+/// a special's key doesn't change when another of its name is listed.
+pub fn parse_chapters_from_html(html: &str) -> MgdlResult<ChapterList> {
+    let mut list = ChapterList::default();
+    let mut seen: HashSet<&str> = HashSet::new();
+
+    let entries: Vec<_> = find_all_tags(html, "div")
+        .into_iter()
+        .filter_map(|div| {
+            let link = *find_all_tags(div, "a").first()?;
+            let href = extract_attr(link, "href")?;
+            href.contains("/chapters/").then_some((link, href))
+        })
+        .enumerate()
+        .filter(|(_, (_, href))| seen.insert(href))
+        .map(|(i, (link, href))| (i, href, parse_chapter_entry(link, href)))
+        .collect();
+    for (i, href, entry) in entries {
+        match entry {
+            Ok(mut chapter) => {
+                if let Some(ChapterNumber::Special(_)) = ChapterNumber::from_key(&chapter.number) {
+                    chapter.number = format!("{}-{}", chapter.number, special_tag(&chapter.hash));
+                }
+                match list.chapters.iter().find(|ch| ch.number == chapter.number) {
+                    Some(first) => list.warnings.push(format!(
+                        "entry {} ({href}): chapter {} is listed twice, keeping {}",
+                        i + 1,
+                        display_number(&chapter.number),
+                        first.hash
+                    )),
+                    None => list.chapters.push(chapter),
+                }
+            }
+            Err(err) => list
+                .warnings
+                .push(format!("entry {} ({href}): {err}", i + 1)),
+        }
+    }
 
-    let divs = find_all_tags(html, "div");
-    for div in divs {
-        let links = find_all_tags(div, "a");
-        let Some(link) = links.first() else {
-            continue;
-        };
+    if list.chapters.is_empty() && !list.warnings.is_empty() {
+        return Err(MgdlError::Scrape(format!(
+            "No chapter in the list could be read; {}",
+            list.warnings[0]
+        )));
+    }
+    Ok(list)
+}
 
-        let Some(href) = extract_attr(link, "href") else {
-            continue;
-        };
+/// Last characters of a chapter hash, lowercased; ULIDs start with a
+/// timestamp shared by chapters added together.
+fn special_tag(hash: &str) -> String {
+    let chars: Vec<char> = hash
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    chars[chars.len().saturating_sub(SPECIAL_TAG_LEN)..]
+        .iter()
+        .collect()
+}
+
+fn parse_chapter_entry(link: &str, href: &str) -> MgdlResult<Chapter> {
+    let hash = href
+        .split('/')
+        .next_back()
+        .filter(|hash| !hash.is_empty())
+        .ok_or(MgdlError::Scrape("Could not find chapter hash".to_string()))?;
+
+    let link_text = strip_tags(link);
+    let label = chapter_label(&link_text)
+        .ok_or(MgdlError::Scrape("Chapter number not found".to_string()))?;
+    let number = ChapterNumber::parse(label).ok_or(MgdlError::Scrape(format!(
+        "Invalid chapter number {label:?}"
+    )))?;
+
+    let mut chapter = Chapter::new(hash, &number.key());
+    chapter.volume = parse_volume(&link_text);
+    chapter.released = find_all_tags(link, "time")
+        .first()
+        .and_then(|time| extract_attr(time, "datetime"))
+        .and_then(|datetime| datetime.get(..10))
+        .map(str::to_string);
+    Ok(chapter)
+}
 
-        let hash = href
-            .split('/')
-            .next_back()
-            .ok_or(MgdlError::Scrape("Could not find chapter hash".to_string()))?;
-
-        // Extract chapter number from link text (e.g., "Chapter 18" or "Chapter 5.5")
-        let link_text = strip_tags(link);
-        let raw_number = link_text
-            .split_whitespace()
-            .skip_while(|w| *w != "Chapter")
-            .nth(1)
-            .ok_or(MgdlError::Scrape("Chapter number not found".to_string()))?
-            .to_string();
-
-        let parts: Vec<usize> = raw_number
-            .split('.')
-            .map(|s| s.parse::<usize>())
-            .collect::<Result<Vec<_>, _>>()?;
-
-        let number = match parts.as_slice() {
-            [major] => format!("{:04}-01", major),
-            [major, minor] => format!("{:04}-{:02}", major, minor),
-            _ => {
-                return Err(MgdlError::Scrape(
-                    "Invalid chapter number format".to_string(),
-                ))
+/// Chapter label from link text: the word after `Chapter` (`Chapter 5.5`,
+/// `Chapter 10a`, `Chapter Extra`), or else the first word that isn't part of
+/// a volume (`Oneshot`, `Vol. 2 Extra`).
+fn chapter_label(link_text: &str) -> Option<&str> {
+    let mut words = link_text.split_whitespace();
+    let after_chapter = words
+        .clone()
+        .skip_while(|w| *w != "Chapter")
+        .nth(1)
+        .filter(|w| *w != "-");
+    let label = after_chapter.or_else(|| {
+        while let Some(word) = words.next() {
+            let lower = word.to_ascii_lowercase();
+            let volume = lower
+                .strip_prefix("volume")
+                .or_else(|| lower.strip_prefix("vol"))
+                .map(|rest| rest.trim_start_matches('.'));
+            match volume {
+                Some("") => {
+                    words.next();
+                }
+                Some(rest) if rest.bytes().all(|b| b.is_ascii_digit()) => {}
+                _ if word == "-" || word == "Chapter" => {}
+                _ => return Some(word),
             }
-        };
+        }
+        None
+    })?;
+    Some(label.trim_end_matches([',', ':']))
+}
 
-        chapters.push(Chapter::new(hash, &number));
+/// Volume from chapter link text such as `Vol. 3 Chapter 18` or
+/// `Volume 3 - Chapter 18`.
+fn parse_volume(link_text: &str) -> Option<u32> {
+    let mut words = link_text.split_whitespace();
+    while let Some(word) = words.next() {
+        let lower = word.to_ascii_lowercase();
+        let Some(rest) = lower
+            .strip_prefix("volume")
+            .or_else(|| lower.strip_prefix("vol"))
+        else {
+            continue;
+        };
+        // `Volumes`, `Volcano`: not a volume number, keep looking
+        let rest = rest.trim_start_matches('.');
+        let number = if rest.is_empty() { words.next()? } else { rest };
+        if let Ok(volume) = number.trim_end_matches([',', ':', '-']).parse() {
+            return Some(volume);
+        }
     }
-
-    Ok(chapters)
+    None
 }
 
 /// Parse manga metadata from pre-fetched HTML (the manga series page).
@@ -116,12 +230,11 @@ pub fn parse_manga_from_html(html: &str, url: &str) -> MgdlResult<Manga> {
         .ok_or(MgdlError::Scrape("Manga name not found".to_string()))?
         .trim()
         .to_string();
-    let normalized_name = normalize(&name);
-
     let hash = extract_hash(url).ok_or(MgdlError::Scrape(format!(
         "Could not parse manga hash from {}",
         url
     )))?;
+    let normalized_name = folder_name(&name, &hash);
 
     let mut authors = String::new();
     let mut status = String::new();
@@ -174,11 +287,11 @@ pub async fn manga_from_url(
     client: &Client,
     base_url: &str,
     manga_url: &str,
-    max_attempts: usize,
-) -> MgdlResult<(Manga, Vec<Chapter>)> {
-    let html = get_with_retry(client, manga_url, max_attempts).await?;
+    retry: Retry,
+) -> MgdlResult<(Manga, ChapterList)> {
+    let html = get_with_retry(client, manga_url, retry).await?;
     let manga = parse_manga_from_html(&html, manga_url)?;
-    let chapters = get_manga_chapters(client, base_url, &manga.hash, max_attempts).await?;
+    let chapters = get_manga_chapters(client, base_url, &manga.hash, retry).await?;
     Ok((manga, chapters))
 }
 
@@ -186,61 +299,75 @@ async fn get_manga_chapters(
     client: &Client,
     base_url: &str,
     manga_hash: &str,
-    max_attempts: usize,
-) -> MgdlResult<Vec<Chapter>> {
+    retry: Retry,
+) -> MgdlResult<ChapterList> {
     let url = format!("{base_url}/series/{manga_hash}/full-chapter-list");
-    let html = get_with_retry(client, &url, max_attempts).await?;
+    let html = get_with_retry(client, &url, retry).await?;
     parse_chapters_from_html(&html)
 }
 
-pub async fn download_page(
-    client: &Client,
-    page_url: String,
-    chapter_path: PathBuf,
-    page_number: usize,
-    max_attempts: usize,
-) -> MgdlResult<usize> {
-    let url_without_query = page_url.split('?').next().unwrap_or(&page_url);
-    let file_ext = url_without_query
+/// File extension of a page image URL, ignoring its query string.
+pub fn page_extension(page_url: &str) -> MgdlResult<&str> {
+    let url_without_query = page_url.split('?').next().unwrap_or(page_url);
+    url_without_query
         .split('.')
         .next_back()
         .ok_or(MgdlError::Scrape(
             "Could not find file extension".to_string(),
-        ))?;
+        ))
+}
 
-    let bytes = retry(
+pub async fn download_page(
+    client: &Client,
+    page_url: String,
+    file_path: PathBuf,
+    retry: Retry,
+) -> MgdlResult<usize> {
+    let bytes = with_retries(
         || async {
             let response = client.get(&page_url).send().await?;
             Ok(response.bytes().await?)
         },
-        max_attempts,
-        INITIAL_DELAY,
+        retry,
     )
     .await?;
 
     let byte_count = bytes.len();
-    let file_path = chapter_path.join(format!("{:03}.{}", page_number, file_ext));
+    // a page may be hardlinked to identical ones; replace it, don't write
+    // through the link
+    let _ = fs::remove_file(&file_path).await;
     let mut file = fs::File::create(&file_path).await?;
     file.write_all(&bytes).await?;
 
     Ok(byte_count)
 }
 
-async fn retry<F, Fut, T>(
-    mut operation: F,
-    max_attempts: usize,
-    initial_delay: u64,
-) -> MgdlResult<T>
+/// Size of a page image from a HEAD request, if the server reports one.
+pub async fn page_size(client: &Client, page_url: &str) -> Option<u64> {
+    let response = client.head(page_url).send().await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+async fn with_retries<F, Fut, T>(mut operation: F, retry: Retry) -> MgdlResult<T>
 where
     F: FnMut() -> Fut,
     Fut: std::future::Future<Output = MgdlResult<T>>,
 {
-    let mut delay = initial_delay;
+    let mut delay = retry.delay;
 
-    for attempt in 0..max_attempts {
+    for attempt in 0..retry.attempts {
         match operation().await {
             Ok(result) => return Ok(result),
-            Err(_) if attempt + 1 < max_attempts => {
+            Err(_) if attempt + 1 < retry.attempts => {
                 sleep(Duration::from_millis(delay)).await;
                 delay *= 2;
             }
@@ -251,8 +378,8 @@ where
     Err(MgdlError::Scrape("Max retry attempts exhausted".into()))
 }
 
-pub async fn get_with_retry(client: &Client, url: &str, max_attempts: usize) -> MgdlResult<String> {
-    retry(
+pub async fn get_with_retry(client: &Client, url: &str, retry: Retry) -> MgdlResult<String> {
+    with_retries(
         || async {
             let response = client.get(url).send().await?;
             let text = response.text().await?;
@@ -266,8 +393,7 @@ pub async fn get_with_retry(client: &Client, url: &str, max_attempts: usize) ->
 
             Ok(text)
         },
-        max_attempts,
-        INITIAL_DELAY,
+        retry,
     )
     .await
 }
@@ -279,8 +405,11 @@ pub async fn scrape_to_csv(
     manga_url: &str,
     max_attempts: Option<usize>,
 ) -> MgdlResult<()> {
-    let max_attempts = max_attempts.unwrap_or(10);
-    let (manga, chapters) = manga_from_url(client, base_url, manga_url, max_attempts).await?;
+    let retry = Retry {
+        attempts: max_attempts.unwrap_or(10),
+        delay: INITIAL_DELAY,
+    };
+    let (manga, chapters) = manga_from_url(client, base_url, manga_url, retry).await?;
 
     let manga_id = Uuid::new_v4().to_string();
 
@@ -299,8 +428,8 @@ pub async fn scrape_to_csv(
 
     page_w.write_record(["id", "manga_id", "chapter_number", "number", "url"])?;
 
-    for chapter in chapters {
-        let pages = get_chapter_pages(client, base_url, &chapter.hash, max_attempts).await?;
+    for chapter in chapters.chapters {
+        let pages = get_chapter_pages(client, base_url, &chapter.hash, retry).await?;
 
         for page in pages {
             let page_id = Uuid::new_v4().to_string();
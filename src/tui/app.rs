@@ -0,0 +1,478 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::VecDeque;
+
+use crate::models::{cmp_chapter_keys, Chapter, LocalChapter, Manga};
+
+/// Lines of messages and warnings kept for the log pane.
+const LOG_LINES: usize = 200;
+
+/// A tracked series as listed in the library screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeriesRow {
+    pub hash: String,
+    pub name: String,
+    pub status: String,
+    pub detached: bool,
+    /// chapters on disk
+    pub local: usize,
+    /// chapters on the site but not on disk, once the site was checked
+    pub new: Option<usize>,
+}
+
+impl SeriesRow {
+    pub fn new(manga: &Manga, local: usize) -> Self {
+        Self {
+            hash: manga.hash.clone(),
+            name: manga.name.clone(),
+            status: manga.status.clone(),
+            detached: manga.detached,
+            local,
+            new: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChapterState {
+    /// on the site and on disk
+    Downloaded,
+    /// on the site only
+    New,
+    /// on disk only, e.g. removed from the site
+    LocalOnly,
+    /// picked for download, waiting for its turn
+    Queued,
+    Downloading {
+        done: usize,
+        total: usize,
+    },
+    Done,
+    Failed,
+}
+
+/// A chapter of the open series, merged from the site's list and the disk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChapterRow {
+    pub number: String,
+    pub released: Option<String>,
+    /// pages on disk
+    pub pages: Option<usize>,
+    pub state: ChapterState,
+    pub picked: bool,
+}
+
+impl ChapterRow {
+    /// Only chapters the site lists can be downloaded.
+    fn pickable(&self) -> bool {
+        !matches!(
+            self.state,
+            ChapterState::LocalOnly | ChapterState::Queued | ChapterState::Downloading { .. }
+        )
+    }
+}
+
+/// One row per chapter on the site or on disk, in chapter order.
+pub fn chapter_rows(remote: &[Chapter], local: &[LocalChapter]) -> Vec<ChapterRow> {
+    let mut rows: Vec<ChapterRow> = remote
+        .iter()
+        .map(|chapter| {
+            let pages = local
+                .iter()
+                .find(|ch| ch.number == chapter.number)
+                .map(|ch| ch.pages);
+            ChapterRow {
+                number: chapter.number.clone(),
+                released: chapter.released.clone(),
+                pages,
+                state: match pages {
+                    Some(_) => ChapterState::Downloaded,
+                    None => ChapterState::New,
+                },
+                picked: false,
+            }
+        })
+        .collect();
+    for chapter in local {
+        if !remote.iter().any(|ch| ch.number == chapter.number) {
+            rows.push(ChapterRow {
+                number: chapter.number.clone(),
+                released: None,
+                pages: Some(chapter.pages),
+                state: ChapterState::LocalOnly,
+                picked: false,
+            });
+        }
+    }
+    rows.sort_by(|a, b| cmp_chapter_keys(&a.number, &b.number));
+    rows
+}
+
+/// What the UI hears from the downloader side.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Update {
+    /// the tracked series, replacing the list
+    Library(Vec<SeriesRow>),
+    /// a series was checked against the site
+    Checked {
+        hash: String,
+        local: usize,
+        new: usize,
+    },
+    /// chapters of a series, shown if it is still open
+    Chapters {
+        hash: String,
+        rows: Vec<ChapterRow>,
+    },
+    /// what is being worked on; empty once done
+    Status(String),
+    ChapterQueued {
+        series: String,
+        chapter: String,
+        pages: usize,
+    },
+    PageDone {
+        series: String,
+        chapter: String,
+    },
+    ChapterDone {
+        series: String,
+        chapter: String,
+        failed: bool,
+    },
+    Message(String),
+    Warning(String),
+    /// a download request was handled
+    Idle,
+}
+
+/// What the UI asks the downloader side for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    /// check every series against the site again
+    Refresh,
+    /// list the chapters of a series
+    Open(String),
+    Download {
+        hash: String,
+        chapters: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenSeries {
+    pub hash: String,
+    pub name: String,
+    /// `None` until the chapter list arrives
+    pub rows: Option<Vec<ChapterRow>>,
+    pub cursor: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct App {
+    pub series: Vec<SeriesRow>,
+    pub cursor: usize,
+    pub open: Option<OpenSeries>,
+    pub status: String,
+    /// messages, with whether each is a warning
+    pub log: VecDeque<(bool, String)>,
+    /// download requests sent and not yet handled
+    pub downloads: usize,
+    /// quit once the running downloads are done
+    pub quitting: bool,
+    pub quit: bool,
+}
+
+impl App {
+    pub fn apply(&mut self, update: Update) {
+        match update {
+            Update::Library(series) => {
+                self.series = series;
+                self.cursor = self.cursor.min(self.series.len().saturating_sub(1));
+            }
+            Update::Checked { hash, local, new } => {
+                if let Some(row) = self.series.iter_mut().find(|row| row.hash == hash) {
+                    row.local = local;
+                    row.new = Some(new);
+                }
+            }
+            Update::Chapters { hash, rows } => {
+                if let Some(open) = self.open.as_mut().filter(|open| open.hash == hash) {
+                    open.cursor = open.cursor.min(rows.len().saturating_sub(1));
+                    open.rows = Some(rows);
+                }
+            }
+            Update::Status(status) => self.status = status,
+            Update::ChapterQueued {
+                series,
+                chapter,
+                pages,
+            } => {
+                if let Some(row) = self.chapter_mut(&series, &chapter) {
+                    row.state = ChapterState::Downloading {
+                        done: 0,
+                        total: pages,
+                    };
+                }
+            }
+            Update::PageDone { series, chapter } => {
+                if let Some(row) = self.chapter_mut(&series, &chapter) {
+                    if let ChapterState::Downloading { done, .. } = &mut row.state {
+                        *done += 1;
+                    }
+                }
+            }
+            Update::ChapterDone {
+                series,
+                chapter,
+                failed,
+            } => {
+                if let Some(row) = self.chapter_mut(&series, &chapter) {
+                    row.state = match failed {
+                        true => ChapterState::Failed,
+                        false => ChapterState::Done,
+                    };
+                }
+            }
+            Update::Message(message) => self.push_log(false, message),
+            Update::Warning(message) => self.push_log(true, message),
+            Update::Idle => {
+                self.downloads = self.downloads.saturating_sub(1);
+                if self.quitting && self.downloads == 0 {
+                    self.quit = true;
+                }
+            }
+        }
+    }
+
+    pub fn on_key(&mut self, key: KeyEvent) -> Option<Request> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.request_quit();
+            return None;
+        }
+        match self.open {
+            Some(_) => self.on_series_key(key.code),
+            None => self.on_library_key(key.code),
+        }
+    }
+
+    fn on_library_key(&mut self, code: KeyCode) -> Option<Request> {
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.cursor = (self.cursor + 1).min(self.series.len().saturating_sub(1))
+            }
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                let row = self.series.get(self.cursor)?;
+                self.open = Some(OpenSeries {
+                    hash: row.hash.clone(),
+                    name: row.name.clone(),
+                    rows: None,
+                    cursor: 0,
+                });
+                return Some(Request::Open(row.hash.clone()));
+            }
+            KeyCode::Char('r') => {
+                for row in &mut self.series {
+                    row.new = None;
+                }
+                return Some(Request::Refresh);
+            }
+            KeyCode::Char('q') | KeyCode::Esc => self.request_quit(),
+            _ => {}
+        }
+        None
+    }
+
+    fn on_series_key(&mut self, code: KeyCode) -> Option<Request> {
+        let open = self.open.as_mut()?;
+        let rows = open.rows.as_mut();
+        match code {
+            KeyCode::Esc | KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => {
+                self.open = None
+            }
+            KeyCode::Char('q') => self.request_quit(),
+            KeyCode::Up | KeyCode::Char('k') => open.cursor = open.cursor.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                let len = rows.map_or(0, |rows| rows.len());
+                open.cursor = (open.cursor + 1).min(len.saturating_sub(1));
+            }
+            KeyCode::Char(' ') => {
+                if let Some(row) = rows.and_then(|rows| rows.get_mut(open.cursor)) {
+                    row.picked = !row.picked && row.pickable();
+                }
+            }
+            KeyCode::Char('a') => {
+                for row in rows.into_iter().flatten() {
+                    if row.state == ChapterState::New {
+                        row.picked = true;
+                    }
+                }
+            }
+            KeyCode::Char('d') | KeyCode::Enter => {
+                let mut chapters = Vec::new();
+                for row in rows.into_iter().flatten().filter(|row| row.picked) {
+                    row.picked = false;
+                    row.state = ChapterState::Queued;
+                    chapters.push(row.number.clone());
+                }
+                if chapters.is_empty() {
+                    self.status = "Pick chapters with space first".to_string();
+                    return None;
+                }
+                let hash = open.hash.clone();
+                self.downloads += 1;
+                return Some(Request::Download { hash, chapters });
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn request_quit(&mut self) {
+        if self.downloads == 0 {
+            self.quit = true;
+        } else {
+            self.quitting = true;
+            self.status = "Quitting once the downloads finish".to_string();
+        }
+    }
+
+    /// A chapter of the open series, if `series` is the open one.
+    fn chapter_mut(&mut self, series: &str, chapter: &str) -> Option<&mut ChapterRow> {
+        let open = self.open.as_mut().filter(|open| open.name == series)?;
+        open.rows
+            .as_mut()?
+            .iter_mut()
+            .find(|row| row.number == chapter)
+    }
+
+    fn push_log(&mut self, warning: bool, message: String) {
+        if self.log.len() == LOG_LINES {
+            self.log.pop_front();
+        }
+        self.log.push_back((warning, message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(number: &str, pages: usize) -> LocalChapter {
+        LocalChapter {
+            number: number.to_string(),
+            pages,
+            volume: None,
+        }
+    }
+
+    fn press(app: &mut App, code: KeyCode) -> Option<Request> {
+        app.on_key(KeyEvent::from(code))
+    }
+
+    fn library() -> App {
+        let mut app = App::default();
+        let manga = Manga::new("abc", "My Manga", "my_manga", "", "Ongoing");
+        app.apply(Update::Library(vec![SeriesRow::new(&manga, 1)]));
+        app
+    }
+
+    #[test]
+    fn rows_merge_site_and_disk() {
+        let remote = [Chapter::new("h1", "0001"), Chapter::new("h2", "0002")];
+        let rows = chapter_rows(&remote, &[local("0002", 20), local("0000.5", 8)]);
+        let states: Vec<_> = rows
+            .iter()
+            .map(|row| (row.number.as_str(), row.pages, row.state.clone()))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("0000.5", Some(8), ChapterState::LocalOnly),
+                ("0001", None, ChapterState::New),
+                ("0002", Some(20), ChapterState::Downloaded),
+            ]
+        );
+    }
+
+    #[test]
+    fn picked_chapters_are_downloaded_with_progress() {
+        let mut app = library();
+        assert_eq!(
+            press(&mut app, KeyCode::Enter),
+            Some(Request::Open("abc".to_string()))
+        );
+        let remote = [Chapter::new("h1", "0001"), Chapter::new("h2", "0002")];
+        app.apply(Update::Chapters {
+            hash: "abc".to_string(),
+            rows: chapter_rows(&remote, &[local("0001", 20)]),
+        });
+
+        assert_eq!(press(&mut app, KeyCode::Char('d')), None);
+        press(&mut app, KeyCode::Char('a'));
+        assert_eq!(
+            press(&mut app, KeyCode::Char('d')),
+            Some(Request::Download {
+                hash: "abc".to_string(),
+                chapters: vec!["0002".to_string()],
+            })
+        );
+
+        let series = "My Manga".to_string();
+        let chapter = "0002".to_string();
+        app.apply(Update::ChapterQueued {
+            series: series.clone(),
+            chapter: chapter.clone(),
+            pages: 3,
+        });
+        app.apply(Update::PageDone {
+            series: series.clone(),
+            chapter: chapter.clone(),
+        });
+        let state = |app: &App| {
+            app.open.as_ref().unwrap().rows.as_ref().unwrap()[1]
+                .state
+                .clone()
+        };
+        assert_eq!(state(&app), ChapterState::Downloading { done: 1, total: 3 });
+        app.apply(Update::ChapterDone {
+            series,
+            chapter,
+            failed: false,
+        });
+        assert_eq!(state(&app), ChapterState::Done);
+    }
+
+    #[test]
+    fn quitting_waits_for_downloads() {
+        let mut app = library();
+        press(&mut app, KeyCode::Enter);
+        app.apply(Update::Chapters {
+            hash: "abc".to_string(),
+            rows: chapter_rows(&[Chapter::new("h1", "0001")], &[]),
+        });
+        press(&mut app, KeyCode::Char(' '));
+        assert!(press(&mut app, KeyCode::Char('d')).is_some());
+
+        press(&mut app, KeyCode::Char('q'));
+        assert!(!app.quit);
+        app.apply(Update::Idle);
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn updates_for_closed_series_are_ignored() {
+        let mut app = library();
+        app.apply(Update::Chapters {
+            hash: "abc".to_string(),
+            rows: Vec::new(),
+        });
+        assert_eq!(app.open, None);
+        app.apply(Update::Checked {
+            hash: "abc".to_string(),
+            local: 2,
+            new: 3,
+        });
+        assert_eq!(app.series[0].new, Some(3));
+    }
+}
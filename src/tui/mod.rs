@@ -0,0 +1,260 @@
+mod app;
+mod view;
+
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyEventKind};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    sync::mpsc::{Receiver, Sender},
+    thread,
+    time::Duration,
+};
+use tokio::sync::mpsc::{self as async_mpsc, error::TryRecvError, UnboundedReceiver};
+
+use crate::{
+    downloader::Downloader,
+    error::{MgdlError, MgdlResult},
+    models::{Chapter, Manga},
+};
+use app::{chapter_rows, App, Request, SeriesRow};
+
+pub use app::Update;
+
+/// How often the screen is redrawn while no key is pressed.
+const TICK: Duration = Duration::from_millis(50);
+
+/// Run the terminal UI until the user quits. The screen is drawn on its own
+/// thread; this task scrapes and downloads what it asks for, one request at
+/// a time, and the downloader's events reach the screen through `ui`, the
+/// same channel the logger was given.
+pub async fn run(
+    dldr: &Downloader,
+    ui: Sender<Update>,
+    updates: Receiver<Update>,
+) -> MgdlResult<()> {
+    let mut session = Session::new(dldr, ui)?;
+    let (requests_tx, mut requests) = async_mpsc::unbounded_channel();
+    let screen = thread::spawn(move || draw_loop(updates, requests_tx));
+
+    session.serve(&mut requests).await;
+
+    screen
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("terminal UI crashed")))
+        .map_err(MgdlError::from)
+}
+
+fn draw_loop(
+    updates: Receiver<Update>,
+    requests: async_mpsc::UnboundedSender<Request>,
+) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let mut app = App::default();
+    let result = (|| {
+        while !app.quit {
+            for update in updates.try_iter() {
+                app.apply(update);
+            }
+            terminal.draw(|frame| view::draw(frame, &app))?;
+            if !event::poll(TICK)? {
+                continue;
+            }
+            if let TermEvent::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if let Some(request) = app.on_key(key) {
+                    if requests.send(request).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    })();
+    ratatui::restore();
+    result
+}
+
+/// The downloader side of the UI.
+struct Session<'a> {
+    dldr: &'a Downloader,
+    ui: Sender<Update>,
+    library: Vec<Manga>,
+    /// chapter lists scraped so far, by series hash
+    remote: HashMap<String, Vec<Chapter>>,
+    /// series still to check against the site, by hash
+    unchecked: VecDeque<String>,
+}
+
+impl<'a> Session<'a> {
+    fn new(dldr: &'a Downloader, ui: Sender<Update>) -> MgdlResult<Self> {
+        let mut session = Self {
+            dldr,
+            ui,
+            library: Vec::new(),
+            remote: HashMap::new(),
+            unchecked: VecDeque::new(),
+        };
+        session.load_library()?;
+        Ok(session)
+    }
+
+    fn send(&self, update: Update) {
+        let _ = self.ui.send(update);
+    }
+
+    fn load_library(&mut self) -> MgdlResult<()> {
+        self.library = self.dldr.library()?;
+        self.library.sort_by_key(|manga| manga.name.to_lowercase());
+        let rows = self
+            .library
+            .iter()
+            .map(|manga| {
+                Ok(SeriesRow::new(
+                    manga,
+                    self.dldr.local_chapters(manga)?.len(),
+                ))
+            })
+            .collect::<MgdlResult<_>>()?;
+        self.send(Update::Library(rows));
+        self.remote.clear();
+        self.unchecked = self
+            .library
+            .iter()
+            .filter(|manga| !manga.detached)
+            .map(|manga| manga.hash.clone())
+            .collect();
+        Ok(())
+    }
+
+    /// Handle requests as they come, checking the library against the site
+    /// in between, until the screen goes away. Checks and chapter lists give
+    /// way to the next request; downloads run to the end.
+    async fn serve(&mut self, requests: &mut UnboundedReceiver<Request>) {
+        let mut next = None;
+        loop {
+            let request = match next.take() {
+                Some(request) => request,
+                None => match requests.try_recv() {
+                    Ok(request) => request,
+                    Err(TryRecvError::Disconnected) => break,
+                    Err(TryRecvError::Empty) => match self.unchecked.pop_front() {
+                        Some(hash) => {
+                            tokio::select! {
+                                result = self.check(&hash) => self.report(result),
+                                request = requests.recv() => {
+                                    self.unchecked.push_front(hash);
+                                    match request {
+                                        Some(request) => next = Some(request),
+                                        None => break,
+                                    }
+                                }
+                            }
+                            continue;
+                        }
+                        None => match requests.recv().await {
+                            Some(request) => request,
+                            None => break,
+                        },
+                    },
+                },
+            };
+            if let Request::Download { .. } = request {
+                let result = self.handle(request).await;
+                self.report(result);
+                self.send(Update::Idle);
+            } else {
+                tokio::select! {
+                    result = self.handle(request) => self.report(result),
+                    request = requests.recv() => match request {
+                        Some(request) => next = Some(request),
+                        None => break,
+                    },
+                }
+            }
+            self.send(Update::Status(String::new()));
+        }
+    }
+
+    async fn handle(&mut self, request: Request) -> MgdlResult<()> {
+        match request {
+            Request::Refresh => self.load_library(),
+            Request::Open(hash) => self.open(&hash).await,
+            Request::Download { hash, chapters } => {
+                let manga = self.manga(&hash)?.clone();
+                let picked: Vec<Chapter> = self
+                    .chapters(&manga)
+                    .await?
+                    .into_iter()
+                    .filter(|ch| chapters.contains(&ch.number))
+                    .collect();
+                let result = self.dldr.download_picked(&manga, &picked).await;
+                // show what made it to disk, even after a failure
+                self.open(&hash).await?;
+                result
+            }
+        }
+    }
+
+    /// Send the chapter list of a series and its count of new chapters.
+    async fn open(&mut self, hash: &str) -> MgdlResult<()> {
+        let manga = self.manga(hash)?.clone();
+        self.send(Update::Status(format!("Loading {}", &manga.name)));
+        let remote = self.chapters(&manga).await?;
+        let local = self.dldr.local_chapters(&manga)?;
+        let rows = chapter_rows(&remote, &local);
+        self.send(Update::Checked {
+            hash: hash.to_string(),
+            local: local.len(),
+            new: rows.iter().filter(|row| row.pages.is_none()).count(),
+        });
+        self.send(Update::Chapters {
+            hash: hash.to_string(),
+            rows,
+        });
+        Ok(())
+    }
+
+    async fn check(&mut self, hash: &str) -> MgdlResult<()> {
+        let manga = self.manga(hash)?.clone();
+        self.send(Update::Status(format!("Checking {}", &manga.name)));
+        let remote = self.chapters(&manga).await?;
+        let local = self.dldr.local_chapters(&manga)?;
+        let new = chapter_rows(&remote, &local)
+            .iter()
+            .filter(|row| row.pages.is_none())
+            .count();
+        self.send(Update::Checked {
+            hash: hash.to_string(),
+            local: local.len(),
+            new,
+        });
+        self.send(Update::Status(String::new()));
+        Ok(())
+    }
+
+    /// The site's chapter list of a series, scraped once per session.
+    async fn chapters(&mut self, manga: &Manga) -> MgdlResult<Vec<Chapter>> {
+        if let Some(chapters) = self.remote.get(&manga.hash) {
+            return Ok(chapters.clone());
+        }
+        let chapters = self.dldr.remote_chapters(manga).await?;
+        self.remote.insert(manga.hash.clone(), chapters.clone());
+        Ok(chapters)
+    }
+
+    fn manga(&self, hash: &str) -> MgdlResult<&Manga> {
+        self.library
+            .iter()
+            .find(|manga| manga.hash == hash)
+            .ok_or_else(|| MgdlError::Db(format!("no tracked series with hash {hash}")))
+    }
+
+    /// Errors end up in the log pane rather than ending the session.
+    fn report(&self, result: MgdlResult<()>) {
+        if let Err(err) = result {
+            self.send(Update::Warning(err.to_string()));
+        }
+    }
+}
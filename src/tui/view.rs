@@ -0,0 +1,169 @@
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Cell, Paragraph, Row, Table, TableState},
+    Frame,
+};
+
+use super::app::{App, ChapterRow, ChapterState, OpenSeries, SeriesRow};
+
+const LIBRARY_KEYS: &str = "↑/↓ move  enter open  r recheck  q quit";
+const SERIES_KEYS: &str = "↑/↓ move  space pick  a pick new  d download  esc back  q quit";
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [main, log, footer] = Layout::vertical([
+        Constraint::Min(5),
+        Constraint::Length(7),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    match &app.open {
+        Some(open) => draw_series(frame, main, open),
+        None => draw_library(frame, main, app),
+    }
+    draw_log(frame, log, app);
+
+    let keys = match app.open {
+        Some(_) => SERIES_KEYS,
+        None => LIBRARY_KEYS,
+    };
+    let footer_line = match app.status.as_str() {
+        "" => Line::from(keys.dark_gray()),
+        status => Line::from(vec![status.yellow(), "  ".into(), keys.dark_gray()]),
+    };
+    frame.render_widget(Paragraph::new(footer_line), footer);
+}
+
+fn draw_library(frame: &mut Frame, area: Rect, app: &App) {
+    let rows = app.series.iter().map(series_row);
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Fill(1),
+            Constraint::Length(12),
+            Constraint::Length(6),
+            Constraint::Length(6),
+        ],
+    )
+    .header(Row::new(["Series", "Status", "Local", "New"]).bold())
+    .block(Block::bordered().title(format!(" Library ({}) ", app.series.len())))
+    .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    let mut state = TableState::new().with_selected(Some(app.cursor));
+    frame.render_stateful_widget(table, area, &mut state);
+}
+
+fn series_row(row: &SeriesRow) -> Row<'_> {
+    let status = match row.detached {
+        true => Cell::from("detached".red()),
+        false => Cell::from(row.status.as_str()),
+    };
+    let new = match row.new {
+        Some(0) => Cell::from("0"),
+        Some(count) => Cell::from(count.to_string().green().bold()),
+        None => Cell::from("…".dark_gray()),
+    };
+    Row::new([
+        Cell::from(row.name.as_str()),
+        status,
+        Cell::from(row.local.to_string()),
+        new,
+    ])
+}
+
+fn draw_series(frame: &mut Frame, area: Rect, open: &OpenSeries) {
+    let block = Block::bordered().title(format!(" {} ", open.name));
+    let Some(rows) = &open.rows else {
+        frame.render_widget(
+            Paragraph::new("Loading chapters…".dark_gray()).block(block),
+            area,
+        );
+        return;
+    };
+    let picked = rows.iter().filter(|row| row.picked).count();
+    let block = match picked {
+        0 => block,
+        count => block.title_bottom(format!(" {count} picked ")),
+    };
+    let table = Table::new(
+        rows.iter().map(chapter_row),
+        [
+            Constraint::Length(3),
+            Constraint::Length(14),
+            Constraint::Length(12),
+            Constraint::Length(7),
+            Constraint::Fill(1),
+        ],
+    )
+    .header(Row::new(["", "Chapter", "Released", "Pages", "State"]).bold())
+    .block(block)
+    .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    let mut state = TableState::new().with_selected(Some(open.cursor));
+    frame.render_stateful_widget(table, area, &mut state);
+}
+
+fn chapter_row(row: &ChapterRow) -> Row<'_> {
+    let mark = if row.picked { "[x]" } else { "[ ]" };
+    let pages = row.pages.map(|pages| pages.to_string()).unwrap_or_default();
+    Row::new([
+        Cell::from(mark),
+        Cell::from(row.number.as_str()),
+        Cell::from(row.released.as_deref().unwrap_or("")),
+        Cell::from(pages),
+        Cell::from(state_span(&row.state)),
+    ])
+}
+
+fn state_span(state: &ChapterState) -> Span<'static> {
+    match state {
+        ChapterState::Downloaded => "downloaded".into(),
+        ChapterState::New => "new".green(),
+        ChapterState::LocalOnly => "local only".dark_gray(),
+        ChapterState::Queued => "queued".yellow(),
+        ChapterState::Downloading { done, total } => {
+            format!("{} {done}/{total}", progress(*done, *total, 20)).cyan()
+        }
+        ChapterState::Done => "done".green().bold(),
+        ChapterState::Failed => "failed (see log)".red(),
+    }
+}
+
+/// `[=====     ]`, `width` cells between the brackets.
+fn progress(done: usize, total: usize, width: usize) -> String {
+    let filled = (done * width)
+        .checked_div(total)
+        .unwrap_or(width)
+        .min(width);
+    format!("[{}{}]", "=".repeat(filled), " ".repeat(width - filled))
+}
+
+fn draw_log(frame: &mut Frame, area: Rect, app: &App) {
+    let height = area.height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = app
+        .log
+        .iter()
+        .skip(app.log.len().saturating_sub(height))
+        .map(|(warning, message)| match warning {
+            true => Line::from(message.as_str()).fg(Color::Red),
+            false => Line::from(message.as_str()),
+        })
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" Log ")),
+        area,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_fills_with_pages() {
+        assert_eq!(progress(0, 4, 4), "[    ]");
+        assert_eq!(progress(2, 4, 4), "[==  ]");
+        assert_eq!(progress(4, 4, 4), "[====]");
+        assert_eq!(progress(0, 0, 4), "[====]");
+    }
+}
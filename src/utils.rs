@@ -1,32 +1,20 @@
 use directories::BaseDirs;
-use std::path::PathBuf;
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use crate::error::{MgdlError, MgdlResult};
 
+/// Turn a title into a folder-safe ASCII name. Non-Latin scripts are
+/// transliterated (e.g. `進撃の巨人` becomes `jin_ji_noju_ren`), so the result
+/// is only empty for titles made entirely of symbols.
 pub fn normalize(s: &str) -> String {
-    let mut out = String::with_capacity(s.len());
-
-    for c in s.chars() {
-        match c {
-            'á' | 'à' | 'â' | 'ã' | 'ä' => out.push('a'),
-            'é' | 'è' | 'ê' | 'ë' => out.push('e'),
-            'í' | 'ì' | 'î' | 'ï' => out.push('i'),
-            'ó' | 'ò' | 'ô' | 'õ' | 'ö' => out.push('o'),
-            'ú' | 'ù' | 'û' | 'ü' => out.push('u'),
-            'ç' => out.push('c'),
-            'ñ' => out.push('n'),
-            'ý' | 'ÿ' => out.push('y'),
-            'Á' | 'À' | 'Â' | 'Ã' | 'Ä' => out.push('A'),
-            'É' | 'È' | 'Ê' | 'Ë' => out.push('E'),
-            'Í' | 'Ì' | 'Î' | 'Ï' => out.push('I'),
-            'Ó' | 'Ò' | 'Ô' | 'Õ' | 'Ö' => out.push('O'),
-            'Ú' | 'Ù' | 'Û' | 'Ü' => out.push('U'),
-            'Ç' => out.push('C'),
-            'Ñ' => out.push('N'),
-            'Ý' => out.push('Y'),
-            c if c.is_ascii_alphanumeric() => out.push(c),
-            _ => out.push('_'),
-        }
+    let ascii = deunicode::deunicode(s);
+    let mut out = String::with_capacity(ascii.len());
+
+    for c in ascii.chars() {
+        out.push(if c.is_ascii_alphanumeric() { c } else { '_' });
     }
 
     // collapse consecutive underscores and trim them from edges
@@ -50,6 +38,30 @@ pub fn normalize(s: &str) -> String {
     result.to_lowercase()
 }
 
+/// Make a title safe to use as a file name on any platform.
+pub fn sanitize_file_name(s: &str) -> String {
+    let cleaned: String = s
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    cleaned.trim().trim_end_matches('.').to_string()
+}
+
+/// Folder name for a manga: its normalized title, or a slug derived from the
+/// hash when the title normalizes to nothing.
+pub fn folder_name(title: &str, hash: &str) -> String {
+    let name = normalize(title);
+    if name.is_empty() {
+        format!("series_{}", normalize(hash))
+    } else {
+        name
+    }
+}
+
 pub fn extract_hash(url: &str) -> Option<String> {
     let path = url.trim_end_matches('/');
     let after_series = path.split("/series/").nth(1)?;
@@ -60,6 +72,72 @@ pub fn extract_hash(url: &str) -> Option<String> {
     Some(hash.to_string())
 }
 
+pub fn expand_tilde(path: PathBuf) -> MgdlResult<PathBuf> {
+    if let Ok(stripped) = path.strip_prefix("~") {
+        let base_dirs = BaseDirs::new()
+            .ok_or_else(|| MgdlError::Config("Could not determine home directory".to_string()))?;
+        return Ok(base_dirs.home_dir().join(stripped));
+    }
+
+    Ok(path)
+}
+
+/// Broken-down UTC time, for timestamps in file names and metadata.
+pub struct UtcDateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub millis: u32,
+}
+
+impl UtcDateTime {
+    pub fn now() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self::from_unix(now.as_secs(), now.subsec_millis())
+    }
+
+    pub fn from_unix(secs: u64, millis: u32) -> Self {
+        let (days, rem) = (secs / 86_400, (secs % 86_400) as u32);
+        let (year, month, day) = civil_from_days(days as i64);
+        Self {
+            year,
+            month,
+            day,
+            hour: rem / 3600,
+            minute: rem % 3600 / 60,
+            second: rem % 60,
+            millis,
+        }
+    }
+
+    /// `YYYY-MM-DDTHH:MM:SSZ`
+    pub fn to_iso8601(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Days since 1970-01-01 to a (year, month, day) civil date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,6 +182,44 @@ mod tests {
         assert_eq!(normalize("Chapter 123"), "chapter_123");
     }
 
+    #[test]
+    fn normalize_japanese() {
+        assert_eq!(normalize("ワンピース"), "wanpisu");
+    }
+
+    #[test]
+    fn normalize_korean() {
+        assert_eq!(normalize("나 혼자만 레벨업"), "na_honjaman_rebeleob");
+    }
+
+    #[test]
+    fn folder_name_falls_back_to_hash() {
+        assert_eq!(folder_name("★☆★", "01JK8N8A"), "series_01jk8n8a");
+        assert_eq!(folder_name("Café", "01JK8N8A"), "cafe");
+    }
+
+    #[test]
+    fn sanitize_file_name_replaces_separators() {
+        assert_eq!(sanitize_file_name("Fate/Zero: Vol?"), "Fate_Zero_ Vol_");
+        assert_eq!(sanitize_file_name(" Trailing. "), "Trailing");
+    }
+
+    #[test]
+    fn civil_from_days_epoch() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+    }
+
+    #[test]
+    fn civil_from_days_leap_day() {
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    }
+
+    #[test]
+    fn utc_date_time_iso8601() {
+        let time = UtcDateTime::from_unix(1_709_210_096, 0);
+        assert_eq!(time.to_iso8601(), "2024-02-29T12:34:56Z");
+    }
+
     #[test]
     fn extract_hash_valid() {
         let url = "https://example.com/series/01JK8N8A7W8ZGR7014BM2ZMGBB/tokyo-alien-bros";
@@ -137,13 +253,3 @@ mod tests {
         );
     }
 }
-
-pub fn expand_tilde(path: PathBuf) -> MgdlResult<PathBuf> {
-    if let Ok(stripped) = path.strip_prefix("~") {
-        let base_dirs = BaseDirs::new()
-            .ok_or_else(|| MgdlError::Config("Could not determine home directory".to_string()))?;
-        return Ok(base_dirs.home_dir().join(stripped));
-    }
-
-    Ok(path)
-}
@@ -0,0 +1,96 @@
+use mgdl::backup::Backups;
+use mgdl::db::Db;
+use mgdl::models::Manga;
+use tempfile::TempDir;
+
+fn temp_db(dir: &TempDir) -> Db {
+    Db::new(dir.path().join("test.db")).unwrap()
+}
+
+#[test]
+fn snapshot_and_restore() {
+    let dir = TempDir::new().unwrap();
+    let db_path = dir.path().join("test.db");
+    let db = temp_db(&dir);
+    let backups = Backups::new(dir.path().join("backups"), 5);
+
+    db.upsert_manga(Manga::new("h1", "Kept", "kept", "A", "Ongoing"))
+        .unwrap();
+    let snapshot = backups.snapshot(&db, "manual").unwrap();
+    assert!(snapshot.exists());
+
+    db.drop_table().unwrap();
+    drop(db);
+    assert_eq!(backups.restore(&db_path, None).unwrap(), snapshot);
+
+    let db = Db::new(db_path).unwrap();
+    let result = db.get_manga_by_normalized_name("kept").unwrap();
+    assert_eq!(result.hash, "h1");
+}
+
+#[test]
+fn restore_replaces_a_broken_db() {
+    let dir = TempDir::new().unwrap();
+    let db_path = dir.path().join("test.db");
+    let db = temp_db(&dir);
+    let backups = Backups::new(dir.path().join("backups"), 5);
+    db.upsert_manga(Manga::new("h1", "Kept", "kept", "A", "Ongoing"))
+        .unwrap();
+    backups.snapshot(&db, "manual").unwrap();
+    drop(db);
+
+    std::fs::write(&db_path, b"not a database").unwrap();
+    assert!(Db::new(db_path.clone()).is_err());
+    backups.restore(&db_path, None).unwrap();
+    assert!(Db::new(db_path.clone()).is_ok());
+
+    // the broken file was kept, but a second restore doesn't pick it
+    assert_eq!(backups.list().unwrap().len(), 2);
+    backups.restore(&db_path, None).unwrap();
+    let db = Db::new(db_path).unwrap();
+    assert_eq!(db.get_manga_by_normalized_name("kept").unwrap().hash, "h1");
+}
+
+#[test]
+fn retention_keeps_newest() {
+    let dir = TempDir::new().unwrap();
+    let db = temp_db(&dir);
+    let backups = Backups::new(dir.path().join("backups"), 2);
+
+    for _ in 0..4 {
+        backups.snapshot(&db, "manual").unwrap();
+    }
+
+    let list = backups.list().unwrap();
+    assert_eq!(list.len(), 2);
+    assert_eq!(backups.latest().unwrap(), list[1]);
+}
+
+#[test]
+fn latest_without_backups_fails() {
+    let dir = TempDir::new().unwrap();
+    let backups = Backups::new(dir.path().join("backups"), 2);
+    assert!(backups.latest().is_err());
+}
+
+#[test]
+fn snapshot_before_migration_skips_current_db() {
+    let dir = TempDir::new().unwrap();
+    let db_path = dir.path().join("test.db");
+    drop(Db::new(db_path.clone()).unwrap());
+
+    let backups = Backups::new(dir.path().join("backups"), 2);
+    backups.snapshot_before_migration(&db_path).unwrap();
+    assert!(backups.list().unwrap().is_empty());
+}
+
+#[test]
+fn latest_is_most_recent_snapshot() {
+    let dir = TempDir::new().unwrap();
+    let db = temp_db(&dir);
+    let backups = Backups::new(dir.path().join("backups"), 5);
+
+    backups.snapshot(&db, "manual").unwrap();
+    let newest = backups.snapshot(&db, "manual").unwrap();
+    assert_eq!(backups.latest().unwrap(), newest);
+}
@@ -1,5 +1,5 @@
-use mgdl::db::Db;
-use mgdl::models::Manga;
+use mgdl::db::{Db, LEGACY_NAMES_SETTING};
+use mgdl::models::{LocalChapter, Manga};
 use tempfile::TempDir;
 
 fn temp_db() -> (Db, TempDir) {
@@ -31,15 +31,48 @@ fn upsert_updates_existing() {
     db.upsert_manga(sample_manga("Test Manga", "Ongoing"))
         .unwrap();
 
-    let updated = Manga::new("hash2", "Test Manga", "test manga", "Author B", "Complete");
+    let updated = Manga::new("hash1", "Test Manga", "test manga", "Author B", "Complete");
     db.upsert_manga(updated).unwrap();
 
     let result = db.get_manga_by_normalized_name("test manga").unwrap();
-    assert_eq!(result.hash, "hash2");
+    assert_eq!(result.hash, "hash1");
     assert_eq!(result.authors, "Author B");
     assert_eq!(result.status, "Complete");
 }
 
+#[test]
+fn upsert_follows_title_change_by_hash() {
+    let (db, _dir) = temp_db();
+    db.upsert_manga(Manga::new("h1", "Old Title", "old_title", "A", "Ongoing"))
+        .unwrap();
+
+    let renamed = db
+        .upsert_manga(Manga::new("h1", "New Title", "new_title", "A", "Ongoing"))
+        .unwrap();
+    assert_eq!(renamed.name, "New Title");
+    assert_eq!(
+        renamed.normalized_name, "old_title",
+        "folder name must stay stable across title changes"
+    );
+
+    db.relink_manga("old_title", "new_title").unwrap();
+    let by_alias = db.get_manga_by_normalized_name("old_title").unwrap();
+    assert_eq!(by_alias.hash, "h1");
+    assert_eq!(by_alias.normalized_name, "new_title");
+}
+
+#[test]
+fn same_title_different_hash_is_separate_manga() {
+    let (db, _dir) = temp_db();
+    db.upsert_manga(Manga::new("h1", "Same", "same", "A", "Ongoing"))
+        .unwrap();
+    db.upsert_manga(Manga::new("h2", "Same", "same_2", "B", "Ongoing"))
+        .unwrap();
+
+    assert_eq!(db.get_manga_by_hash("h1").unwrap().unwrap().authors, "A");
+    assert_eq!(db.get_manga_by_hash("h2").unwrap().unwrap().authors, "B");
+}
+
 #[test]
 fn get_ongoing_manga() {
     let (db, _dir) = temp_db();
@@ -85,3 +118,184 @@ fn query_nonexistent_returns_error() {
     let (db, _dir) = temp_db();
     assert!(db.get_manga_by_normalized_name("nonexistent").is_err());
 }
+
+#[test]
+fn detach_and_relink() {
+    let (db, _dir) = temp_db();
+    db.upsert_manga(sample_manga("Moved", "Ongoing")).unwrap();
+
+    db.set_detached("moved", true).unwrap();
+    assert!(db.get_manga_by_normalized_name("moved").unwrap().detached);
+    assert_eq!(db.get_detached_manga().unwrap().len(), 1);
+
+    db.relink_manga("moved", "moved_elsewhere").unwrap();
+    let result = db.get_manga_by_normalized_name("moved_elsewhere").unwrap();
+    assert!(!result.detached);
+    assert!(db.get_detached_manga().unwrap().is_empty());
+}
+
+#[test]
+fn relink_nonexistent_returns_error() {
+    let (db, _dir) = temp_db();
+    assert!(db.relink_manga("nonexistent", "other").is_err());
+}
+
+#[test]
+fn migrates_unversioned_db() {
+    let dir = TempDir::new().unwrap();
+    let db_path = dir.path().join("test.db");
+
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    conn.execute(
+        "CREATE TABLE mangas (
+            hash TEXT NOT NULL PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            normalized_name TEXT UNIQUE,
+            authors TEXT NOT NULL,
+            status TEXT NOT NULL
+        )",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO mangas VALUES ('h', 'Old', 'old', 'A', 'Ongoing')",
+        [],
+    )
+    .unwrap();
+    drop(conn);
+
+    let db = Db::new(db_path).unwrap();
+    let result = db.get_manga_by_normalized_name("old").unwrap();
+    assert_eq!(result.name, "Old");
+    assert!(!result.detached);
+    // its chapter folders still carry `NNNN-NN` names
+    assert!(db.get_setting(LEGACY_NAMES_SETTING).unwrap().is_some());
+}
+
+#[test]
+fn migrates_old_chapter_numbers() {
+    let dir = TempDir::new().unwrap();
+    let db_path = dir.path().join("test.db");
+    drop(Db::new(db_path.clone()).unwrap());
+
+    // roll back to before the chapter number migration
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    let version: i64 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap();
+    conn.execute_batch(&format!(
+        "PRAGMA user_version = {};
+         INSERT INTO chapters (manga_hash, number, pages) VALUES
+            ('h1', '0005-01', 10), ('h1', '0005-05', 8), ('h1', '0005-10', 4),
+            ('h1', 'special-extra', 2);
+         INSERT INTO filtered_pages VALUES ('h1', '0005-05', 3);",
+        version - 1
+    ))
+    .unwrap();
+    drop(conn);
+
+    let db = Db::new(db_path).unwrap();
+    let numbers: Vec<String> = db
+        .get_chapters("h1")
+        .unwrap()
+        .into_iter()
+        .map(|ch| ch.number)
+        .collect();
+    assert_eq!(numbers, vec!["0005", "0005.10", "0005.5", "special-extra"]);
+    assert_eq!(db.get_filtered_pages("h1", "0005.5").unwrap(), vec![3]);
+    assert!(db.get_setting(LEGACY_NAMES_SETTING).unwrap().is_some());
+}
+
+#[test]
+fn upsert_and_delete_chapters() {
+    let (db, _dir) = temp_db();
+    let chapter = |number: &str, pages| LocalChapter {
+        number: number.to_string(),
+        pages,
+        volume: None,
+    };
+
+    db.upsert_chapter("h1", &chapter("0001", 10)).unwrap();
+    db.upsert_chapter("h1", &chapter("0002", 5)).unwrap();
+    db.upsert_chapter("h1", &chapter("0001", 12)).unwrap();
+    db.upsert_chapter("h2", &chapter("0001", 3)).unwrap();
+
+    let chapters = db.get_chapters("h1").unwrap();
+    assert_eq!(chapters, vec![chapter("0001", 12), chapter("0002", 5)]);
+
+    db.delete_chapter("h1", "0002").unwrap();
+    assert_eq!(db.get_chapters("h1").unwrap().len(), 1);
+    assert_eq!(db.get_chapters("h2").unwrap().len(), 1);
+}
+
+#[test]
+fn upsert_chapter_keeps_known_volume() {
+    let (db, _dir) = temp_db();
+    let chapter = |pages, volume| LocalChapter {
+        number: "0001".to_string(),
+        pages,
+        volume,
+    };
+
+    db.upsert_chapter("h1", &chapter(10, Some(2))).unwrap();
+    db.upsert_chapter("h1", &chapter(12, None)).unwrap();
+    assert_eq!(db.get_chapters("h1").unwrap(), vec![chapter(12, Some(2))]);
+
+    db.upsert_chapter("h1", &chapter(12, Some(3))).unwrap();
+    assert_eq!(db.get_chapters("h1").unwrap(), vec![chapter(12, Some(3))]);
+}
+
+#[test]
+fn delete_manga_removes_chapters() {
+    let (db, _dir) = temp_db();
+    db.upsert_manga(sample_manga("Gone", "Ongoing")).unwrap();
+    db.upsert_chapter(
+        "hash1",
+        &LocalChapter {
+            number: "0001".to_string(),
+            pages: 1,
+            volume: None,
+        },
+    )
+    .unwrap();
+
+    db.delete_manga_by_normalized_name("gone").unwrap();
+    assert!(db.get_chapters("hash1").unwrap().is_empty());
+}
+
+#[test]
+fn normalized_name_owner() {
+    let (db, _dir) = temp_db();
+    db.upsert_manga(Manga::new("h1", "Taken", "taken", "A", "Ongoing"))
+        .unwrap();
+    assert_eq!(
+        db.normalized_name_owner("taken").unwrap(),
+        Some("h1".to_string())
+    );
+    assert_eq!(db.normalized_name_owner("free").unwrap(), None);
+}
+
+#[test]
+fn blocked_pages_include_global_ones() {
+    let (db, _dir) = temp_db();
+    db.add_blocked_page(1, None).unwrap();
+    db.add_blocked_page(u64::MAX, Some("hash1")).unwrap();
+    db.add_blocked_page(u64::MAX, Some("hash1")).unwrap();
+    db.add_blocked_page(3, Some("hash2")).unwrap();
+
+    let mut blocked = db.get_blocked_pages("hash1").unwrap();
+    blocked.sort();
+    assert_eq!(blocked, vec![1, u64::MAX]);
+    assert_eq!(db.get_blocked_pages("other").unwrap(), vec![1]);
+}
+
+#[test]
+fn filtered_pages_by_chapter() {
+    let (db, _dir) = temp_db();
+    db.add_filtered_page("hash1", "0001", 5).unwrap();
+    db.add_filtered_page("hash1", "0001", 2).unwrap();
+    db.add_filtered_page("hash1", "0002", 1).unwrap();
+
+    assert_eq!(db.get_filtered_pages("hash1", "0001").unwrap(), vec![2, 5]);
+    assert!(db.get_filtered_pages("hash2", "0001").unwrap().is_empty());
+}
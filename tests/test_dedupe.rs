@@ -0,0 +1,96 @@
+use mgdl::db::Db;
+use mgdl::dedupe::{content_hash, dedupe_chapter, DedupeReport};
+use mgdl::layout::Layout;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use tempfile::TempDir;
+
+fn write_chapter(root: &std::path::Path, dir: &str, pages: &[(&str, &[u8])]) {
+    let path = root.join(dir);
+    fs::create_dir_all(&path).unwrap();
+    for (name, bytes) in pages {
+        fs::write(path.join(name), bytes).unwrap();
+    }
+}
+
+#[test]
+fn content_hash_is_hex_sha256() {
+    assert_eq!(
+        content_hash(b"abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
+
+#[test]
+fn identical_pages_are_linked_once() {
+    let dir = TempDir::new().unwrap();
+    let db = Db::new(dir.path().join("mgdl.db")).unwrap();
+    let root = dir.path().join("manga");
+    let layout = Layout::default();
+    let credits: &[u8] = b"credits page";
+    write_chapter(
+        &root,
+        "series/chapter_0001",
+        &[
+            ("001.jpg", b"one"),
+            ("002.jpg", credits),
+            ("notes.txt", credits),
+        ],
+    );
+    write_chapter(
+        &root,
+        "series/chapter_0002",
+        &[("001.jpg", b"two"), ("002.jpg", credits)],
+    );
+
+    let first = dedupe_chapter(&db, &layout, &root, &root.join("series/chapter_0001")).unwrap();
+    assert_eq!(
+        first,
+        DedupeReport {
+            pages: 2,
+            linked: 0,
+            bytes_saved: 0
+        }
+    );
+    let second = dedupe_chapter(&db, &layout, &root, &root.join("series/chapter_0002")).unwrap();
+    assert_eq!(
+        second,
+        DedupeReport {
+            pages: 2,
+            linked: 1,
+            bytes_saved: credits.len() as u64
+        }
+    );
+
+    let kept = fs::metadata(root.join("series/chapter_0001/002.jpg")).unwrap();
+    let linked = fs::metadata(root.join("series/chapter_0002/002.jpg")).unwrap();
+    assert_eq!(kept.ino(), linked.ino());
+    assert_eq!(kept.nlink(), 2);
+    assert_eq!(
+        db.get_page_path(&content_hash(credits)).unwrap().as_deref(),
+        Some("series/chapter_0001/002.jpg")
+    );
+
+    // a second pass finds nothing left to share
+    let again = dedupe_chapter(&db, &layout, &root, &root.join("series/chapter_0002")).unwrap();
+    assert_eq!(again.linked, 0);
+}
+
+#[test]
+fn missing_stored_page_is_replaced() {
+    let dir = TempDir::new().unwrap();
+    let db = Db::new(dir.path().join("mgdl.db")).unwrap();
+    let root = dir.path().to_path_buf();
+    let layout = Layout::default();
+    write_chapter(&root, "a/chapter_0001", &[("001.png", b"same")]);
+    dedupe_chapter(&db, &layout, &root, &root.join("a/chapter_0001")).unwrap();
+    fs::remove_dir_all(root.join("a")).unwrap();
+
+    write_chapter(&root, "b/chapter_0001", &[("001.png", b"same")]);
+    let report = dedupe_chapter(&db, &layout, &root, &root.join("b/chapter_0001")).unwrap();
+    assert_eq!(report.linked, 0);
+    assert_eq!(
+        db.get_page_path(&content_hash(b"same")).unwrap().as_deref(),
+        Some("b/chapter_0001/001.png")
+    );
+}
@@ -0,0 +1,605 @@
+use mgdl::export::series_json::{SeriesJson, SERIES_JSON_FILE};
+use mgdl::export::{
+    assign_volumes, bundle_title, cbz, comic_info::ComicInfo, epub::write_epub, export_file_name,
+    folder::write_folder, group_by_volume, load_chapters, pdf::write_pdf, ExportChapter,
+    ExportFormat,
+};
+use mgdl::layout::Layout;
+use mgdl::library::{existing_chapter_pages, local_chapters, PageImage, VolumeMap, VOLUMES_FILE};
+use mgdl::models::{Chapter, ChapterRange, LocalChapter, Manga, ReadingDirection};
+use std::fs;
+use std::io::Read;
+use tempfile::TempDir;
+
+fn sample_manga() -> Manga {
+    Manga::new("h1", "My Manga", "my_manga", "Author A", "Ongoing")
+}
+
+fn sample_info() -> ComicInfo {
+    let manga = sample_manga();
+    let mut chapter = Chapter::new("c1", "0005.5");
+    chapter.released = Some("2025-07-31".to_string());
+    ComicInfo::new(&manga, &chapter, 0)
+}
+
+fn write_pages(dir: &std::path::Path, pages: &[&str]) {
+    fs::create_dir_all(dir).unwrap();
+    for page in pages {
+        fs::write(dir.join(page), page.as_bytes()).unwrap();
+    }
+}
+
+#[test]
+fn pack_chapter_writes_comic_info_and_removes_folder() {
+    let dir = TempDir::new().unwrap();
+    let chapter_path = dir.path().join("chapter_0005.5");
+    let cbz_path = dir
+        .path()
+        .join(Layout::default().cbz_file(&sample_manga(), "0005.5", None));
+    write_pages(&chapter_path, &["001.jpg", "002.jpg"]);
+    // a folder chapter's own ComicInfo.xml is replaced, not packed as a page
+    sample_info().write(&chapter_path).unwrap();
+
+    cbz::pack_chapter(&chapter_path, &cbz_path, sample_info()).unwrap();
+
+    assert!(!chapter_path.exists());
+    assert!(cbz_path.ends_with("My Manga - Ch 0005.5.cbz"));
+    let mut names = cbz::entry_names(&cbz_path).unwrap();
+    names.sort();
+    assert_eq!(names, vec!["001.jpg", "002.jpg"]);
+
+    let mut archive = zip::ZipArchive::new(fs::File::open(&cbz_path).unwrap()).unwrap();
+    let mut xml = String::new();
+    archive
+        .by_name("ComicInfo.xml")
+        .unwrap()
+        .read_to_string(&mut xml)
+        .unwrap();
+    assert!(xml.contains("<Series>My Manga</Series>"));
+    assert!(xml.contains("<Number>5.5</Number>"));
+    assert!(xml.contains("<Writer>Author A</Writer>"));
+    assert!(xml.contains("<Year>2025</Year>"));
+    assert!(xml.contains("<PageCount>2</PageCount>"));
+}
+
+#[test]
+fn pack_chapter_merges_into_existing_archive() {
+    let dir = TempDir::new().unwrap();
+    let chapter_path = dir.path().join("chapter_0001");
+    let cbz_path = dir
+        .path()
+        .join(Layout::default().cbz_file(&sample_manga(), "0001", None));
+
+    write_pages(&chapter_path, &["001.jpg"]);
+    cbz::pack_chapter(&chapter_path, &cbz_path, sample_info()).unwrap();
+    write_pages(&chapter_path, &["002.jpg"]);
+    cbz::pack_chapter(&chapter_path, &cbz_path, sample_info()).unwrap();
+
+    let pages = existing_chapter_pages(&Layout::default(), &chapter_path, Some(&cbz_path));
+    assert_eq!(pages.len(), 2);
+    assert!(pages.contains(&1) && pages.contains(&2));
+}
+
+#[test]
+fn local_chapters_include_cbz() {
+    let dir = TempDir::new().unwrap();
+    let packed = dir.path().join("chapter_0001");
+    write_pages(&packed, &["001.jpg", "002.jpg"]);
+    cbz::pack_chapter(
+        &packed,
+        &dir.path().join(Layout::default().cbz_file(
+            &Manga::new("h1", "Old Title", "old_title", "", ""),
+            "0001",
+            None,
+        )),
+        sample_info(),
+    )
+    .unwrap();
+    write_pages(&dir.path().join("chapter_0002"), &["001.jpg"]);
+
+    let chapters = local_chapters(&Layout::default(), dir.path()).unwrap();
+    assert_eq!(chapters.len(), 2);
+    assert_eq!(chapters[0].number, "0001");
+    assert_eq!(chapters[0].pages, 2);
+    assert_eq!(chapters[1].pages, 1);
+}
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    image::RgbImage::new(width, height)
+        .write_to(
+            &mut std::io::Cursor::new(&mut bytes),
+            image::ImageFormat::Png,
+        )
+        .unwrap();
+    bytes
+}
+
+fn read_entry(archive: &mut zip::ZipArchive<fs::File>, name: &str) -> String {
+    let mut text = String::new();
+    archive
+        .by_name(name)
+        .unwrap()
+        .read_to_string(&mut text)
+        .unwrap();
+    text
+}
+
+#[test]
+fn load_chapters_filters_by_range_and_orders_pages() {
+    let dir = TempDir::new().unwrap();
+    for number in ["0001", "0002", "0003"] {
+        let chapter_path = dir.path().join(format!("chapter_{number}"));
+        fs::create_dir_all(&chapter_path).unwrap();
+        fs::write(chapter_path.join("002.png"), png(2, 2)).unwrap();
+        fs::write(chapter_path.join("001.png"), png(2, 2)).unwrap();
+    }
+
+    let range = ChapterRange::parse("2..").unwrap();
+    let chapters = load_chapters(&Layout::default(), dir.path(), Some(&range)).unwrap();
+
+    let numbers: Vec<&str> = chapters.iter().map(|ch| ch.number.as_str()).collect();
+    assert_eq!(numbers, vec!["0002", "0003"]);
+    let names: Vec<&str> = chapters[0].pages.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, vec!["001.png", "002.png"]);
+    assert_eq!(
+        export_file_name("My Manga", &chapters, ExportFormat::Epub),
+        "My Manga - Ch 0002-0003.epub"
+    );
+}
+
+#[test]
+fn write_epub_builds_fixed_layout_book() {
+    let dir = TempDir::new().unwrap();
+    let manga = Manga::new(
+        "h1",
+        "My & Manga",
+        "my_manga",
+        "Author A, Author B",
+        "Ongoing",
+    );
+    let page = |name: &str, width, height| PageImage {
+        name: name.to_string(),
+        bytes: png(width, height),
+    };
+    let chapters = vec![
+        ExportChapter {
+            number: "0001".to_string(),
+            volume: None,
+            pages: vec![page("001.png", 30, 40), page("002.png", 30, 40)],
+        },
+        ExportChapter {
+            number: "0001.5".to_string(),
+            volume: None,
+            pages: vec![page("001.png", 50, 20)],
+        },
+    ];
+    let path = dir.path().join("book.epub");
+
+    write_epub(&path, &manga, &chapters, None, ReadingDirection::Ltr).unwrap();
+
+    let mut archive = zip::ZipArchive::new(fs::File::open(&path).unwrap()).unwrap();
+    {
+        let mut mimetype = archive.by_index(0).unwrap();
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), zip::CompressionMethod::Stored);
+        let mut text = String::new();
+        mimetype.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "application/epub+zip");
+    }
+
+    let opf = read_entry(&mut archive, "OEBPS/content.opf");
+    assert!(opf.contains("<dc:title>My &amp; Manga (Ch. 1-1.5)</dc:title>"));
+    assert!(opf.contains("<dc:creator>Author B</dc:creator>"));
+    assert!(opf.contains("rendition:layout\">pre-paginated"));
+    assert!(opf.contains("id=\"img_c0001_p0001\" href=\"images/c0001_p0001.png\" media-type=\"image/png\" properties=\"cover-image\""));
+    assert_eq!(opf.matches("<itemref ").count(), 3);
+    assert!(opf.contains("<spine page-progression-direction=\"ltr\">"));
+
+    let nav = read_entry(&mut archive, "OEBPS/nav.xhtml");
+    assert!(nav.contains("<a href=\"pages/c0001_p0001.xhtml\">Chapter 1</a>"));
+    assert!(nav.contains("<a href=\"pages/c0002_p0001.xhtml\">Chapter 1.5</a>"));
+
+    let page = read_entry(&mut archive, "OEBPS/pages/c0002_p0001.xhtml");
+    assert!(page.contains("content=\"width=50, height=20\""));
+}
+
+#[test]
+fn write_epub_puts_cover_first() {
+    let dir = TempDir::new().unwrap();
+    let manga = Manga::new("h1", "My Manga", "my_manga", "", "");
+    let chapters = vec![ExportChapter {
+        number: "0001".to_string(),
+        volume: None,
+        pages: vec![PageImage {
+            name: "001.png".to_string(),
+            bytes: png(4, 4),
+        }],
+    }];
+    let cover = PageImage {
+        name: "cover.png".to_string(),
+        bytes: png(8, 8),
+    };
+    let path = dir.path().join("book.epub");
+
+    write_epub(
+        &path,
+        &manga,
+        &chapters,
+        Some(&cover),
+        ReadingDirection::Rtl,
+    )
+    .unwrap();
+
+    let mut archive = zip::ZipArchive::new(fs::File::open(&path).unwrap()).unwrap();
+    let opf = read_entry(&mut archive, "OEBPS/content.opf");
+    assert!(opf
+        .contains("href=\"images/cover.png\" media-type=\"image/png\" properties=\"cover-image\""));
+    let first_itemref = opf.find("<itemref idref=\"page_cover\"/>").unwrap();
+    assert!(first_itemref < opf.find("page_c0001_p0001\"/>").unwrap());
+    assert!(opf.contains("<spine page-progression-direction=\"rtl\">"));
+}
+
+fn jpeg(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    image::RgbImage::new(width, height)
+        .write_to(
+            &mut std::io::Cursor::new(&mut bytes),
+            image::ImageFormat::Jpeg,
+        )
+        .unwrap();
+    bytes
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+#[test]
+fn write_pdf_sizes_pages_to_images_with_outline() {
+    let dir = TempDir::new().unwrap();
+    let manga = Manga::new("h1", "My Manga", "my_manga", "Author A", "Ongoing");
+    let photo = jpeg(16, 24);
+    let chapters = vec![
+        ExportChapter {
+            number: "0001".to_string(),
+            volume: None,
+            pages: vec![
+                PageImage {
+                    name: "001.jpg".to_string(),
+                    bytes: photo.clone(),
+                },
+                PageImage {
+                    name: "002.png".to_string(),
+                    bytes: png(30, 40),
+                },
+            ],
+        },
+        ExportChapter {
+            number: "0002.5".to_string(),
+            volume: None,
+            pages: vec![PageImage {
+                name: "001.png".to_string(),
+                bytes: png(50, 20),
+            }],
+        },
+    ];
+    let path = dir.path().join("book.pdf");
+
+    write_pdf(&path, &manga, &chapters, ReadingDirection::Ltr).unwrap();
+
+    let bytes = fs::read(&path).unwrap();
+    assert!(bytes.starts_with(b"%PDF-1.7"));
+    assert!(bytes.ends_with(b"%%EOF\n"));
+    let text = String::from_utf8_lossy(&bytes);
+    assert_eq!(text.matches("/Type /Page ").count(), 3);
+    assert!(text.contains("/MediaBox [0 0 16 24]"));
+    assert!(text.contains("/MediaBox [0 0 50 20]"));
+    assert!(text.contains("/Count 3 >>"));
+    // JPEGs are embedded untouched
+    assert!(text.contains("/Filter /DCTDecode"));
+    assert!(contains(&bytes, &photo));
+    assert!(text.contains("/Title (Chapter 1) "));
+    assert!(text.contains("/Title (Chapter 2.5) "));
+    assert!(text.contains("/Author (Author A)"));
+    assert!(!text.contains("/ViewerPreferences"));
+
+    // every xref entry points at its object
+    let startxref = text.rfind("startxref\n").unwrap();
+    let xref: usize = text[startxref + 10..]
+        .lines()
+        .next()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let entries: Vec<&str> = text[xref..]
+        .lines()
+        .skip(3)
+        .take_while(|l| l.ends_with(" n "))
+        .collect();
+    for (i, entry) in entries.iter().enumerate() {
+        let offset: usize = entry[..10].parse().unwrap();
+        assert!(text[offset..].starts_with(&format!("{} 0 obj", i + 1)));
+    }
+}
+
+#[test]
+fn write_pdf_keeps_alpha_as_soft_mask() {
+    let dir = TempDir::new().unwrap();
+    let manga = Manga::new("h1", "My Manga", "my_manga", "", "");
+    let mut bytes = Vec::new();
+    image::RgbaImage::new(4, 4)
+        .write_to(
+            &mut std::io::Cursor::new(&mut bytes),
+            image::ImageFormat::Png,
+        )
+        .unwrap();
+    let chapters = vec![ExportChapter {
+        number: "0001".to_string(),
+        volume: None,
+        pages: vec![PageImage {
+            name: "001.png".to_string(),
+            bytes,
+        }],
+    }];
+    let path = dir.path().join("book.pdf");
+
+    write_pdf(&path, &manga, &chapters, ReadingDirection::Rtl).unwrap();
+
+    let text = String::from_utf8_lossy(&fs::read(&path).unwrap()).into_owned();
+    assert!(text.contains("/SMask "));
+    assert!(text.contains("/Filter /FlateDecode"));
+    assert!(text.contains("/ViewerPreferences << /Direction /R2L >>"));
+}
+
+/// An RGB JPEG rewritten to claim four components behind an Adobe APP14
+/// marker; only the headers matter since the bytes are passed through.
+fn adobe_cmyk_jpeg(width: u32, height: u32) -> Vec<u8> {
+    let rgb = jpeg(width, height);
+    let sof = rgb.windows(2).position(|w| w == [0xff, 0xc0]).unwrap();
+    let len = u16::from_be_bytes([rgb[sof + 2], rgb[sof + 3]]);
+    let mut bytes = rgb[..2].to_vec();
+    bytes.extend_from_slice(&[0xff, 0xee, 0x00, 0x0e]);
+    bytes.extend_from_slice(b"Adobe\x00\x64\x00\x00\x00\x00\x00");
+    bytes.extend_from_slice(&rgb[2..sof + 2]);
+    bytes.extend_from_slice(&(len + 3).to_be_bytes());
+    bytes.extend_from_slice(&rgb[sof + 4..sof + 9]);
+    bytes.push(4);
+    bytes.extend_from_slice(&rgb[sof + 10..sof + 2 + len as usize]);
+    bytes.extend_from_slice(&[4, 0x11, 0]);
+    bytes.extend_from_slice(&rgb[sof + 2 + len as usize..]);
+    bytes
+}
+
+fn single_page(bytes: Vec<u8>) -> Vec<ExportChapter> {
+    vec![ExportChapter {
+        number: "0001".to_string(),
+        volume: None,
+        pages: vec![PageImage {
+            name: "001.img".to_string(),
+            bytes,
+        }],
+    }]
+}
+
+#[test]
+fn write_pdf_keeps_16_bit_samples() {
+    let dir = TempDir::new().unwrap();
+    let manga = Manga::new("h1", "My Manga", "my_manga", "", "");
+    let mut gray = Vec::new();
+    image::ImageBuffer::<image::Luma<u16>, _>::from_pixel(2, 2, image::Luma([0x1234]))
+        .write_to(
+            &mut std::io::Cursor::new(&mut gray),
+            image::ImageFormat::Png,
+        )
+        .unwrap();
+    let path = dir.path().join("gray.pdf");
+
+    write_pdf(&path, &manga, &single_page(gray), ReadingDirection::Ltr).unwrap();
+
+    let bytes = fs::read(&path).unwrap();
+    let text = String::from_utf8_lossy(&bytes);
+    assert!(text.contains("/ColorSpace /DeviceGray /BitsPerComponent 16 /Filter /FlateDecode"));
+    let start = bytes
+        .windows(20)
+        .position(|w| w == b"/FlateDecode /Length")
+        .unwrap();
+    let stream = start
+        + bytes[start..]
+            .windows(7)
+            .position(|w| w == b"stream\n")
+            .unwrap()
+        + 7;
+    let mut samples = Vec::new();
+    flate2::read::ZlibDecoder::new(&bytes[stream..])
+        .read_to_end(&mut samples)
+        .unwrap();
+    assert_eq!(samples, [0x12, 0x34].repeat(4));
+
+    let mut rgba = Vec::new();
+    image::ImageBuffer::<image::Rgba<u16>, _>::from_pixel(2, 2, image::Rgba([1, 2, 3, 4]))
+        .write_to(
+            &mut std::io::Cursor::new(&mut rgba),
+            image::ImageFormat::Png,
+        )
+        .unwrap();
+    let path = dir.path().join("rgba.pdf");
+
+    write_pdf(&path, &manga, &single_page(rgba), ReadingDirection::Ltr).unwrap();
+
+    let text = String::from_utf8_lossy(&fs::read(&path).unwrap()).into_owned();
+    assert!(text.contains("/ColorSpace /DeviceRGB /BitsPerComponent 16 "));
+    assert!(text.contains("/ColorSpace /DeviceGray /BitsPerComponent 16 /Filter /FlateDecode"));
+    assert!(text.contains("/SMask "));
+}
+
+#[test]
+fn write_pdf_marks_cmyk_jpegs() {
+    let dir = TempDir::new().unwrap();
+    let manga = Manga::new("h1", "My Manga", "my_manga", "", "");
+    let photo = adobe_cmyk_jpeg(8, 8);
+    let path = dir.path().join("book.pdf");
+
+    write_pdf(
+        &path,
+        &manga,
+        &single_page(photo.clone()),
+        ReadingDirection::Ltr,
+    )
+    .unwrap();
+
+    let bytes = fs::read(&path).unwrap();
+    let text = String::from_utf8_lossy(&bytes);
+    assert!(text.contains(
+        "/ColorSpace /DeviceCMYK /BitsPerComponent 8 /Filter /DCTDecode /Decode [1 0 1 0 1 0 1 0]"
+    ));
+    assert!(contains(&bytes, &photo));
+}
+
+fn text_chapter(number: &str, pages: &[&str]) -> ExportChapter {
+    ExportChapter {
+        number: number.to_string(),
+        volume: None,
+        pages: pages
+            .iter()
+            .map(|name| PageImage {
+                name: name.to_string(),
+                bytes: name.as_bytes().to_vec(),
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn volumes_come_from_mapping_then_db() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join(VOLUMES_FILE), "1 = \"1..2\"\n").unwrap();
+    let mut chapters = vec![
+        text_chapter("0001", &[]),
+        text_chapter("0002.5", &[]),
+        text_chapter("0003", &[]),
+        text_chapter("0004", &[]),
+    ];
+    let stored = vec![LocalChapter {
+        number: "0003".to_string(),
+        pages: 0,
+        volume: Some(2),
+    }];
+
+    assign_volumes(
+        &mut chapters,
+        &VolumeMap::read(dir.path()).unwrap(),
+        &stored,
+    );
+
+    let volumes: Vec<_> = chapters.iter().map(|ch| ch.volume).collect();
+    assert_eq!(volumes, vec![Some(1), Some(1), Some(2), None]);
+
+    let bundles = group_by_volume(chapters);
+    let names: Vec<String> = bundles
+        .iter()
+        .map(|b| export_file_name("My Manga", b, ExportFormat::Cbz))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "My Manga - Volume 01.cbz",
+            "My Manga - Volume 02.cbz",
+            "My Manga - Ch 0004.cbz"
+        ]
+    );
+    assert_eq!(bundle_title("My Manga", &bundles[0]), "My Manga Vol. 1");
+    assert_eq!(bundle_title("My Manga", &bundles[2]), "My Manga (Ch. 4)");
+}
+
+#[test]
+fn cbz_bundle_holds_chapter_folders() {
+    let dir = TempDir::new().unwrap();
+    let manga = Manga::new("h1", "My Manga", "my_manga", "Author A", "Ongoing");
+    let mut chapters = vec![
+        text_chapter("0001", &["001.jpg", "002.jpg"]),
+        text_chapter("0002", &["001.jpg"]),
+    ];
+    for chapter in &mut chapters {
+        chapter.volume = Some(3);
+    }
+    let path = dir
+        .path()
+        .join(export_file_name(&manga.name, &chapters, ExportFormat::Cbz));
+
+    cbz::write_bundle(
+        &Layout::default(),
+        &path,
+        &manga,
+        &chapters,
+        ReadingDirection::Rtl,
+    )
+    .unwrap();
+
+    assert!(path.ends_with("My Manga - Volume 03.cbz"));
+    let mut names = cbz::entry_names(&path).unwrap();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            "chapter_0001/001.jpg",
+            "chapter_0001/002.jpg",
+            "chapter_0002/001.jpg"
+        ]
+    );
+    let mut archive = zip::ZipArchive::new(fs::File::open(&path).unwrap()).unwrap();
+    let xml = read_entry(&mut archive, "ComicInfo.xml");
+    assert!(xml.contains("<Title>My Manga Vol. 3</Title>"));
+    assert!(xml.contains("<Volume>3</Volume>"));
+    assert!(xml.contains("<PageCount>3</PageCount>"));
+    assert!(xml.contains("<Manga>YesAndRightToLeft</Manga>"));
+}
+
+#[test]
+fn folder_export_copies_chapters() {
+    let dir = TempDir::new().unwrap();
+    let manga = sample_manga();
+    let chapters = vec![text_chapter("0001", &["001.jpg"])];
+    let path = dir.path().join(export_file_name(
+        "My Manga",
+        &chapters,
+        ExportFormat::Folder,
+    ));
+
+    write_folder(&Layout::default(), &path, &manga, &chapters).unwrap();
+
+    assert!(path.ends_with("My Manga - Ch 0001"));
+    assert_eq!(
+        fs::read(path.join("chapter_0001/001.jpg")).unwrap(),
+        b"001.jpg"
+    );
+}
+
+#[test]
+fn series_json_follows_mylar_schema() {
+    let dir = TempDir::new().unwrap();
+    let mut first = Chapter::new("c1", "0001");
+    first.released = Some("2019-04-01T10:00:00Z".to_string());
+    let mut second = Chapter::new("c2", "0002");
+    second.released = Some("2020-01-05".to_string());
+    let chapters = vec![second, first, Chapter::new("c3", "0003")];
+    let mut manga = sample_manga();
+    manga.status = "Completed".to_string();
+
+    SeriesJson::new(&manga, &chapters)
+        .write(dir.path())
+        .unwrap();
+
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.path().join(SERIES_JSON_FILE)).unwrap())
+            .unwrap();
+    let metadata = &json["metadata"];
+    assert_eq!(json["version"], "1.0.2");
+    assert_eq!(metadata["type"], "comicSeries");
+    assert_eq!(metadata["name"], "My Manga");
+    assert_eq!(metadata["year"], 2019);
+    assert_eq!(metadata["total_issues"], 3);
+    assert_eq!(metadata["status"], "Ended");
+    assert_eq!(metadata["description_text"], "By Author A");
+    assert_eq!(metadata["publisher"], "");
+}
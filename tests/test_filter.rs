@@ -0,0 +1,68 @@
+use image::{DynamicImage, GrayImage, ImageFormat, Luma, RgbImage};
+use mgdl::filter::{dhash, dhash_file, Blocklist, MAX_DISTANCE};
+use std::io::Cursor;
+use tempfile::TempDir;
+
+/// A credits-like page: dark text bands on white.
+fn credits(width: u32, height: u32) -> DynamicImage {
+    DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+        let band = (y * 10 / height) % 2 == 1 && x > width / 8 && x < width * 7 / 8;
+        Luma([if band { 30 } else { 250 }])
+    }))
+}
+
+/// A page with a diagonal gradient, nothing like the credits.
+fn art(width: u32, height: u32) -> DynamicImage {
+    DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+        Luma([((x * 255 / width + (height - y) * 128 / height) % 256) as u8])
+    }))
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut bytes), format)
+        .unwrap();
+    bytes
+}
+
+#[test]
+fn dhash_survives_scaling_and_recompression() {
+    let original = dhash(&credits(800, 1200));
+    let small = dhash(&credits(400, 600));
+    let jpeg =
+        dhash(&image::load_from_memory(&encode(&credits(800, 1200), ImageFormat::Jpeg)).unwrap());
+    let color = dhash(&DynamicImage::ImageRgb8(credits(800, 1200).to_rgb8()));
+
+    for other in [small, jpeg, color] {
+        assert!((original ^ other).count_ones() <= MAX_DISTANCE);
+    }
+    assert!((original ^ dhash(&art(800, 1200))).count_ones() > MAX_DISTANCE);
+}
+
+#[test]
+fn blocklist_matches_similar_pages_only() {
+    let blocklist = Blocklist::new(vec![dhash(&credits(800, 1200))], MAX_DISTANCE);
+    assert!(blocklist.matches(&encode(&credits(640, 960), ImageFormat::Png)));
+    assert!(!blocklist.matches(&encode(&art(640, 960), ImageFormat::Png)));
+    assert!(!blocklist.matches(b"not an image"));
+    assert!(!Blocklist::default().matches(&encode(&credits(640, 960), ImageFormat::Png)));
+
+    // every hash is within 64 bits of every other
+    let loose = Blocklist::new(vec![dhash(&credits(800, 1200))], 64);
+    assert!(loose.matches(&encode(&art(640, 960), ImageFormat::Png)));
+}
+
+#[test]
+fn dhash_file_reads_sample_pages() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("credits.png");
+    DynamicImage::ImageRgb8(RgbImage::from_fn(90, 80, |x, _| {
+        image::Rgb([(x * 2) as u8, 0, 0])
+    }))
+    .save(&path)
+    .unwrap();
+    // brightness rises to the right, so no pixel is brighter than the next
+    assert_eq!(dhash_file(&path).unwrap(), 0);
+    assert!(dhash_file(&dir.path().join("missing.png")).is_err());
+}
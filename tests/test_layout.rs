@@ -0,0 +1,164 @@
+use mgdl::layout::{Layout, LayoutConfig};
+use mgdl::models::Manga;
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn manga(name: &str) -> Manga {
+    Manga::new("h1", name, "", "", "")
+}
+
+fn custom() -> Layout {
+    Layout::new(LayoutConfig {
+        series: "{title} [{hash}]".to_string(),
+        chapter: "Vol {volume:02}/Ch {chapter:03}".to_string(),
+        page: "p{page:04}.{ext}".to_string(),
+        cbz: "Vol {volume:02}/{title} {chapter:03}".to_string(),
+    })
+    .unwrap()
+}
+
+#[test]
+fn default_chapter_dir_roundtrip() {
+    let layout = Layout::default();
+    let dir = TempDir::new().unwrap();
+    let name = layout.chapter_dir(&manga("My Manga"), "0010", None);
+    assert_eq!(name, PathBuf::from("chapter_0010"));
+    fs::create_dir_all(dir.path().join(&name)).unwrap();
+
+    let found = layout.find_chapter_dirs(dir.path());
+    assert_eq!(found.len(), 1);
+    assert_eq!(found["0010"].path, dir.path().join(name));
+}
+
+#[test]
+fn default_chapter_dirs_read_old_numbers() {
+    let dir = TempDir::new().unwrap();
+    for name in ["chapter_0001-01", "chapter_0001-05", "chapter_0002.1"] {
+        fs::create_dir_all(dir.path().join(name)).unwrap();
+    }
+    let found = Layout::default().find_chapter_dirs(dir.path());
+    assert_eq!(found["0001"].path, dir.path().join("chapter_0001-01"));
+    assert_eq!(found["0001.5"].path, dir.path().join("chapter_0001-05"));
+    assert_eq!(found["0002.1"].path, dir.path().join("chapter_0002.1"));
+}
+
+#[test]
+fn default_chapter_dirs_skip_other_folders() {
+    let dir = TempDir::new().unwrap();
+    for name in ["covers", "chapter_", "chapter_ab-01"] {
+        fs::create_dir_all(dir.path().join(name)).unwrap();
+    }
+    fs::write(dir.path().join("chapter_0001-01"), b"not a folder").unwrap();
+    assert!(Layout::default().find_chapter_dirs(dir.path()).is_empty());
+}
+
+#[test]
+fn default_cbz_file_roundtrip() {
+    let layout = Layout::default();
+    let dir = TempDir::new().unwrap();
+    assert_eq!(
+        layout.cbz_file(&manga("A/B"), "0010", None),
+        PathBuf::from("A_B - Ch 0010.cbz")
+    );
+    assert_eq!(
+        layout.cbz_file(&manga("A/B"), "0005.5", None),
+        PathBuf::from("A_B - Ch 0005.5.cbz")
+    );
+    for name in [
+        "Any Title - Ch 0010.cbz",
+        "Other - Ch 0005.5.cbz",
+        "0010.cbz",
+        "X - Ch extra.cbz",
+    ] {
+        fs::write(dir.path().join(name), b"").unwrap();
+    }
+
+    let found = layout.find_cbz_files(dir.path());
+    let mut numbers: Vec<_> = found.keys().cloned().collect();
+    numbers.sort();
+    assert_eq!(numbers, vec!["0005.5", "0010", "special-extra"]);
+}
+
+#[test]
+fn custom_layout_nests_chapters_in_volumes() {
+    let layout = custom();
+    let dir = TempDir::new().unwrap();
+    let manga = manga("My: Manga");
+    assert_eq!(
+        layout.series_folder(&manga.name, &manga.hash),
+        "My_ Manga [h1]"
+    );
+
+    let chapter = layout.chapter_dir(&manga, "0007.5", Some(2));
+    assert_eq!(chapter, PathBuf::from("Vol 02/Ch 007.5"));
+    assert_eq!(
+        layout.chapter_dir(&manga, "0012", None),
+        PathBuf::from("Vol 00/Ch 012")
+    );
+    let cbz = layout.cbz_file(&manga, "0008", Some(3));
+    assert_eq!(cbz, PathBuf::from("Vol 03/My_ Manga 008.cbz"));
+
+    fs::create_dir_all(dir.path().join(&chapter)).unwrap();
+    fs::create_dir_all(dir.path().join("Vol 02/notes")).unwrap();
+    fs::create_dir_all(dir.path().join(cbz.parent().unwrap())).unwrap();
+    fs::write(dir.path().join(&cbz), b"").unwrap();
+
+    let dirs = layout.find_chapter_dirs(dir.path());
+    assert_eq!(dirs.len(), 1);
+    assert_eq!(dirs["0007.5"].volume, Some(2));
+    assert_eq!(dirs["0007.5"].path, dir.path().join(chapter));
+    let cbzs = layout.find_cbz_files(dir.path());
+    assert_eq!(cbzs.len(), 1);
+    assert_eq!(cbzs["0008"].volume, Some(3));
+}
+
+#[test]
+fn page_names_follow_template() {
+    let layout = custom();
+    assert_eq!(layout.page_file_name(12, "webp"), "p0012.webp");
+    assert_eq!(layout.page_number("p0012.webp"), Some(12));
+    assert_eq!(layout.page_number("012.webp"), None);
+
+    let old = Layout::default();
+    assert_eq!(layout.convert_page_name(&old, "012.jpg"), "p0012.jpg");
+    assert_eq!(old.convert_page_name(&layout, "p0003.png"), "003.png");
+
+    assert_eq!(layout.slice_file_name(12, 3, "jpg"), "p0012_03.jpg");
+    assert_eq!(layout.page_number("p0012_03.jpg"), Some(12));
+    assert_eq!(layout.page_number("p0012_x.jpg"), None);
+    assert_eq!(old.convert_page_name(&layout, "p0012_03.jpg"), "012_03.jpg");
+    assert_eq!(
+        layout.convert_page_name(&old, "ComicInfo.xml"),
+        "ComicInfo.xml"
+    );
+}
+
+#[test]
+fn layout_rejects_bad_templates() {
+    let bad = [
+        LayoutConfig {
+            series: "{title}/{hash}".to_string(),
+            ..Default::default()
+        },
+        LayoutConfig {
+            chapter: "chapter_{volume}".to_string(),
+            ..Default::default()
+        },
+        LayoutConfig {
+            page: "{page}.jpg".to_string(),
+            ..Default::default()
+        },
+        LayoutConfig {
+            cbz: "{title} {chapter:x}".to_string(),
+            ..Default::default()
+        },
+        LayoutConfig {
+            series: "{name} {page}".to_string(),
+            ..Default::default()
+        },
+    ];
+    for config in bad {
+        assert!(Layout::new(config.clone()).is_err(), "{config:?}");
+    }
+}
@@ -0,0 +1,90 @@
+use mgdl::layout::Layout;
+use mgdl::library::{existing_page_numbers, local_chapters, Sidecar, VolumeMap, VOLUMES_FILE};
+use mgdl::models::{LocalChapter, Manga};
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn sidecar_roundtrip() {
+    let dir = TempDir::new().unwrap();
+    let manga = Manga::new("h1", "Title", "title", "Author A", "Ongoing");
+    Sidecar::new(&manga, "https://example.com/series/h1")
+        .write(dir.path())
+        .unwrap();
+
+    let sidecar = Sidecar::read(dir.path()).unwrap().unwrap();
+    assert_eq!(sidecar.hash, "h1");
+    assert_eq!(sidecar.url, "https://example.com/series/h1");
+
+    let restored = sidecar.to_manga("other_folder");
+    assert_eq!(restored.name, "Title");
+    assert_eq!(restored.normalized_name, "other_folder");
+    assert_eq!(restored.authors, "Author A");
+}
+
+#[test]
+fn sidecar_missing_is_none() {
+    let dir = TempDir::new().unwrap();
+    assert!(Sidecar::read(dir.path()).unwrap().is_none());
+}
+
+#[test]
+fn local_chapters_from_layout() {
+    let dir = TempDir::new().unwrap();
+    let ch1 = dir.path().join("chapter_0001");
+    let ch2 = dir.path().join("chapter_0002");
+    fs::create_dir_all(&ch1).unwrap();
+    fs::create_dir_all(&ch2).unwrap();
+    fs::create_dir_all(dir.path().join("extras")).unwrap();
+    fs::write(ch1.join("001.jpg"), b"x").unwrap();
+    fs::write(ch1.join("002.png"), b"x").unwrap();
+    fs::write(ch2.join("001.jpg"), b"x").unwrap();
+
+    let layout = Layout::default();
+    assert_eq!(existing_page_numbers(&layout, &ch1).len(), 2);
+    let chapters = local_chapters(&layout, dir.path()).unwrap();
+    assert_eq!(
+        chapters,
+        vec![
+            LocalChapter {
+                number: "0001".to_string(),
+                pages: 2,
+                volume: None,
+            },
+            LocalChapter {
+                number: "0002".to_string(),
+                pages: 1,
+                volume: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn volume_map_reads_ranges() {
+    let dir = TempDir::new().unwrap();
+    assert_eq!(VolumeMap::read(dir.path()).unwrap().volume_of(1), None);
+
+    fs::write(
+        dir.path().join(VOLUMES_FILE),
+        "1 = \"1..8\"\n2 = \"9..17\"\n10 = \"80\"\n",
+    )
+    .unwrap();
+    let volumes = VolumeMap::read(dir.path()).unwrap();
+    assert_eq!(volumes.volume_of(1), Some(1));
+    assert_eq!(volumes.volume_of(9), Some(2));
+    assert_eq!(volumes.volume_of(80), Some(10));
+    assert_eq!(volumes.volume_of(18), None);
+}
+
+#[test]
+fn volume_map_rejects_bad_entries() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join(VOLUMES_FILE), "one = \"1..8\"\n").unwrap();
+    assert!(VolumeMap::read(dir.path()).is_err());
+
+    fs::write(dir.path().join(VOLUMES_FILE), "1 = \"a..b\"\n").unwrap();
+    assert!(VolumeMap::read(dir.path()).is_err());
+    fs::write(dir.path().join(VOLUMES_FILE), "1 = \"latest:2\"\n").unwrap();
+    assert!(VolumeMap::read(dir.path()).is_err());
+}
@@ -1,8 +1,15 @@
 use mgdl::scrape::{
     get_chapter_pages, get_with_retry, manga_from_url, parse_chapters_from_html,
-    parse_manga_from_html,
+    parse_manga_from_html, Retry, INITIAL_DELAY,
 };
 
+fn retry(attempts: usize) -> Retry {
+    Retry {
+        attempts,
+        delay: INITIAL_DELAY,
+    }
+}
+
 fn base_url() -> String {
     std::env::var("MGDL_BASE_URL").expect("MGDL_BASE_URL env var must be set for live tests")
 }
@@ -28,7 +35,7 @@ async fn test_live_manga_scrape() {
     let manga_url = manga_url();
     let manga_hash = manga_hash();
 
-    let html = get_with_retry(&client, &manga_url, 3)
+    let html = get_with_retry(&client, &manga_url, retry(3))
         .await
         .expect("Failed to fetch manga page");
 
@@ -59,13 +66,19 @@ async fn test_live_chapter_list() {
     let manga_hash = manga_hash();
 
     let url = format!("{}/series/{}/full-chapter-list", base_url, manga_hash);
-    let html = get_with_retry(&client, &url, 3)
+    let html = get_with_retry(&client, &url, retry(3))
         .await
         .expect("Failed to fetch chapter list");
 
-    let chapters = parse_chapters_from_html(&html).expect(
+    let list = parse_chapters_from_html(&html).expect(
         "UPSTREAM FORMAT CHANGE: failed to parse chapter list — site likely changed their HTML structure",
     );
+    assert!(
+        list.warnings.is_empty(),
+        "UPSTREAM FORMAT CHANGE: chapter list entries skipped: {:?}",
+        list.warnings
+    );
+    let chapters = list.chapters;
 
     assert!(
         !chapters.is_empty(),
@@ -93,12 +106,12 @@ async fn test_live_chapter_pages() {
     let manga_hash = manga_hash();
 
     let url = format!("{}/series/{}/full-chapter-list", base_url, manga_hash);
-    let html = get_with_retry(&client, &url, 3).await.unwrap();
-    let chapters = parse_chapters_from_html(&html).unwrap();
+    let html = get_with_retry(&client, &url, retry(3)).await.unwrap();
+    let chapters = parse_chapters_from_html(&html).unwrap().chapters;
     assert!(!chapters.is_empty(), "Need at least one chapter");
 
     let last_chapter = chapters.last().unwrap();
-    let pages = get_chapter_pages(&client, &base_url, &last_chapter.hash, 3)
+    let pages = get_chapter_pages(&client, &base_url, &last_chapter.hash, retry(3))
         .await
         .expect(
             "UPSTREAM FORMAT CHANGE: failed to fetch chapter pages — site likely changed their HTML structure",
@@ -127,12 +140,12 @@ async fn test_live_full_manga_from_url() {
     let manga_url = manga_url();
     let manga_hash = manga_hash();
 
-    let (manga, chapters) = manga_from_url(&client, &base_url, &manga_url, 3)
+    let (manga, list) = manga_from_url(&client, &base_url, &manga_url, retry(3))
         .await
         .expect("UPSTREAM FORMAT CHANGE: manga_from_url failed end-to-end");
 
     assert!(!manga.name.is_empty());
-    assert!(!chapters.is_empty());
+    assert!(!list.chapters.is_empty());
     assert_eq!(manga.hash, manga_hash);
 }
 
@@ -145,21 +158,22 @@ async fn test_live_page_download() {
 
     let dir = tempfile::TempDir::new().unwrap();
     let url = format!("{}/series/{}/full-chapter-list", base_url, manga_hash);
-    let html = get_with_retry(&client, &url, 3).await.unwrap();
-    let chapters = parse_chapters_from_html(&html).unwrap();
+    let html = get_with_retry(&client, &url, retry(3)).await.unwrap();
+    let chapters = parse_chapters_from_html(&html).unwrap().chapters;
     let last = chapters.last().unwrap();
 
-    let pages = get_chapter_pages(&client, &base_url, &last.hash, 3)
+    let pages = get_chapter_pages(&client, &base_url, &last.hash, retry(3))
         .await
         .unwrap();
     let first_page = &pages[0];
+    let ext = mgdl::scrape::page_extension(&first_page.url).unwrap();
+    let file_name = mgdl::layout::Layout::default().page_file_name(first_page.number, ext);
 
     mgdl::scrape::download_page(
         &client,
         first_page.url.clone(),
-        dir.path().to_path_buf(),
-        first_page.number,
-        3,
+        dir.path().join(file_name),
+        retry(3),
     )
     .await
     .expect("Failed to download a page image");
@@ -184,7 +198,7 @@ async fn test_live_retry_mechanism() {
     let client = client();
     let manga_url = manga_url();
 
-    let result = get_with_retry(&client, &manga_url, 2).await;
+    let result = get_with_retry(&client, &manga_url, retry(2)).await;
     assert!(
         result.is_ok(),
         "get_with_retry should succeed on a valid URL: {:?}",
@@ -1,17 +1,16 @@
-use mgdl::models::{Chapter, ChapterRange, Manga};
+use mgdl::models::{Chapter, ChapterNumber, ChapterRange, Manga};
 
 #[test]
 fn chapter_range_full() {
     let r = ChapterRange::parse("1..100").unwrap();
-    assert_eq!(r.start, Some(1));
-    assert_eq!(r.end, Some(100));
+    assert!(r.contains(1));
+    assert!(r.contains(100));
+    assert!(!r.contains(101));
 }
 
 #[test]
 fn chapter_range_open_start() {
     let r = ChapterRange::parse("..50").unwrap();
-    assert_eq!(r.start, None);
-    assert_eq!(r.end, Some(50));
     assert!(r.contains(0));
     assert!(r.contains(50));
     assert!(!r.contains(51));
@@ -20,8 +19,6 @@ fn chapter_range_open_start() {
 #[test]
 fn chapter_range_open_end() {
     let r = ChapterRange::parse("10..").unwrap();
-    assert_eq!(r.start, Some(10));
-    assert_eq!(r.end, None);
     assert!(!r.contains(9));
     assert!(r.contains(10));
     assert!(r.contains(99999));
@@ -30,8 +27,6 @@ fn chapter_range_open_end() {
 #[test]
 fn chapter_range_single_chapter() {
     let r = ChapterRange::parse("42").unwrap();
-    assert_eq!(r.start, Some(42));
-    assert_eq!(r.end, Some(42));
     assert!(r.contains(42));
     assert!(!r.contains(41));
     assert!(!r.contains(43));
@@ -51,30 +46,186 @@ fn chapter_range_invalid_input() {
     assert!(ChapterRange::parse("abc..5").is_err());
 }
 
+fn numbers(chapters: &[Chapter]) -> Vec<&str> {
+    chapters.iter().map(|ch| ch.number.as_str()).collect()
+}
+
+fn chapters(numbers: &[&str]) -> Vec<Chapter> {
+    numbers.iter().map(|n| Chapter::new("hash", n)).collect()
+}
+
+#[test]
+fn chapter_range_decimals() {
+    let all = chapters(&["0005", "0005.5", "0006", "0010.5", "0011"]);
+    let r = ChapterRange::parse("5.5").unwrap();
+    assert_eq!(numbers(&r.select(all.clone())), vec!["0005.5"]);
+    // a whole end bound takes in the chapter's decimals
+    let r = ChapterRange::parse("6..10").unwrap();
+    assert_eq!(numbers(&r.select(all.clone())), vec!["0006", "0010.5"]);
+    let r = ChapterRange::parse("5.5..6").unwrap();
+    assert_eq!(numbers(&r.select(all)), vec!["0005.5", "0006"]);
+}
+
+#[test]
+fn chapter_range_lists() {
+    let all = chapters(&["0001", "0002", "0003", "0007", "0008", "0010"]);
+    let r = ChapterRange::parse("1, 3,7..9").unwrap();
+    assert_eq!(
+        numbers(&r.select(all)),
+        vec!["0001", "0003", "0007", "0008"]
+    );
+}
+
+#[test]
+fn chapter_range_latest() {
+    let all = chapters(&["0001", "0002", "0002.5", "0003", "0004"]);
+    let r = ChapterRange::parse("latest:3").unwrap();
+    assert!(!r.is_numeric());
+    assert_eq!(numbers(&r.select(all)), vec!["0002.5", "0003", "0004"]);
+}
+
+#[test]
+fn chapter_range_dates() {
+    let mut all = chapters(&["0001", "0002", "0003", "0004"]);
+    all[0].released = Some("2023-12-31".to_string());
+    all[1].released = Some("2024-01-01".to_string());
+    all[2].released = Some("2024-03-15".to_string());
+    let r = ChapterRange::parse("2024-01-01..").unwrap();
+    assert_eq!(numbers(&r.select(all.clone())), vec!["0002", "0003"]);
+    let r = ChapterRange::parse("..2024-01-01").unwrap();
+    assert_eq!(numbers(&r.select(all)), vec!["0001", "0002"]);
+}
+
+#[test]
+fn chapter_range_exclusions() {
+    let all = chapters(&["0010", "0011", "0012", "0013"]);
+    let r = ChapterRange::parse("10..13,!12").unwrap();
+    assert_eq!(
+        numbers(&r.select(all.clone())),
+        vec!["0010", "0011", "0013"]
+    );
+    // exclusions alone keep everything else
+    let r = ChapterRange::parse("!10..11").unwrap();
+    assert_eq!(numbers(&r.select(all)), vec!["0012", "0013"]);
+    assert!(!r.contains(10));
+    assert!(r.contains(12));
+}
+
+#[test]
+fn chapter_range_rejects_bad_terms() {
+    assert!(ChapterRange::parse("10..5").is_err());
+    assert!(ChapterRange::parse("latest:0").is_err());
+    assert!(ChapterRange::parse("latest:x").is_err());
+    assert!(ChapterRange::parse("1,,3").is_err());
+    assert!(ChapterRange::parse("").is_err());
+    assert!(ChapterRange::parse("2024-13-01..").is_err());
+}
+
 #[test]
 fn chapter_major_number_standard() {
-    let ch = Chapter::new("hash", "0010-01");
+    let ch = Chapter::new("hash", "0010");
     assert_eq!(ch.major_number(), Some(10));
 }
 
 #[test]
 fn chapter_major_number_zero() {
-    let ch = Chapter::new("hash", "0000-01");
+    let ch = Chapter::new("hash", "0000");
     assert_eq!(ch.major_number(), Some(0));
 }
 
 #[test]
 fn chapter_major_number_large() {
-    let ch = Chapter::new("hash", "9999-01");
+    let ch = Chapter::new("hash", "9999");
     assert_eq!(ch.major_number(), Some(9999));
 }
 
 #[test]
 fn chapter_major_number_decimal() {
-    let ch = Chapter::new("hash", "0005-05");
+    let ch = Chapter::new("hash", "0005.5");
     assert_eq!(ch.major_number(), Some(5));
 }
 
+fn key(label: &str) -> String {
+    ChapterNumber::parse(label).unwrap().key()
+}
+
+#[test]
+fn chapter_number_keys() {
+    assert_eq!(key("5"), "0005");
+    assert_eq!(key("5.1"), "0005.1");
+    assert_eq!(key("5.10"), "0005.10");
+    assert_eq!(key("10A"), "0010a");
+    assert_eq!(key("Extra"), "special-extra");
+    assert_eq!(key("Side Story: 2"), "special-side-story-2");
+    assert!(ChapterNumber::parse("5.").is_none());
+    assert!(ChapterNumber::parse("10.5.2").is_none());
+    assert!(ChapterNumber::parse("!").is_none());
+}
+
+#[test]
+fn chapter_number_keys_roundtrip() {
+    for label in ["5", "5.1", "5.10", "10a", "extra", "0"] {
+        let number = ChapterNumber::parse(label).unwrap();
+        assert_eq!(ChapterNumber::from_key(&number.key()), Some(number.clone()));
+        assert_eq!(number.to_string(), label);
+    }
+}
+
+#[test]
+fn chapter_number_reads_old_keys() {
+    let old = |key| ChapterNumber::from_key(key).unwrap().key();
+    assert_eq!(old("0005-01"), "0005");
+    assert_eq!(old("0005-05"), "0005.5");
+    assert_eq!(old("0005-10"), "0005.10");
+    assert!(ChapterNumber::from_key("ab-01").is_none());
+    assert!(ChapterNumber::from_key("special-").is_none());
+}
+
+#[test]
+fn chapter_number_order() {
+    let mut numbers: Vec<ChapterNumber> = ["extra", "10a", "5.5", "5.10", "10", "5", "5.1", "4"]
+        .iter()
+        .map(|label| ChapterNumber::parse(label).unwrap())
+        .collect();
+    numbers.sort();
+    let labels: Vec<String> = numbers.iter().map(|n| n.to_string()).collect();
+    assert_eq!(
+        labels,
+        ["4", "5", "5.1", "5.10", "5.5", "10", "10a", "extra"]
+    );
+
+    // keys sort the same way
+    let mut keys: Vec<String> = numbers.iter().map(|n| n.key()).collect();
+    keys.sort();
+    assert_eq!(keys, numbers.iter().map(|n| n.key()).collect::<Vec<_>>());
+}
+
+#[test]
+fn chapter_range_tells_decimals_apart() {
+    let all = chapters(&["0005", "0005.1", "0005.10", "0005.5", "0010a"]);
+    let r = ChapterRange::parse("5.1").unwrap();
+    assert_eq!(numbers(&r.select(all.clone())), vec!["0005.1"]);
+    let r = ChapterRange::parse("5.10..5.5").unwrap();
+    assert_eq!(numbers(&r.select(all.clone())), vec!["0005.10", "0005.5"]);
+    let r = ChapterRange::parse("latest:4").unwrap();
+    assert_eq!(
+        numbers(&r.select(all.clone())),
+        vec!["0005.1", "0005.10", "0005.5", "0010a"]
+    );
+    let r = ChapterRange::parse("10").unwrap();
+    assert_eq!(numbers(&r.select(all)), vec!["0010a"]);
+    assert!(ChapterRange::parse("5.10..5.1").is_err());
+}
+
+#[test]
+fn chapter_range_skips_specials_unless_only_excluding() {
+    let all = chapters(&["0001", "0002", "special-extra"]);
+    let r = ChapterRange::parse("1..").unwrap();
+    assert_eq!(numbers(&r.select(all.clone())), vec!["0001", "0002"]);
+    let r = ChapterRange::parse("!1").unwrap();
+    assert_eq!(numbers(&r.select(all)), vec!["0002", "special-extra"]);
+}
+
 #[test]
 fn manga_new_fields() {
     let m = Manga::new("h1", "Name", "name", "Auth", "Ongoing");
@@ -0,0 +1,306 @@
+use image::{GenericImageView, ImageFormat, Rgba, RgbaImage};
+use mgdl::layout::Layout;
+use mgdl::library::existing_page_numbers;
+use mgdl::models::ReadingDirection::{Ltr, Rtl};
+use mgdl::process::{process_page, PageFormat, Profile};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Cursor;
+use tempfile::TempDir;
+
+fn encode(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+    let image = RgbaImage::from_fn(width, height, |x, _| Rgba([(x % 256) as u8, 40, 200, 255]));
+    let mut bytes = Vec::new();
+    let image = image::DynamicImage::ImageRgba8(image);
+    let image = if format == ImageFormat::Jpeg {
+        image::DynamicImage::ImageRgb8(image.to_rgb8())
+    } else {
+        image
+    };
+    image
+        .write_to(&mut Cursor::new(&mut bytes), format)
+        .unwrap();
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> image::DynamicImage {
+    image::load_from_memory(bytes).unwrap()
+}
+
+#[test]
+fn profile_converts_fits_and_grays() {
+    let profile = Profile {
+        format: Some(PageFormat::Jpeg),
+        width: Some(100),
+        height: Some(100),
+        grayscale: true,
+        contrast: 20.0,
+        quality: Some(70),
+        ..Default::default()
+    };
+    let processed = profile
+        .apply(&encode(400, 200, ImageFormat::Png), Ltr)
+        .unwrap()
+        .unwrap();
+    let bytes = &processed.pages[0];
+
+    assert_eq!(processed.format, PageFormat::Jpeg);
+    assert_eq!(image::guess_format(bytes).unwrap(), ImageFormat::Jpeg);
+    let image = decode(bytes);
+    assert_eq!(image.dimensions(), (100, 50));
+    assert!(!image.color().has_color());
+}
+
+#[test]
+fn profile_leaves_matching_pages_alone() {
+    let page = encode(80, 120, ImageFormat::Png);
+    let profile = Profile {
+        format: Some(PageFormat::Png),
+        width: Some(100),
+        ..Default::default()
+    };
+    assert!(profile.apply(&page, Ltr).unwrap().is_none());
+    assert!(Profile::default().apply(&page, Ltr).unwrap().is_none());
+
+    // only shrinks, never enlarges
+    let profile = Profile {
+        height: Some(60),
+        ..Default::default()
+    };
+    let processed = profile.apply(&page, Ltr).unwrap().unwrap();
+    assert_eq!(processed.format, PageFormat::Png);
+    assert_eq!(decode(&processed.pages[0]).dimensions(), (40, 60));
+}
+
+#[test]
+fn profile_writes_webp_and_avif() {
+    let page = encode(32, 48, ImageFormat::Jpeg);
+    for (format, image_format) in [
+        (PageFormat::Webp, ImageFormat::WebP),
+        (PageFormat::Avif, ImageFormat::Avif),
+    ] {
+        let profile = Profile {
+            format: Some(format),
+            ..Default::default()
+        };
+        let processed = profile.apply(&page, Ltr).unwrap().unwrap();
+        assert_eq!(
+            image::guess_format(&processed.pages[0]).unwrap(),
+            image_format
+        );
+    }
+}
+
+#[test]
+fn avif_pages_are_not_decoded() {
+    let page = encode(32, 48, ImageFormat::Avif);
+    let profile = Profile {
+        grayscale: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        profile.apply(&page, Ltr),
+        Err(mgdl::error::MgdlError::Process(_))
+    ));
+}
+
+#[test]
+fn unwritable_formats_become_png() {
+    let profile = Profile {
+        grayscale: true,
+        ..Default::default()
+    };
+    let processed = profile
+        .apply(&encode(10, 10, ImageFormat::Gif), Ltr)
+        .unwrap()
+        .unwrap();
+    assert_eq!(processed.format, PageFormat::Png);
+    assert_eq!(
+        image::guess_format(&processed.pages[0]).unwrap(),
+        ImageFormat::Png
+    );
+}
+
+/// A white strip with dark panels, separated by white gaps at the given rows.
+fn strip(width: u32, height: u32, gaps: &[std::ops::Range<u32>]) -> Vec<u8> {
+    let image = image::GrayImage::from_fn(width, height, |x, y| {
+        let gap = gaps.iter().any(|gap| gap.contains(&y));
+        image::Luma([if gap {
+            255
+        } else {
+            ((x * 7 + y * 3) % 200) as u8
+        }])
+    });
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .unwrap();
+    bytes
+}
+
+#[test]
+fn tall_strips_split_at_gaps() {
+    // screen of 50x100 on a 100 px wide strip is 200 px tall
+    let profile = Profile {
+        width: Some(50),
+        height: Some(100),
+        split_tall: true,
+        ..Default::default()
+    };
+    let processed = profile
+        .apply(&strip(100, 700, &[170..180, 540..560]), Ltr)
+        .unwrap()
+        .unwrap();
+    let heights: Vec<u32> = processed
+        .pages
+        .iter()
+        .map(|page| decode(page).height())
+        .collect();
+    // cuts at 179 and 559 (bottom of each gap) and 379 (no gap), each slice
+    // then fit to the 50 px wide screen
+    assert_eq!(heights, vec![90, 100, 90, 71]);
+
+    // pages up to two screens tall are left whole
+    let processed = profile.apply(&strip(100, 400, &[]), Ltr).unwrap().unwrap();
+    assert_eq!(processed.pages.len(), 1);
+}
+
+#[test]
+fn split_pages_are_written_as_slices() {
+    let dir = TempDir::new().unwrap();
+    let layout = Layout::default();
+    let page = dir.path().join("004.png");
+    fs::write(&page, strip(100, 900, &[])).unwrap();
+    let profile = Profile {
+        height: Some(300),
+        split_tall: true,
+        ..Default::default()
+    };
+
+    process_page(
+        &profile,
+        Ltr,
+        &page,
+        |slice, ext| {
+            dir.path().join(match slice {
+                Some(slice) => layout.slice_file_name(4, slice, ext),
+                None => layout.page_file_name(4, ext),
+            })
+        },
+        None,
+    )
+    .unwrap();
+
+    let mut names: Vec<String> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["004_01.png", "004_02.png", "004_03.png"]);
+    assert!(names.iter().all(|name| layout.page_number(name) == Some(4)));
+    assert_eq!(
+        existing_page_numbers(&layout, dir.path()),
+        HashSet::from([4])
+    );
+}
+
+#[test]
+fn spreads_split_in_reading_order() {
+    // left half dark, right half light
+    let spread = image::GrayImage::from_fn(200, 100, |x, _| {
+        image::Luma([if x < 100 { 20 } else { 230 }])
+    });
+    let mut bytes = Vec::new();
+    spread
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .unwrap();
+    let profile = Profile {
+        split_spreads: true,
+        ..Default::default()
+    };
+    let shade = |page: &Vec<u8>| decode(page).to_luma8().get_pixel(0, 0)[0];
+
+    let processed = profile.apply(&bytes, Rtl).unwrap().unwrap();
+    assert_eq!(processed.pages.len(), 2);
+    assert_eq!(decode(&processed.pages[0]).dimensions(), (100, 100));
+    assert_eq!(shade(&processed.pages[0]), 230);
+    assert_eq!(shade(&processed.pages[1]), 20);
+
+    let processed = profile.apply(&bytes, Ltr).unwrap().unwrap();
+    assert_eq!(shade(&processed.pages[0]), 20);
+
+    // portrait pages are single pages
+    let page = encode(100, 150, ImageFormat::Png);
+    assert!(profile.apply(&page, Rtl).unwrap().is_none());
+}
+
+#[test]
+fn process_page_replaces_or_keeps_original() {
+    let dir = TempDir::new().unwrap();
+    let chapter = dir.path().join("chapter_0001");
+    fs::create_dir_all(&chapter).unwrap();
+    let profile = Profile {
+        format: Some(PageFormat::Jpeg),
+        ..Default::default()
+    };
+    let target = |_, ext: &str| chapter.join(format!("001.{ext}"));
+
+    let page = chapter.join("001.png");
+    fs::write(&page, encode(20, 20, ImageFormat::Png)).unwrap();
+    process_page(&profile, Ltr, &page, target, None).unwrap();
+    assert!(!page.exists());
+    assert!(chapter.join("001.jpg").exists());
+
+    let page = chapter.join("002.webp");
+    let original = dir.path().join(".originals/chapter_0001/002.webp");
+    fs::write(&page, encode(20, 20, ImageFormat::WebP)).unwrap();
+    let keep = Profile {
+        keep_original: true,
+        ..profile
+    };
+    process_page(
+        &keep,
+        Ltr,
+        &page,
+        |_, ext| chapter.join(format!("002.{ext}")),
+        Some(&original),
+    )
+    .unwrap();
+    assert!(!page.exists());
+    assert!(original.exists());
+    assert_eq!(
+        image::guess_format(&fs::read(chapter.join("002.jpg")).unwrap()).unwrap(),
+        ImageFormat::Jpeg
+    );
+}
+
+#[test]
+fn profile_validation() {
+    assert!(Profile::default().validate("p").is_ok());
+    for bad in [
+        Profile {
+            width: Some(0),
+            ..Default::default()
+        },
+        Profile {
+            quality: Some(0),
+            ..Default::default()
+        },
+        Profile {
+            quality: Some(101),
+            ..Default::default()
+        },
+        Profile {
+            format: Some(PageFormat::Webp),
+            quality: Some(80),
+            ..Default::default()
+        },
+        Profile {
+            split_tall: true,
+            width: Some(100),
+            ..Default::default()
+        },
+    ] {
+        assert!(bad.validate("p").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 mod common;
 
+use mgdl::models::ChapterNumber;
 use mgdl::scrape::{parse_chapters_from_html, parse_manga_from_html, parse_pages_from_html};
 
 // URL only used for extract_hash parsing — domain is irrelevant
@@ -49,7 +50,7 @@ fn parse_manga_bad_url_fails() {
 #[test]
 fn parse_chapters_count_and_hashes() {
     let html = common::load_fixture("chapter_list.html");
-    let chapters = parse_chapters_from_html(&html).unwrap();
+    let chapters = parse_chapters_from_html(&html).unwrap().chapters;
 
     assert!(
         !chapters.is_empty(),
@@ -59,8 +60,8 @@ fn parse_chapters_count_and_hashes() {
     for ch in &chapters {
         assert!(!ch.hash.is_empty(), "Chapter hash should not be empty");
         assert!(
-            ch.number.contains('-'),
-            "Chapter number '{}' should be in XXXX-YY format",
+            ChapterNumber::from_key(&ch.number).is_some(),
+            "Chapter number '{}' should be a stored chapter number",
             ch.number
         );
     }
@@ -69,31 +70,154 @@ fn parse_chapters_count_and_hashes() {
 #[test]
 fn parse_chapters_number_format() {
     let html = common::load_fixture("chapter_list.html");
-    let chapters = parse_chapters_from_html(&html).unwrap();
+    let chapters = parse_chapters_from_html(&html).unwrap().chapters;
 
     for ch in &chapters {
-        let parts: Vec<&str> = ch.number.split('-').collect();
+        let major = ch.number.split('.').next().unwrap();
         assert_eq!(
-            parts.len(),
-            2,
-            "Expected XXXX-YY format, got: {}",
-            ch.number
-        );
-        assert_eq!(
-            parts[0].len(),
+            major.len(),
             4,
             "Major part should be 4 digits: {}",
             ch.number
         );
-        assert_eq!(
-            parts[1].len(),
-            2,
-            "Minor part should be 2 digits: {}",
+        assert!(
+            major.bytes().all(|b| b.is_ascii_digit()),
+            "Expected NNNN or NNNN.N format, got: {}",
             ch.number
         );
     }
 }
 
+#[test]
+fn parse_chapters_decimals_suffixes_and_specials() {
+    let html = r#"
+        <div><a href="https://example.com/chapters/c1"><span>Chapter 5</span></a></div>
+        <div><a href="https://example.com/chapters/c2"><span>Chapter 5.1</span></a></div>
+        <div><a href="https://example.com/chapters/c3"><span>Chapter 5.10</span></a></div>
+        <div><a href="https://example.com/chapters/c4"><span>Chapter 10A</span></a></div>
+        <div><a href="https://example.com/chapters/c5"><span>Chapter Extra</span></a></div>
+        <div><a href="https://example.com/chapters/c6"><span>Vol. 2 Oneshot</span></a></div>
+    "#;
+    let chapters = parse_chapters_from_html(html).unwrap().chapters;
+    let numbers: Vec<&str> = chapters.iter().map(|ch| ch.number.as_str()).collect();
+    assert_eq!(
+        numbers,
+        vec![
+            "0005",
+            "0005.1",
+            "0005.10",
+            "0010a",
+            "special-extra-c5",
+            "special-oneshot-c6"
+        ]
+    );
+    assert_eq!(chapters[5].volume, Some(2));
+}
+
+#[test]
+fn parse_chapters_same_named_specials() {
+    let html = r#"
+        <div><a href="https://example.com/chapters/01K1G9HZJ3Q8YTJG6W11FY9QWX"><span>Vol. 2 Extra</span></a></div>
+        <div><a href="https://example.com/chapters/01K1G9HZJ3G4WFRGRR9HJ0DTFQ"><span>Vol. 1 Extra</span></a></div>
+        <div><a href="https://example.com/chapters/c1"><span>Oneshot</span></a></div>
+    "#;
+    let list = parse_chapters_from_html(html).unwrap();
+    assert!(list.warnings.is_empty());
+    let numbers: Vec<&str> = list.chapters.iter().map(|ch| ch.number.as_str()).collect();
+    assert_eq!(
+        numbers,
+        vec![
+            "special-extra-fy9qwx",
+            "special-extra-j0dtfq",
+            "special-oneshot-c1"
+        ]
+    );
+}
+
+#[test]
+fn special_keys_stay_when_a_sibling_is_listed() {
+    let first = r#"<div><a href="https://example.com/chapters/01K1G9HZJ3G4WFRGRR9HJ0DTFQ"><span>Vol. 1 Extra</span></a></div>"#;
+    let sibling = r#"<div><a href="https://example.com/chapters/01K1G9HZJ3Q8YTJG6W11FY9QWX"><span>Vol. 2 Extra</span></a></div>"#;
+    let before = parse_chapters_from_html(first).unwrap().chapters;
+    let after = parse_chapters_from_html(&format!("{first}{sibling}"))
+        .unwrap()
+        .chapters;
+    assert_eq!(before[0].number, "special-extra-j0dtfq");
+    assert_eq!(after[0].number, before[0].number);
+    assert_eq!(after[1].number, "special-extra-fy9qwx");
+}
+
+#[test]
+fn parse_chapters_release_dates() {
+    let html = common::load_fixture("chapter_list.html");
+    let chapters = parse_chapters_from_html(&html).unwrap().chapters;
+
+    for ch in &chapters {
+        assert_eq!(ch.released.as_deref(), Some("2025-07-31"));
+    }
+}
+
+#[test]
+fn parse_chapters_volumes() {
+    let html = common::load_fixture("chapter_list.html");
+    let chapters = parse_chapters_from_html(&html).unwrap().chapters;
+    assert!(chapters.iter().all(|ch| ch.volume.is_none()));
+
+    let html = r#"
+        <div><a href="https://example.com/chapters/c2"><span>Vol. 3 Chapter 18</span></a></div>
+        <div><a href="https://example.com/chapters/c1"><span>Volume 2 - Chapter 9.5</span></a></div>
+        <div><a href="https://example.com/chapters/c0"><span>Chapter 4 - Volcano, Vol. 1</span></a></div>
+    "#;
+    let chapters = parse_chapters_from_html(html).unwrap().chapters;
+    assert_eq!(chapters[0].number, "0018");
+    assert_eq!(chapters[0].volume, Some(3));
+    assert_eq!(chapters[1].number, "0009.5");
+    assert_eq!(chapters[1].volume, Some(2));
+    assert_eq!(chapters[2].number, "0004");
+    assert_eq!(chapters[2].volume, Some(1));
+}
+
+#[test]
+fn parse_chapters_skips_bad_entries_with_warnings() {
+    let html = r#"
+        <div><a href="https://example.com/about">About</a></div>
+        <div><a href="https://example.com/chapters/c3"><span>Chapter 3</span></a></div>
+        <div><a href="https://example.com/chapters/c2"><span>Chapter 2.5.1</span></a></div>
+        <div><a href="https://example.com/chapters/c1"><span>Chapter 1</span></a></div>
+    "#;
+    let list = parse_chapters_from_html(html).unwrap();
+    let numbers: Vec<&str> = list.chapters.iter().map(|ch| ch.number.as_str()).collect();
+    assert_eq!(numbers, vec!["0003", "0001"]);
+    assert_eq!(list.warnings.len(), 1);
+    assert!(list.warnings[0].starts_with("entry 2 (https://example.com/chapters/c2):"));
+    assert!(list.warnings[0].contains("2.5.1"));
+}
+
+#[test]
+fn parse_chapters_dedupes_repeated_entries() {
+    let html = r#"
+        <div><a href="https://example.com/chapters/c2"><span>Chapter 2</span></a></div>
+        <div><a href="https://example.com/chapters/c2"><span>Chapter 2</span></a></div>
+        <div><a href="https://example.com/chapters/c2b"><span>Chapter 2</span></a></div>
+        <div><a href="https://example.com/chapters/c1"><span>Chapter 1</span></a></div>
+    "#;
+    let list = parse_chapters_from_html(html).unwrap();
+    let hashes: Vec<&str> = list.chapters.iter().map(|ch| ch.hash.as_str()).collect();
+    assert_eq!(hashes, vec!["c2", "c1"]);
+    // the same link twice is dropped quietly, another link to chapter 2 isn't
+    assert_eq!(list.warnings.len(), 1);
+    assert!(list.warnings[0].contains("chapter 2 is listed twice, keeping c2"));
+}
+
+#[test]
+fn parse_chapters_fails_when_no_entry_reads() {
+    let html = r#"
+        <div><a href="https://example.com/chapters/c1"><span>Chapter ?</span></a></div>
+        <div><a href="https://example.com/chapters/c2"><span>1.2.3</span></a></div>
+    "#;
+    assert!(parse_chapters_from_html(html).is_err());
+}
+
 #[test]
 fn parse_pages_count_and_urls() {
     let html = common::load_fixture("chapter_pages.html");
@@ -139,6 +263,8 @@ fn parse_pages_empty_html_fails() {
 
 #[test]
 fn parse_chapters_empty_html() {
-    let chapters = parse_chapters_from_html("<html><body></body></html>").unwrap();
+    let chapters = parse_chapters_from_html("<html><body></body></html>")
+        .unwrap()
+        .chapters;
     assert!(chapters.is_empty());
 }
@@ -1,4 +1,4 @@
-use mgdl::utils::{extract_hash, normalize};
+use mgdl::utils::{extract_hash, folder_name, normalize};
 
 #[test]
 fn normalize_manga_title() {
@@ -71,3 +71,18 @@ fn extract_hash_no_series_segment() {
 fn extract_hash_empty_after_series() {
     assert!(extract_hash("https://example.com/series/").is_none());
 }
+
+#[test]
+fn normalize_cjk_title_is_not_empty() {
+    assert_eq!(normalize("進撃の巨人"), "jin_ji_noju_ren");
+}
+
+#[test]
+fn normalize_greek() {
+    assert_eq!(normalize("Ελληνικά"), "ellenika");
+}
+
+#[test]
+fn folder_name_symbol_only_title() {
+    assert_eq!(folder_name("!!!", "HASH123"), "series_hash123");
+}